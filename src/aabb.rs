@@ -0,0 +1,99 @@
+use crate::interval::{self, Interval};
+use crate::ray::Ray;
+use crate::vec3::{make_point, zero_vector, Point3};
+
+// Axis-aligned bounding box, one interval per axis
+#[derive(Clone, Copy, Default)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub fn axis_interval(&self, axis: i32) -> Interval {
+        match axis {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x,
+        }
+    }
+
+    // Slab test: whether the ray passes through the box within ray_t
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    // The part of ray_t during which the ray is inside the box
+    pub fn clip(&self, r: &Ray, mut ray_t: Interval) -> Option<Interval> {
+        let origin = r.origin();
+        let direction = r.direction();
+        let mut axis = 0;
+        while axis < 3 {
+            let slab = self.axis_interval(axis);
+            let inverse = 1.0 / direction[axis];
+            let t0 = (slab.min - origin[axis]) * inverse;
+            let t1 = (slab.max - origin[axis]) * inverse;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            ray_t.min = ray_t.min.max(near);
+            ray_t.max = ray_t.max.min(far);
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+            axis += 1;
+        }
+        Some(ray_t)
+    }
+
+    // Whether the box reaches infinitely far along some axis
+    pub fn is_unbounded(&self) -> bool {
+        [self.x, self.y, self.z]
+            .iter()
+            .any(|slab| slab.min == -f64::INFINITY || slab.max == f64::INFINITY)
+    }
+
+    pub fn longest_axis(&self) -> i32 {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Center and radius of a sphere around the box, the unit sphere for
+    // empty or unbounded boxes
+    pub fn bounding_sphere(&self) -> (Point3, f64) {
+        let corner_min = make_point(self.x.min, self.y.min, self.z.min);
+        let corner_max = make_point(self.x.max, self.y.max, self.z.max);
+        let radius = 0.5 * (corner_max - corner_min).length();
+        if radius.is_finite() && radius > 0.0 {
+            (0.5 * (corner_min + corner_max), radius)
+        } else {
+            (zero_vector(), 1.0)
+        }
+    }
+}
+
+// Box with a and b as opposite corners
+pub fn make_aabb(a: Point3, b: Point3) -> Aabb {
+    Aabb {
+        x: interval::new(a.x().min(b.x()), a.x().max(b.x())),
+        y: interval::new(a.y().min(b.y()), a.y().max(b.y())),
+        z: interval::new(a.z().min(b.z()), a.z().max(b.z())),
+    }
+}
+
+// Smallest box containing both a and b
+pub fn surrounding_box(a: &Aabb, b: &Aabb) -> Aabb {
+    Aabb {
+        x: interval::union(a.x, b.x),
+        y: interval::union(a.y, b.y),
+        z: interval::union(a.z, b.z),
+    }
+}
@@ -0,0 +1,268 @@
+use crate::color::{make_color, Color};
+use crate::distribution::{make_distribution_2d, Distribution2D};
+use crate::hdr_image::HdrImage;
+use crate::light::LightEmission;
+use crate::onb::make_onb;
+use crate::ray::Ray;
+use crate::rtweekend::{degrees_to_radians, PI};
+use crate::vec3::{dot, sample_unit_disk, sample_unit_sphere, unit_vector, Point3, Vec3};
+
+// Radiance arriving from infinitely far away, seen by rays that escape the
+// scene. Backgrounds that can pick directions proportional to their radiance
+// are also used as a light source when scattering off non-specular surfaces.
+pub trait Background {
+    fn value(&self, direction: Vec3) -> Color;
+
+    fn sample(&self, _u: (f64, f64)) -> Option<Vec3> {
+        None
+    }
+
+    // Solid angle density of sample() for a unit direction
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+// The background as a light source for methods that trace paths from the
+// lights. Its rays start on a disk just outside the sphere bounding the
+// scene, like those of directional lights. Backgrounds that can't pick
+// directions themselves are sampled uniformly over the sphere.
+pub struct BackgroundEmitter<'a> {
+    background: &'a dyn Background,
+    uniform: bool,
+    pub scene_center: Point3,
+    pub scene_radius: f64,
+}
+
+impl BackgroundEmitter<'_> {
+    // Direction towards the background and its solid angle density
+    pub fn sample_direction(&self, u: (f64, f64)) -> Option<(Vec3, f64)> {
+        if self.uniform {
+            return Some((sample_unit_sphere(u), 1.0 / (4.0 * PI)));
+        }
+        let direction = self.background.sample(u)?;
+        let pdf = self.background.pdf(direction);
+        if pdf > 0.0 {
+            Some((direction, pdf))
+        } else {
+            None
+        }
+    }
+
+    pub fn pdf_direction(&self, direction: Vec3) -> f64 {
+        if self.uniform {
+            1.0 / (4.0 * PI)
+        } else {
+            self.background.pdf(direction)
+        }
+    }
+
+    // Area density of ray origins on the disk, for a ray along `direction`
+    // that reaches p. Rays from the disk never reach points outside the
+    // half cylinder it sweeps out, such as the far parts of an infinite
+    // plane.
+    pub fn pdf_position(&self, p: Point3, direction: Vec3) -> f64 {
+        let offset = p - self.scene_center;
+        let along = dot(offset, direction);
+        let across = offset - along * direction;
+        if along < -self.scene_radius
+            || across.length_squared() > self.scene_radius * self.scene_radius
+        {
+            return 0.0;
+        }
+        1.0 / (PI * self.scene_radius * self.scene_radius)
+    }
+
+    pub fn sample_le(&self, u1: (f64, f64), u2: (f64, f64)) -> Option<LightEmission> {
+        let (to_background, pdf_dir) = self.sample_direction(u1)?;
+        let direction = -to_background;
+        let disk = sample_unit_disk(u2);
+        let on_disk = self.scene_center
+            + self.scene_radius * make_onb(direction).local(Vec3(disk.x(), disk.y(), 0.0));
+        Some(LightEmission {
+            ray: Ray {
+                origin: on_disk - self.scene_radius * direction,
+                direction,
+            },
+            normal: direction,
+            radiance: self.background.value(to_background),
+            pdf_pos: self.pdf_position(on_disk, direction),
+            pdf_dir,
+        })
+    }
+}
+
+pub fn make_background_emitter(
+    background: &dyn Background,
+    scene_center: Point3,
+    scene_radius: f64,
+) -> BackgroundEmitter<'_> {
+    BackgroundEmitter {
+        background,
+        uniform: background.sample((0.5, 0.5)).is_none(),
+        scene_center,
+        scene_radius,
+    }
+}
+
+// Vertical blend between two colors, the sky of the first book
+pub struct GradientSky {
+    horizon: Color,
+    zenith: Color,
+}
+
+impl Background for GradientSky {
+    fn value(&self, direction: Vec3) -> Color {
+        let unit_direction = unit_vector(direction);
+        let a = 0.5 * (unit_direction.y() + 1.0);
+        (1.0 - a) * self.horizon + a * self.zenith
+    }
+}
+
+pub fn make_gradient_sky(horizon: Color, zenith: Color) -> GradientSky {
+    GradientSky { horizon, zenith }
+}
+
+// Equirectangular (latitude-longitude) environment map. The top row of the
+// image looks straight up (+y) and u runs around the y axis.
+pub struct EnvironmentMap {
+    image: HdrImage,
+    intensity: f64,
+    // Rotation around the y axis, in radians
+    rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(unit_vector(direction));
+        let x = (u * self.image.width as f64) as usize;
+        let y = (v * self.image.height as f64) as usize;
+        self.intensity * self.image.pixel(x, y)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Option<Vec3> {
+        let ((map_u, map_v), pdf) = self.distribution.sample_continuous(u);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(self.uv_to_direction(map_u, map_v))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(unit_vector(direction));
+        let sin_theta = (PI * v).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+        // Change of variables from the unit square to the sphere
+        self.distribution.pdf((u, v)) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl EnvironmentMap {
+    fn direction_to_uv(&self, d: Vec3) -> (f64, f64) {
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = d.z().atan2(d.x()) - self.rotation;
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + self.rotation;
+        Vec3(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+}
+
+pub fn make_environment_map(
+    image: HdrImage,
+    intensity: f64,
+    rotation_degrees: f64,
+) -> EnvironmentMap {
+    // Sample texels by luminance, weighted by sin(theta) since rows near the
+    // poles cover less of the sphere
+    let mut weights = Vec::with_capacity(image.width * image.height);
+    let mut y = 0;
+    while y < image.height {
+        let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+        let mut x = 0;
+        while x < image.width {
+            weights.push(luminance(image.pixel(x, y)) * sin_theta);
+            x += 1;
+        }
+        y += 1;
+    }
+    let distribution = make_distribution_2d(&weights, image.width, image.height);
+    EnvironmentMap {
+        image,
+        intensity,
+        rotation: degrees_to_radians(rotation_degrees),
+        distribution,
+    }
+}
+
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+pub fn default_sky() -> GradientSky {
+    make_gradient_sky(make_color(1.0, 1.0, 1.0), make_color(0.5, 0.7, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_map() -> EnvironmentMap {
+        let values = [0.0, 1.0, 2.0, 3.0, 0.5, 4.0, 1.0, 0.0, 2.0, 6.0, 0.0, 1.0];
+        let image = HdrImage {
+            width: 4,
+            height: 3,
+            pixels: values.iter().map(|v| make_color(*v, *v, *v)).collect(),
+        };
+        make_environment_map(image, 2.0, 30.0)
+    }
+
+    #[test]
+    fn it_has_a_density_over_the_sphere() {
+        let map = test_map();
+        let (n_theta, n_phi) = (120, 240);
+        let (d_theta, d_phi) = (PI / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut total = 0.0;
+        for j in 0..n_theta {
+            let theta = (j as f64 + 0.5) * d_theta;
+            for i in 0..n_phi {
+                let phi = (i as f64 + 0.5) * d_phi;
+                let direction = Vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                total += map.pdf(direction) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_reports_the_density_it_samples_with() {
+        let map = test_map();
+        let n = 40;
+        for j in 0..n {
+            for i in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let direction = map.sample(u).unwrap();
+                let ((_, v), pdf) = map.distribution.sample_continuous(u);
+                let expected = pdf / (2.0 * PI * PI * (PI * v).sin());
+                assert!((map.pdf(direction) - expected).abs() < 1e-9 * expected);
+                // Only bright texels get picked
+                assert!(luminance(map.value(direction)) > 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,871 @@
+use crate::background::{make_background_emitter, BackgroundEmitter};
+use crate::color::{make_color, Color};
+use crate::film::Film;
+use crate::hit_record::HitRecord;
+use crate::integrator::{occluded, Integrator, Scene};
+use crate::interval;
+use crate::material::TransportMode;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{dot, unit_vector, zero_vector};
+use crate::vec3::{Point3, Vec3};
+
+// Bidirectional path tracer after Veach and pbrt. Every camera sample traces
+// one path from the camera and one from a randomly chosen light, then joins
+// each prefix of the camera path to each prefix of the light path. All the
+// ways a path of a given length could have been built are weighted against
+// each other with the balance heuristic. Joins straight to the camera (light
+// tracing) land on other pixels and go to the film as splats.
+//
+// The background counts as one more light, sampled uniformly over the sphere
+// when it has no importance sampling of its own.
+pub struct BdptIntegrator {}
+
+impl Integrator for BdptIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        film: &mut Film,
+    ) -> Color {
+        let context = make_context(scene);
+        let camera_path = context.camera_subpath(r, max_depth + 2, sampler);
+        let light_path = context.light_subpath(max_depth + 1, sampler);
+
+        // Next event estimation (s = 1) samples its own light point, so it
+        // goes ahead even when the light path itself came to nothing
+        let max_s = light_path.len().max(1);
+        let mut color = make_color(0.0, 0.0, 0.0);
+        let mut t = 1;
+        while t <= camera_path.len() {
+            let mut s = 0;
+            while s <= max_s {
+                let depth = (s + t) as i32 - 2;
+                if !(s == 1 && t == 1) && depth >= 0 && depth <= max_depth {
+                    match context.connect(&light_path, &camera_path, s, t, sampler) {
+                        Some((contribution, Some((x, y)))) => film.add_splat(x, y, contribution),
+                        Some((contribution, None)) => color += contribution,
+                        None => {}
+                    }
+                }
+                s += 1;
+            }
+            t += 1;
+        }
+        color
+    }
+}
+
+pub fn make_bdpt_integrator() -> BdptIntegrator {
+    BdptIntegrator {}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    Surface,
+    // A point on one of the scene's lights
+    Light,
+    // Where a path leaves the scene, or starts from the background
+    Background,
+}
+
+struct Vertex {
+    kind: VertexKind,
+    p: Point3,
+    // Surface normal, zero for points without a surface
+    normal: Vec3,
+    // Unit direction back along the path to the previous vertex
+    wo: Vec3,
+    rec: Option<HitRecord>,
+    // Index into the scene's lights for points on a light
+    light: Option<usize>,
+    // Path throughput up to and including this vertex
+    beta: Color,
+    // Whether the path scattered specularly here
+    delta: bool,
+    // Area densities of this vertex when the path is built from its own
+    // end (forward) and from the other end (reverse). Directions to the
+    // background are measured in solid angle instead.
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+fn make_vertex(kind: VertexKind, p: Point3, normal: Vec3, beta: Color) -> Vertex {
+    Vertex {
+        kind,
+        p,
+        normal,
+        wo: zero_vector(),
+        rec: None,
+        light: None,
+        beta,
+        delta: false,
+        pdf_fwd: 0.0,
+        pdf_rev: 0.0,
+    }
+}
+
+fn on_surface(v: &Vertex) -> bool {
+    v.normal.length_squared() > 0.0
+}
+
+fn is_black(c: Color) -> bool {
+    c.x() == 0.0 && c.y() == 0.0 && c.z() == 0.0
+}
+
+// Density measures along paths change when the pdf of 0 means a delta
+// distribution, which must not zero out the ratios of the MIS weight
+fn remap0(pdf: f64) -> f64 {
+    if pdf != 0.0 {
+        pdf
+    } else {
+        1.0
+    }
+}
+
+// Per camera sample view of the scene
+struct Context<'a> {
+    scene: &'a Scene<'a>,
+    background: BackgroundEmitter<'a>,
+    light_choice_pdf: f64,
+}
+
+fn make_context<'a>(scene: &'a Scene<'a>) -> Context<'a> {
+    let (center, radius) = scene.bounding_sphere();
+    Context {
+        scene,
+        background: make_background_emitter(scene.background, center, radius),
+        light_choice_pdf: 1.0 / (scene.lights.lights.len() + 1) as f64,
+    }
+}
+
+impl Context<'_> {
+    fn camera_subpath(&self, r: Ray, max_vertices: i32, sampler: &mut dyn Sampler) -> Vec<Vertex> {
+        let mut path = Vec::new();
+        if max_vertices <= 0 {
+            return path;
+        }
+        let (_, pdf_dir) = self.scene.camera.pdf_we(&r);
+        path.push(make_vertex(
+            VertexKind::Camera,
+            r.origin(),
+            zero_vector(),
+            make_color(1.0, 1.0, 1.0),
+        ));
+        let ray = Ray {
+            origin: r.origin(),
+            direction: unit_vector(r.direction()),
+        };
+        let beta = make_color(1.0, 1.0, 1.0);
+        self.random_walk(
+            ray,
+            beta,
+            pdf_dir,
+            max_vertices - 1,
+            true,
+            &mut path,
+            sampler,
+        );
+        path
+    }
+
+    fn light_subpath(&self, max_vertices: i32, sampler: &mut dyn Sampler) -> Vec<Vertex> {
+        let mut path = Vec::new();
+        if max_vertices <= 0 {
+            return path;
+        }
+        let index = self.choose_light(sampler.get_1d());
+        let u1 = sampler.get_2d();
+        let u2 = sampler.get_2d();
+
+        let (kind, emission) = match index {
+            Some(i) => {
+                let light = &self.scene.lights.lights[i];
+                match light.sample_le(
+                    u1,
+                    u2,
+                    self.background.scene_center,
+                    self.background.scene_radius,
+                ) {
+                    Some(e) => (VertexKind::Light, e),
+                    None => return path,
+                }
+            }
+            None => match self.background.sample_le(u1, u2) {
+                Some(e) => (VertexKind::Background, e),
+                None => return path,
+            },
+        };
+        if emission.pdf_pos == 0.0 || emission.pdf_dir == 0.0 || is_black(emission.radiance) {
+            return path;
+        }
+
+        let direction = unit_vector(emission.ray.direction());
+        let mut vertex = make_vertex(
+            kind,
+            emission.ray.origin(),
+            emission.normal,
+            emission.radiance,
+        );
+        vertex.light = index;
+        vertex.pdf_fwd = emission.pdf_pos * self.light_choice_pdf;
+        let infinite = self.is_infinite(&vertex);
+        path.push(vertex);
+
+        // Light from infinitely far away has to get to the disk first, past
+        // anything that reaches out of the scene such as an infinite plane.
+        // Nothing can block light starting inside such a shape, but it
+        // meets the shape's surface from behind first.
+        let from_afar = Ray {
+            origin: emission.ray.origin(),
+            direction: -direction,
+        };
+        if infinite && occluded(&from_afar, f64::INFINITY, self.scene.world) {
+            return path;
+        }
+
+        let beta = emission.radiance * dot(emission.normal, direction).abs()
+            / (self.light_choice_pdf * emission.pdf_pos * emission.pdf_dir);
+        let ray = Ray {
+            origin: emission.ray.origin(),
+            direction,
+        };
+        self.random_walk(
+            ray,
+            beta,
+            emission.pdf_dir,
+            max_vertices - 1,
+            false,
+            &mut path,
+            sampler,
+        );
+
+        let starts_inside = path.len() > 1
+            && path[1]
+                .rec
+                .as_ref()
+                .is_some_and(|rec| on_surface(&path[1]) && !rec.front_face);
+        if infinite && starts_inside {
+            path.truncate(1);
+        }
+
+        // Rays from infinitely far away start on a disk, so the first hit's
+        // density comes from the disk's and not from the distance travelled
+        if infinite {
+            if path.len() > 1 {
+                let cos_theta = if on_surface(&path[1]) {
+                    dot(direction, path[1].normal).abs()
+                } else {
+                    1.0
+                };
+                path[1].pdf_fwd = emission.pdf_pos * cos_theta;
+            }
+            if kind == VertexKind::Background {
+                path[0].pdf_fwd = self.light_choice_pdf * self.background.pdf_direction(-direction);
+            }
+        }
+        path
+    }
+
+    // Extends the path until it leaves the scene, is absorbed or has
+    // max_vertices more vertices. Camera paths that escape end on a
+    // background vertex.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk(
+        &self,
+        r: Ray,
+        beta: Color,
+        pdf: f64,
+        max_vertices: i32,
+        from_camera: bool,
+        path: &mut Vec<Vertex>,
+        sampler: &mut dyn Sampler,
+    ) {
+        let mut ray = r;
+        let mut beta = beta;
+        let mut pdf_fwd = pdf;
+        let mut bounces = 0;
+        while bounces < max_vertices && !is_black(beta) {
+            let mut rec = HitRecord::default();
+            if !self
+                .scene
+                .world
+                .hit(&ray, interval::new(0.001, f64::INFINITY), &mut rec)
+            {
+                if from_camera {
+                    let mut vertex = make_vertex(
+                        VertexKind::Background,
+                        ray.origin() + ray.direction(),
+                        zero_vector(),
+                        beta,
+                    );
+                    vertex.wo = -ray.direction();
+                    vertex.pdf_fwd = pdf_fwd;
+                    path.push(vertex);
+                }
+                return;
+            }
+            if !from_camera {
+                rec.transport = TransportMode::Importance;
+            }
+
+            let mut vertex = make_vertex(VertexKind::Surface, rec.p, rec.normal, beta);
+            vertex.wo = -ray.direction();
+            vertex.light = self
+                .scene
+                .lights
+                .lights
+                .iter()
+                .position(|light| light.on_surface(rec.p));
+            vertex.pdf_fwd = self.convert_density(&path[path.len() - 1], pdf_fwd, &vertex);
+            bounces += 1;
+            if bounces >= max_vertices {
+                vertex.rec = Some(rec);
+                path.push(vertex);
+                return;
+            }
+
+            let mut scattered = Ray {
+                origin: zero_vector(),
+                direction: zero_vector(),
+            };
+            let mut attenuation = make_color(0.0, 0.0, 0.0);
+            if !rec
+                .mat
+                .scatter(ray, &rec, &mut attenuation, &mut scattered, sampler)
+            {
+                vertex.rec = Some(rec);
+                path.push(vertex);
+                return;
+            }
+            let wi = unit_vector(scattered.direction());
+            pdf_fwd = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+            let mut pdf_rev = rec.mat.scattering_pdf(
+                &Ray {
+                    origin: rec.p + wi,
+                    direction: -wi,
+                },
+                &rec,
+                &Ray {
+                    origin: rec.p,
+                    direction: vertex.wo,
+                },
+            );
+            if pdf_fwd == 0.0 {
+                vertex.delta = true;
+                pdf_rev = 0.0;
+            }
+            beta *= attenuation;
+            vertex.rec = Some(rec);
+            path.push(vertex);
+
+            let n = path.len();
+            path[n - 2].pdf_rev = self.convert_density(&path[n - 1], pdf_rev, &path[n - 2]);
+            ray = Ray {
+                origin: path[n - 1].p,
+                direction: wi,
+            };
+        }
+    }
+
+    // Joins the first s vertices of the light path to the first t of the
+    // camera path. Returns the weighted contribution and, for joins to the
+    // camera, the raster position it belongs to.
+    fn connect(
+        &self,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Option<(f64, f64)>)> {
+        if t > 1 && s != 0 && camera_path[t - 1].kind == VertexKind::Background {
+            return None;
+        }
+        let mut sampled = None;
+        let mut raster = None;
+        let contribution = if s == 0 {
+            // The camera path found a light by itself
+            let pt = &camera_path[t - 1];
+            self.le(pt, &camera_path[t - 2]) * pt.beta
+        } else if t == 1 {
+            // Light tracing: join the light path to a point on the lens
+            let qs = &light_path[s - 1];
+            if qs.kind != VertexKind::Surface {
+                return None;
+            }
+            let sample = self.scene.camera.sample_wi(qs.p, sampler.get_2d())?;
+            let mut vertex = make_vertex(
+                VertexKind::Camera,
+                sample.lens_point,
+                sample.normal,
+                sample.importance / sample.pdf,
+            );
+            vertex.wo = -sample.wi;
+            let contribution = qs.beta * f(qs, &vertex) * vertex.beta;
+            let shadow_ray = Ray {
+                origin: qs.p,
+                direction: sample.wi,
+            };
+            if is_black(contribution) || occluded(&shadow_ray, sample.distance, self.scene.world) {
+                return None;
+            }
+            raster = Some(sample.raster);
+            sampled = Some(vertex);
+            contribution
+        } else if s == 1 {
+            // Next event estimation with a freshly sampled point on a light
+            let pt = &camera_path[t - 1];
+            if pt.kind != VertexKind::Surface {
+                return None;
+            }
+            let u_light = sampler.get_1d();
+            let u = sampler.get_2d();
+            let (vertex, wi, distance) = self.sample_light_vertex(pt, u_light, u)?;
+            let contribution = pt.beta * f(pt, &vertex) * vertex.beta;
+            let shadow_ray = Ray {
+                origin: pt.p,
+                direction: wi,
+            };
+            if is_black(contribution) || occluded(&shadow_ray, distance, self.scene.world) {
+                return None;
+            }
+            sampled = Some(vertex);
+            contribution
+        } else {
+            let qs = &light_path[s - 1];
+            let pt = &camera_path[t - 1];
+            if qs.kind != VertexKind::Surface || pt.kind != VertexKind::Surface {
+                return None;
+            }
+            let d = qs.p - pt.p;
+            let distance_squared = d.length_squared();
+            if distance_squared == 0.0 {
+                return None;
+            }
+            // The cosines at both ends are part of f
+            let contribution = qs.beta * f(qs, pt) * f(pt, qs) * pt.beta / distance_squared;
+            let distance = distance_squared.sqrt();
+            let shadow_ray = Ray {
+                origin: pt.p,
+                direction: d / distance,
+            };
+            if is_black(contribution) || occluded(&shadow_ray, distance, self.scene.world) {
+                return None;
+            }
+            contribution
+        };
+        if is_black(contribution) {
+            return None;
+        }
+        let weight = self.mis_weight(light_path, camera_path, sampled.as_ref(), s, t);
+        Some((weight * contribution, raster))
+    }
+
+    // Balance heuristic weight of the (s, t) strategy against every other
+    // way of building the same path, from the ratios of reverse to forward
+    // densities along it
+    fn mis_weight(
+        &self,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        // Endpoints of the joined path, with a freshly sampled endpoint
+        // standing in for the path's own
+        let qs = if s == 1 {
+            sampled
+        } else if s > 0 {
+            Some(&light_path[s - 1])
+        } else {
+            None
+        };
+        let pt = if t == 1 {
+            sampled
+        } else {
+            Some(&camera_path[t - 1])
+        };
+        let qs_minus = if s > 1 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+        let pt_minus = if t > 1 {
+            Some(&camera_path[t - 2])
+        } else {
+            None
+        };
+
+        // (forward, reverse, delta) for each vertex, updated for the join
+        let mut camera: Vec<(f64, f64, bool)> = camera_path[..t]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+        let mut light: Vec<(f64, f64, bool)> = light_path[..s.min(light_path.len())]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+        if let (Some(v), true) = (sampled, s == 1) {
+            light = vec![(v.pdf_fwd, v.pdf_rev, v.delta)];
+        }
+        if let (Some(v), true) = (sampled, t == 1) {
+            camera[0] = (v.pdf_fwd, v.pdf_rev, v.delta);
+        }
+
+        let Some(pt) = pt else {
+            return 0.0;
+        };
+
+        // Light paths from the background or a distant light start on a
+        // disk around the scene, and never reach points outside the
+        // cylinder it sweeps out, such as the far parts of an infinite
+        // plane. A zero density there rules the strategies out rather than
+        // marking a delta, so it must not be remapped.
+        let mut unreachable = None;
+        camera[t - 1].2 = false;
+        camera[t - 1].1 = match qs {
+            Some(qs) => self.pdf(qs, qs_minus, pt),
+            None => match pt_minus {
+                Some(pt_minus) => self.pdf_light_origin(pt, pt_minus),
+                None => 0.0,
+            },
+        };
+        if qs.is_some_and(|qs| s == 1 && self.is_infinite(qs)) && camera[t - 1].1 == 0.0 {
+            unreachable = Some(t - 1);
+        }
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].1 = match qs {
+                Some(qs) => self.pdf(pt, Some(qs), pt_minus),
+                None => self.pdf_light(pt, pt_minus),
+            };
+            if qs.is_none() && self.is_infinite(pt) && camera[t - 2].1 == 0.0 {
+                unreachable = Some(t - 2);
+            }
+        }
+        if let Some(qs) = qs {
+            light[s - 1].2 = false;
+            light[s - 1].1 = self.pdf(pt, pt_minus, qs);
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].1 = self.pdf(qs, Some(pt), qs_minus);
+            }
+        }
+
+        let mut sum_ri = 0.0;
+        let mut ri = 1.0;
+        let mut i = t - 1;
+        while i > 0 {
+            if unreachable == Some(i) {
+                ri = 0.0;
+            }
+            ri *= remap0(camera[i].1) / remap0(camera[i].0);
+            if !camera[i].2 && !camera[i - 1].2 {
+                sum_ri += ri;
+            }
+            i -= 1;
+        }
+
+        let first_light_is_delta = match qs {
+            Some(v) if s == 1 => self.is_delta_light(v),
+            _ => s > 0 && self.is_delta_light(&light_path[0]),
+        };
+        ri = 1.0;
+        let mut i = s;
+        while i > 0 {
+            i -= 1;
+            ri *= remap0(light[i].1) / remap0(light[i].0);
+            let delta_before = if i > 0 {
+                light[i - 1].2
+            } else {
+                first_light_is_delta
+            };
+            if !light[i].2 && !delta_before {
+                sum_ri += ri;
+            }
+        }
+        1.0 / (1.0 + sum_ri)
+    }
+
+    // Light choice shared by light paths and next event estimation; none
+    // stands for the background
+    fn choose_light(&self, u: f64) -> Option<usize> {
+        let count = self.scene.lights.lights.len();
+        let index = ((u * (count + 1) as f64) as usize).min(count);
+        if index < count {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    // A light vertex as seen from pt, its direction and distance
+    fn sample_light_vertex(
+        &self,
+        pt: &Vertex,
+        u_light: f64,
+        u: (f64, f64),
+    ) -> Option<(Vertex, Vec3, f64)> {
+        let (mut vertex, wi, distance) = match self.choose_light(u_light) {
+            Some(i) => {
+                let light = &self.scene.lights.lights[i];
+                let sample = light.sample_li(pt.p, u)?;
+                let far = light.is_infinite();
+                let p = if far {
+                    pt.p + 2.0 * self.background.scene_radius * sample.wi
+                } else {
+                    pt.p + sample.distance * sample.wi
+                };
+                let beta = sample.radiance / (sample.pdf * self.light_choice_pdf);
+                let mut vertex = make_vertex(VertexKind::Light, p, sample.normal, beta);
+                vertex.light = Some(i);
+                (vertex, sample.wi, sample.distance)
+            }
+            None => {
+                let (wi, pdf) = self.background.sample_direction(u)?;
+                let radiance = self.scene.background.value(wi);
+                let beta = radiance / (pdf * self.light_choice_pdf);
+                let p = pt.p + 2.0 * self.background.scene_radius * wi;
+                let vertex = make_vertex(VertexKind::Background, p, zero_vector(), beta);
+                (vertex, wi, f64::INFINITY)
+            }
+        };
+        if is_black(vertex.beta) {
+            return None;
+        }
+        vertex.pdf_fwd = self.pdf_light_origin(&vertex, pt);
+        Some((vertex, wi, distance))
+    }
+
+    fn is_infinite(&self, v: &Vertex) -> bool {
+        match (v.kind, v.light) {
+            (VertexKind::Background, _) => true,
+            (VertexKind::Light, Some(i)) => self.scene.lights.lights[i].is_infinite(),
+            _ => false,
+        }
+    }
+
+    fn is_delta_light(&self, v: &Vertex) -> bool {
+        match (v.kind, v.light) {
+            (VertexKind::Light, Some(i)) => self.scene.lights.lights[i].is_delta(),
+            _ => false,
+        }
+    }
+
+    // Turns a solid angle density at `from` into an area density at `next`
+    fn convert_density(&self, from: &Vertex, pdf: f64, next: &Vertex) -> f64 {
+        if self.is_infinite(next) {
+            return pdf;
+        }
+        let w = next.p - from.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if on_surface(next) {
+            pdf *= dot(next.normal, w / distance_squared.sqrt()).abs();
+        }
+        pdf
+    }
+
+    // Density of `next` when the path is extended from v, having arrived
+    // at v from prev
+    fn pdf(&self, v: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        if matches!(v.kind, VertexKind::Light | VertexKind::Background) {
+            return self.pdf_light(v, next);
+        }
+        let wn = next.p - v.p;
+        if wn.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wn = unit_vector(wn);
+        let pdf = match v.kind {
+            VertexKind::Camera => {
+                let (_, pdf_dir) = self.scene.camera.pdf_we(&Ray {
+                    origin: v.p,
+                    direction: wn,
+                });
+                pdf_dir
+            }
+            _ => {
+                let (Some(prev), Some(rec)) = (prev, v.rec.as_ref()) else {
+                    return 0.0;
+                };
+                let wp = unit_vector(prev.p - v.p);
+                rec.mat.scattering_pdf(
+                    &Ray {
+                        origin: prev.p,
+                        direction: -wp,
+                    },
+                    rec,
+                    &Ray {
+                        origin: v.p,
+                        direction: wn,
+                    },
+                )
+            }
+        };
+        self.convert_density(v, pdf, next)
+    }
+
+    // Density of `next` for a light path leaving the light point v
+    fn pdf_light(&self, v: &Vertex, next: &Vertex) -> f64 {
+        let w = next.p - v.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let w = w / distance_squared.sqrt();
+        let mut pdf = if self.is_infinite(v) {
+            self.background.pdf_position(next.p, w)
+        } else {
+            match v.light {
+                Some(i) => {
+                    let ray = Ray {
+                        origin: v.p,
+                        direction: w,
+                    };
+                    let (_, pdf_dir) = self.scene.lights.lights[i].pdf_le(
+                        &ray,
+                        v.normal,
+                        self.background.scene_radius,
+                    );
+                    pdf_dir / distance_squared
+                }
+                None => 0.0,
+            }
+        };
+        if on_surface(next) {
+            pdf *= dot(next.normal, w).abs();
+        }
+        pdf
+    }
+
+    // Density of picking v as the start of a light path, with next as the
+    // vertex it leads to
+    fn pdf_light_origin(&self, v: &Vertex, next: &Vertex) -> f64 {
+        let w = next.p - v.p;
+        if w.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let w = unit_vector(w);
+        if v.kind == VertexKind::Background {
+            return self.light_choice_pdf * self.background.pdf_direction(-w);
+        }
+        match v.light {
+            Some(i) => {
+                let light = &self.scene.lights.lights[i];
+                if light.is_infinite() {
+                    return 0.0;
+                }
+                let ray = Ray {
+                    origin: v.p,
+                    direction: w,
+                };
+                let (pdf_pos, _) = light.pdf_le(&ray, v.normal, self.background.scene_radius);
+                pdf_pos * self.light_choice_pdf
+            }
+            None => 0.0,
+        }
+    }
+
+    // Radiance a light vertex sends towards the vertex before it
+    fn le(&self, v: &Vertex, toward: &Vertex) -> Color {
+        match (v.kind, v.rec.as_ref()) {
+            (VertexKind::Background, _) => self.scene.background.value(unit_vector(v.p - toward.p)),
+            (VertexKind::Surface, Some(rec)) => rec.mat.emitted(rec),
+            _ => make_color(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// BSDF times cosine for light scattering at surface vertex v between the
+// previous vertex and `next`
+fn f(v: &Vertex, next: &Vertex) -> Color {
+    let Some(rec) = v.rec.as_ref() else {
+        return make_color(0.0, 0.0, 0.0);
+    };
+    let wi = next.p - v.p;
+    if wi.length_squared() == 0.0 {
+        return make_color(0.0, 0.0, 0.0);
+    }
+    rec.mat.eval(
+        &Ray {
+            origin: v.p + v.wo,
+            direction: -v.wo,
+        },
+        rec,
+        &Ray {
+            origin: v.p,
+            direction: unit_vector(wi),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::make_gradient_sky;
+    use crate::camera::Camera;
+    use crate::integrator::make_path_integrator;
+    use crate::light::{make_light_list, make_point_light};
+    use crate::material::make_lambertian;
+    use crate::rtweekend::PI;
+    use crate::sampler::make_stratified_sampler;
+    use crate::sphere::make_sphere;
+    use crate::vec3::make_point;
+
+    // Mean pixel value seen from inside a diffuse sphere of albedo 1/2 and
+    // radius 2 around a point light of intensity 4
+    fn mean_radiance(integrator: Box<dyn Integrator>) -> f64 {
+        let world = make_sphere(
+            make_point(0.0, 0.0, 0.0),
+            2.0,
+            Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+        );
+        let mut lights = make_light_list();
+        lights.add(Box::new(make_point_light(
+            make_point(0.0, 0.0, 0.0),
+            make_color(4.0, 4.0, 4.0),
+        )));
+        let mut camera = Camera::default();
+        camera.image_width = 8;
+        camera.samples_per_pixel = 16;
+        camera.max_depth = 20;
+        camera.sampler = Box::new(make_stratified_sampler(true));
+        camera.background = Box::new(make_gradient_sky(
+            make_color(0.0, 0.0, 0.0),
+            make_color(0.0, 0.0, 0.0),
+        ));
+        camera.integrator = integrator;
+        let film = camera.render_film(&world, &lights);
+        let mut sum = 0.0;
+        for j in 0..film.height {
+            for i in 0..film.width {
+                sum += film.pixel(i, j, 1.0 / 16.0).y();
+            }
+        }
+        sum / (film.width * film.height) as f64
+    }
+
+    #[test]
+    fn it_agrees_with_the_path_tracer_in_a_closed_scene() {
+        // The light gives the wall an irradiance of I / r^2 and every bounce
+        // off the inside of a sphere lights all of it evenly, so the wall
+        // glows with a / pi * I / r^2 / (1 - a) everywhere. The black
+        // background still takes its turn as the light to start paths from.
+        let expected = 1.0 / PI;
+        let path = mean_radiance(Box::new(make_path_integrator(3)));
+        let bdpt = mean_radiance(Box::new(make_bdpt_integrator()));
+        assert!((path - expected).abs() < 0.03 * expected, "path {path}");
+        assert!((bdpt - expected).abs() < 0.03 * expected, "bdpt {bdpt}");
+    }
+}
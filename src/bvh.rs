@@ -0,0 +1,164 @@
+use std::cell::Cell;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::interval::{self, Interval};
+use crate::ray::Ray;
+
+thread_local! {
+    // Interior nodes visited by hit() on this thread, for cost heatmaps
+    static NODE_VISITS: Cell<u64> = const { Cell::new(0) };
+}
+
+pub fn reset_node_visits() {
+    NODE_VISITS.with(|visits| visits.set(0));
+}
+
+pub fn node_visits() -> u64 {
+    NODE_VISITS.with(|visits| visits.get())
+}
+
+// Bounding volume hierarchy node over two subtrees or objects
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        NODE_VISITS.with(|visits| visits.set(visits.get() + 1));
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, ray_t, rec);
+        let right_max = if hit_left { rec.t } else { ray_t.max };
+        let hit_right = self.right.hit(r, interval::new(ray_t.min, right_max), rec);
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+// Objects without finite bounds, such as infinite planes, tested one by one
+// next to a hierarchy over everything else
+struct Unbounded {
+    objects: Vec<Box<dyn Hittable>>,
+    tree: Box<dyn Hittable>,
+}
+
+impl Hittable for Unbounded {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = self.tree.hit(r, ray_t, rec);
+        for object in self.objects.iter() {
+            let max = if hit_anything { rec.t } else { ray_t.max };
+            if object.hit(r, interval::new(ray_t.min, max), rec) {
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    // Only the bounded part of the scene, which is what lights placed
+    // around the scene need to cover
+    fn bounding_box(&self) -> Aabb {
+        self.tree.bounding_box()
+    }
+}
+
+// Builds a hierarchy by recursively splitting the objects in half along the
+// longest axis of their bounds. A single object is returned as is. Objects
+// with unbounded boxes are kept out of the hierarchy, since they would make
+// every node above them unbounded too.
+pub fn make_bvh(objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+    let (unbounded, bounded): (Vec<_>, Vec<_>) = objects
+        .into_iter()
+        .partition(|object| object.bounding_box().is_unbounded());
+    let tree = make_tree(bounded);
+    if unbounded.is_empty() {
+        tree
+    } else {
+        Box::new(Unbounded {
+            objects: unbounded,
+            tree,
+        })
+    }
+}
+
+fn make_tree(mut objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+    if objects.len() <= 1 {
+        return match objects.pop() {
+            Some(object) => object,
+            None => Box::new(HittableList {
+                objects: Vec::new(),
+            }),
+        };
+    }
+
+    let bbox = objects.iter().fold(Aabb::default(), |b, object| {
+        surrounding_box(&b, &object.bounding_box())
+    });
+    let axis = bbox.longest_axis();
+    objects.sort_by(|a, b| {
+        let a_min = a.bounding_box().axis_interval(axis).min;
+        let b_min = b.bounding_box().axis_interval(axis).min;
+        a_min.total_cmp(&b_min)
+    });
+
+    let right = objects.split_off(objects.len() / 2);
+    Box::new(BvhNode {
+        left: make_tree(objects),
+        right: make_tree(right),
+        bbox,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::make_color;
+    use crate::material::make_lambertian;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{make_point, Vec3};
+
+    fn spheres() -> Vec<Box<dyn Hittable>> {
+        let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+        let mut i = 0;
+        while i < 20 {
+            objects.push(Box::new(make_sphere(
+                make_point(i as f64 * 1.5 - 15.0, (i % 3) as f64, -(i % 5) as f64),
+                0.5,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )));
+            i += 1;
+        }
+        objects
+    }
+
+    #[test]
+    fn it_finds_the_same_closest_hit_as_a_list() {
+        let list = HittableList { objects: spheres() };
+        let bvh = make_bvh(spheres());
+        let mut x = -16.0;
+        while x < 16.0 {
+            let r = Ray {
+                origin: make_point(x, 1.0, 10.0),
+                direction: Vec3(0.1, -0.05, -1.0),
+            };
+            let mut list_rec = HitRecord::default();
+            let mut bvh_rec = HitRecord::default();
+            let t = interval::new(0.001, f64::INFINITY);
+            let list_hit = list.hit(&r, t, &mut list_rec);
+            assert_eq!(list_hit, bvh.hit(&r, t, &mut bvh_rec));
+            if list_hit {
+                assert!((list_rec.t - bvh_rec.t).abs() < 1e-12);
+            }
+            x += 0.25;
+        }
+    }
+}
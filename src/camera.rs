@@ -1,11 +1,14 @@
-use crate::interval;
-use crate::rtweekend::{degrees_to_radians, random_f64};
-use crate::vec3::{cross, random_in_unit_disk, Point3};
-use crate::{color::Color, hittable::Hittable, ray::Ray};
-use std::f64::INFINITY;
-
-use crate::color::{make_color, write_color};
-use crate::hit_record::HitRecord;
+use crate::background::{default_sky, Background};
+use crate::filter::{make_box_filter, Filter};
+use crate::integrator::{make_path_integrator, Integrator, Scene};
+use crate::light::LightList;
+use crate::rtweekend::{degrees_to_radians, PI};
+use crate::sampler::{make_independent_sampler, Sampler};
+use crate::vec3::{cross, dot, sample_unit_disk, Point3};
+use crate::{hittable::Hittable, ray::Ray};
+
+use crate::color::{make_color, Color};
+use crate::film::{make_film, Film};
 use crate::vec3::Vec3;
 use crate::vec3::{make_point, unit_vector, zero_vector};
 
@@ -20,6 +23,10 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub sampler: Box<dyn Sampler>,
+    pub filter: Box<dyn Filter>,
+    pub background: Box<dyn Background>,
+    pub integrator: Box<dyn Integrator>,
     image_height: i32,
     center: Point3,
     pixel00_loc: Point3,
@@ -53,6 +60,10 @@ impl Default for Camera {
             w: zero_vector(),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            sampler: Box::new(make_independent_sampler()),
+            filter: Box::new(make_box_filter(0.5)),
+            background: Box::new(default_sky()),
+            integrator: Box::new(make_path_integrator(3)),
             defocus_disk_u: zero_vector(),
             defocus_disk_v: zero_vector(),
         }
@@ -98,80 +109,187 @@ impl Camera {
         self.defocus_disk_v = self.v * defocus_radius;
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
+    pub fn render(&mut self, world: &dyn Hittable, lights: &LightList) {
+        let film = self.render_film(world, lights);
+        film.write(1.0 / self.samples_per_pixel as f64);
+    }
+
+    // Renders every pass into a film without writing it out
+    pub fn render_film(&mut self, world: &dyn Hittable, lights: &LightList) -> Film {
         self.initialize();
 
-        print!("P3\n{0} {1}\n255\n", self.image_width, self.image_height);
-        let mut j = 0;
-        while j < self.image_height {
-            let lines_remaining = self.image_height - j;
-            eprint!("Scanlines remaining: {lines_remaining}\r");
-            let mut i = 0;
-            while i < self.image_width {
-                let mut pixel_color = make_color(0.0, 0.0, 0.0);
-                let mut sample = 0;
-                while sample < self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += self.ray_color(r, self.max_depth, world);
-                    sample += 1;
+        let camera: &Camera = self;
+        let scene = Scene {
+            world,
+            lights,
+            background: camera.background.as_ref(),
+            camera,
+        };
+        let mut film = make_film(self.image_width, self.image_height);
+        let mut sampler = self.sampler.clone();
+        let mut sample = 0;
+        while sample < self.samples_per_pixel {
+            let samples_remaining = self.samples_per_pixel - sample;
+            eprint!("Samples remaining: {samples_remaining}    \r");
+            self.integrator.start_pass(&scene, sample, self.max_depth);
+            let mut j = 0;
+            while j < self.image_height {
+                let mut i = 0;
+                while i < self.image_width {
+                    sampler.start_pixel_sample(i, j, sample, self.samples_per_pixel);
+                    let (r, weight) = self.get_ray(i, j, sampler.as_mut());
+                    let color =
+                        self.integrator
+                            .li(r, &scene, self.max_depth, sampler.as_mut(), &mut film);
+                    film.add_sample(i, j, color, weight);
+                    i += 1;
                 }
-                write_color(pixel_color, self.samples_per_pixel);
-                i += 1;
+                j += 1;
             }
-            j += 1;
+            sample += 1;
         }
+        film
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Random sample camera ray for i,j from the defocus disk
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> (Ray, f64) {
+        // Random sample camera ray for i,j from the defocus disk, along with
+        // the reconstruction filter weight of the sample
 
         let pixel_center =
             self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let filter_sample = self.filter.sample(sampler.get_2d());
+        let pixel_sample = pixel_center
+            + (filter_sample.x * self.pixel_delta_u)
+            + (filter_sample.y * self.pixel_delta_v);
 
+        // Always draw the lens sample so later dimensions line up either way
+        let lens_sample = sampler.get_2d();
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens_sample)
         };
 
         let ray_direction = pixel_sample - ray_origin;
-        Ray {
-            origin: ray_origin,
-            direction: ray_direction,
-        }
+        (
+            Ray {
+                origin: ray_origin,
+                direction: ray_direction,
+            },
+            filter_sample.weight,
+        )
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, u: (f64, f64)) -> Point3 {
+        let p = sample_unit_disk(u);
         self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
     }
 
-    fn ray_color(&self, r: Ray, depth: i32, world: &dyn Hittable) -> Color {
-        let mut rec = HitRecord::default();
-        if depth <= 0 {
-            return make_color(0.0, 0.0, 0.0);
+    // The camera as a sensor, for methods that trace paths from the lights
+    // towards it. Importance is normalized so that a splat scaled by
+    // 1 / samples_per_pixel matches the pixel average of camera rays.
+
+    // Importance emitted along a ray leaving the lens, with the raster
+    // position the ray maps to, or none when it misses the image
+    pub fn importance(&self, r: &Ray) -> Option<(Color, (f64, f64))> {
+        let direction = unit_vector(r.direction());
+        let cos_theta = dot(direction, -self.w);
+        if cos_theta <= 0.0 {
+            return None;
         }
-        if world.hit(&r, interval::new(0.001, INFINITY), &mut rec) {
-            let mut scattered = Ray {
-                origin: zero_vector(),
-                direction: zero_vector(),
-            };
-            let mut attenuation = make_color(0.0, 0.0, 0.0);
-            if rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * self.ray_color(scattered, depth - 1, world);
-            }
-            return make_color(0.0, 0.0, 0.0);
+        let raster =
+            self.raster_position(r.origin() + (self.focus_dist / cos_theta) * direction)?;
+        let cos_2 = cos_theta * cos_theta;
+        let we = 1.0 / (self.image_plane_area() * self.lens_area() * cos_2 * cos_2);
+        Some((make_color(we, we, we), raster))
+    }
+
+    // Area density of the ray's origin on the lens and solid angle density
+    // of its direction, for rays generated by get_ray
+    pub fn pdf_we(&self, r: &Ray) -> (f64, f64) {
+        let direction = unit_vector(r.direction());
+        let cos_theta = dot(direction, -self.w);
+        if cos_theta <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let p_focus = r.origin() + (self.focus_dist / cos_theta) * direction;
+        if self.raster_position(p_focus).is_none() {
+            return (0.0, 0.0);
         }
+        let pdf_dir = 1.0 / (self.image_plane_area() * cos_theta * cos_theta * cos_theta);
+        (1.0 / self.lens_area(), pdf_dir)
+    }
 
-        let unit_direction = unit_vector(r.direction());
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        return (1.0 - a) * make_color(1.0, 1.0, 1.0) + a * make_color(0.5, 0.7, 1.0);
+    // Picks a point on the lens as seen from p
+    pub fn sample_wi(&self, p: Point3, u: (f64, f64)) -> Option<CameraSample> {
+        let lens_point = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(u)
+        };
+        let to_lens = lens_point - p;
+        let distance = to_lens.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let wi = to_lens / distance;
+        let cos_lens = dot(wi, self.w).abs();
+        if cos_lens == 0.0 {
+            return None;
+        }
+        let (importance, raster) = self.importance(&Ray {
+            origin: lens_point,
+            direction: -wi,
+        })?;
+        Some(CameraSample {
+            lens_point,
+            normal: -self.w,
+            wi,
+            distance,
+            importance,
+            pdf: distance * distance / (cos_lens * self.lens_area()),
+            raster,
+        })
+    }
+
+    fn raster_position(&self, p_focus: Point3) -> Option<(f64, f64)> {
+        let upper_left = self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let offset = p_focus - upper_left;
+        let x = dot(offset, self.u) / self.pixel_delta_u.length();
+        let y = dot(offset, -self.v) / self.pixel_delta_v.length();
+        if x < 0.0 || y < 0.0 || x >= self.image_width as f64 || y >= self.image_height as f64 {
+            return None;
+        }
+        Some((x, y))
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 * random_f64();
-        let py = -0.5 + random_f64();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
+    // Area of the image rectangle moved to unit distance from the lens
+    fn image_plane_area(&self) -> f64 {
+        let width = self.pixel_delta_u.length() * self.image_width as f64;
+        let height = self.pixel_delta_v.length() * self.image_height as f64;
+        width * height / (self.focus_dist * self.focus_dist)
     }
+
+    // A pinhole counts as a lens of unit area
+    fn lens_area(&self) -> f64 {
+        if self.defocus_angle <= 0.0 {
+            1.0
+        } else {
+            PI * self.defocus_disk_u.length_squared()
+        }
+    }
+}
+
+// A point on the lens seen from a point in the scene
+pub struct CameraSample {
+    pub lens_point: Point3,
+    // Direction the camera looks in
+    pub normal: Vec3,
+    // Unit direction from the scene point to the lens
+    pub wi: Vec3,
+    pub distance: f64,
+    pub importance: Color,
+    // Solid angle density of wi at the scene point
+    pub pdf: f64,
+    pub raster: (f64, f64),
 }
@@ -0,0 +1,164 @@
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::{Hittable, Span};
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // Everything in the first shape that is not in the second
+    Difference,
+}
+
+// Constructive solid geometry: a boolean combination of two closed shapes.
+// The spans the ray spends inside each shape are merged by walking along
+// their boundaries in order, and the surface of the result is wherever
+// being inside it changes. Surfaces of a subtracted shape face the other
+// way on the result, so their records are flipped from front to back.
+pub struct Csg {
+    operation: CsgOperation,
+    a: Box<dyn Hittable>,
+    b: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+        for span in self.intervals(r) {
+            for boundary in [span.enter, span.exit] {
+                if ray_t.surrounds(boundary.t) {
+                    *rec = boundary;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Span> {
+        combine(self.operation, self.a.intervals(r), self.b.intervals(r))
+    }
+}
+
+// Spans inside the combination of two shapes, given the spans inside each
+pub fn combine(operation: CsgOperation, a: Vec<Span>, b: Vec<Span>) -> Vec<Span> {
+    let inside = |in_a: bool, in_b: bool| match operation {
+        CsgOperation::Union => in_a || in_b,
+        CsgOperation::Intersection => in_a && in_b,
+        CsgOperation::Difference => in_a && !in_b,
+    };
+
+    // Each boundary as (t, whether it belongs to a, whether the ray enters
+    // there, its record)
+    let mut boundaries = Vec::new();
+    for (from_a, spans) in [(true, a), (false, b)] {
+        for span in spans {
+            boundaries.push((span.enter.t, from_a, true, span.enter));
+            boundaries.push((span.exit.t, from_a, false, span.exit));
+        }
+    }
+    boundaries.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut spans = Vec::new();
+    let mut in_a = false;
+    let mut in_b = false;
+    let mut enter: Option<HitRecord> = None;
+    for (_, from_a, entering, mut rec) in boundaries {
+        let was_inside = inside(in_a, in_b);
+        if from_a {
+            in_a = entering;
+        } else {
+            in_b = entering;
+        }
+        if inside(in_a, in_b) == was_inside {
+            continue;
+        }
+        if !from_a && operation == CsgOperation::Difference {
+            rec.front_face = !rec.front_face;
+        }
+        if !was_inside {
+            enter = Some(rec);
+        } else if let Some(enter) = enter.take() {
+            spans.push(Span { enter, exit: rec });
+        }
+    }
+    spans
+}
+
+pub fn make_csg(operation: CsgOperation, a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Csg {
+    // The result never reaches outside a, except for unions
+    let bbox = match operation {
+        CsgOperation::Union => surrounding_box(&a.bounding_box(), &b.bounding_box()),
+        _ => a.bounding_box(),
+    };
+    Csg {
+        operation,
+        a,
+        b,
+        bbox,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_csg, CsgOperation};
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{dot, make_point, Vec3};
+
+    #[test]
+    fn it_carves_subtracted_surfaces_with_flipped_normals() {
+        let shape = |operation| {
+            make_csg(
+                operation,
+                Box::new(make_sphere(
+                    make_point(0.0, 0.0, 0.0),
+                    1.0,
+                    Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+                )),
+                Box::new(make_sphere(
+                    make_point(0.0, 0.0, 1.0),
+                    0.5,
+                    Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+                )),
+            )
+        };
+        // In a from t = 4 to 6 and in b from 3.5 to 4.5
+        let r = Ray {
+            origin: make_point(0.0, 0.0, 5.0),
+            direction: Vec3(0.0, 0.0, -1.0),
+        };
+        let hit = |operation| {
+            let mut rec = HitRecord::default();
+            assert!(shape(operation).hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+            rec
+        };
+
+        let union = hit(CsgOperation::Union);
+        assert!((union.t - 3.5).abs() < 1e-9 && union.front_face);
+
+        let intersection = hit(CsgOperation::Intersection);
+        assert!((intersection.t - 4.0).abs() < 1e-9 && intersection.front_face);
+
+        // The far side of the bite, entering the solid through b's surface
+        // from the inside of b
+        let difference = hit(CsgOperation::Difference);
+        assert!((difference.t - 4.5).abs() < 1e-9);
+        assert!(difference.front_face);
+        assert!(dot(difference.normal, r.direction()) < 0.0);
+    }
+}
@@ -0,0 +1,404 @@
+use std::rc::Rc;
+
+use crate::aabb::{make_aabb, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::{self, Interval};
+use crate::material::Material;
+use crate::onb::{make_onb, Onb};
+use crate::ray::Ray;
+use crate::vec3::{cross, dot, make_point, unit_vector, Point3, Vec3};
+
+// Each curve is cut into 2^SPLIT_DEPTH segments up front, so that the
+// hierarchy holds boxes that hug the curve
+const SPLIT_DEPTH: i32 = 2;
+// Most halvings of a segment while looking for a hit
+const MAX_DEPTH: i32 = 10;
+
+#[derive(Clone, Copy)]
+pub enum CurveType {
+    // A strip that always faces the ray, shaded as if it were a round tube,
+    // for hair and fur
+    Cylinder,
+    // A flat strip whose normal turns from the first of these to the second
+    // along the curve, for blades of grass
+    Ribbon([Vec3; 2]),
+}
+
+// What all the segments of one curve share
+struct CurveCommon {
+    kind: CurveType,
+    widths: [f64; 2],
+    mat: Box<dyn Material>,
+}
+
+// Part of a cubic Bézier curve with a width that changes linearly along it.
+// Rays are tested in a frame looking down the ray, where the curve is
+// halved until its pieces are nearly straight and each piece is hit if the
+// ray passes within half the width of it. u runs along the whole curve and
+// v across it, so that 2v - 1 is how far off center the ray passed.
+pub struct Curve {
+    common: Rc<CurveCommon>,
+    // Control points of just this segment
+    points: [Point3; 4],
+    u_min: f64,
+    u_max: f64,
+    bbox: Aabb,
+}
+
+struct CurveHit {
+    // Distance along the unit ray direction
+    z: f64,
+    u: f64,
+    // Offset from the center line in half widths, signed
+    h: f64,
+    // Along the curve, in the ray's frame
+    tangent: Vec3,
+}
+
+impl Hittable for Curve {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+        let length = r.direction().length();
+        let frame = make_onb(r.direction() / length);
+        let points = self.points.map(|p| frame.world_to_local(p - r.origin()));
+
+        // Enough halvings for the pieces to be within a twentieth of the
+        // width of straight (pbrt's estimate from the second differences)
+        let mut l0: f64 = 0.0;
+        let mut i = 0;
+        while i < 2 {
+            let d = points[i] - 2.0 * points[i + 1] + points[i + 2];
+            l0 = l0.max(d.x().abs()).max(d.y().abs()).max(d.z().abs());
+            i += 1;
+        }
+        let eps = 0.05 * self.width(self.u_min).max(self.width(self.u_max));
+        let depth = if l0 > 0.0 && eps > 0.0 {
+            ((std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() * 0.5)
+                .round()
+                .clamp(0.0, MAX_DEPTH as f64) as i32
+        } else {
+            0
+        };
+
+        let z_range = interval::new(ray_t.min * length, ray_t.max * length);
+        let mut nearest = None;
+        self.hit_piece(
+            &points,
+            self.u_min,
+            self.u_max,
+            depth,
+            z_range,
+            &frame,
+            &mut nearest,
+        );
+        let Some(hit) = nearest else {
+            return false;
+        };
+
+        let direction = frame.w;
+        let tangent = unit_vector(frame.local(hit.tangent));
+        // The strip faces the ray; across it is the direction in which h
+        // grows
+        let facing = unit_vector(-direction + dot(direction, tangent) * tangent);
+        let across = cross(facing, tangent);
+        rec.t = hit.z / length;
+        rec.p = r.at(rec.t);
+        match self.common.kind {
+            CurveType::Cylinder => {
+                let normal = (1.0 - hit.h * hit.h).max(0.0).sqrt() * facing + hit.h * across;
+                rec.set_face_normal(r, normal);
+            }
+            CurveType::Ribbon(normals) => {
+                let n = ribbon_normal(normals, hit.u);
+                rec.set_face_normal(r, unit_vector(n - dot(n, tangent) * tangent));
+            }
+        }
+        rec.u = hit.u;
+        rec.v = 0.5 + 0.5 * hit.h;
+        rec.clear_extras();
+        rec.tangent = tangent;
+        rec.mat = self.common.mat.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Curve {
+    fn width(&self, u: f64) -> f64 {
+        let [w0, w1] = self.common.widths;
+        (1.0 - u) * w0 + u * w1
+    }
+
+    // Looks for the nearest hit on the piece of curve between u0 and u1,
+    // given by control points in the ray's frame
+    #[allow(clippy::too_many_arguments)]
+    fn hit_piece(
+        &self,
+        points: &[Point3; 4],
+        u0: f64,
+        u1: f64,
+        depth: i32,
+        z_range: Interval,
+        frame: &Onb,
+        nearest: &mut Option<CurveHit>,
+    ) {
+        // The piece lies within its control points' box grown by half the
+        // width; skip it if the ray misses that
+        let half_width = 0.5 * self.width(u0).max(self.width(u1));
+        let z_max = nearest.as_ref().map_or(z_range.max, |hit| hit.z);
+        let mut low = points[0];
+        let mut high = points[0];
+        for p in &points[1..] {
+            low = make_point(low.x().min(p.x()), low.y().min(p.y()), low.z().min(p.z()));
+            high = make_point(
+                high.x().max(p.x()),
+                high.y().max(p.y()),
+                high.z().max(p.z()),
+            );
+        }
+        if low.x() - half_width > 0.0
+            || high.x() + half_width < 0.0
+            || low.y() - half_width > 0.0
+            || high.y() + half_width < 0.0
+            || low.z() - half_width > z_max
+            || high.z() + half_width < z_range.min
+        {
+            return;
+        }
+
+        if depth > 0 {
+            let halves = subdivide(points);
+            let mid = 0.5 * (u0 + u1);
+            let first = [halves[0], halves[1], halves[2], halves[3]];
+            let second = [halves[3], halves[4], halves[5], halves[6]];
+            self.hit_piece(&first, u0, mid, depth - 1, z_range, frame, nearest);
+            self.hit_piece(&second, mid, u1, depth - 1, z_range, frame, nearest);
+            return;
+        }
+
+        // The ray has to pass between the planes square to the curve at the
+        // piece's two ends
+        let (p0, p1, p2, p3) = (points[0], points[1], points[2], points[3]);
+        if (p1.y() - p0.y()) * -p0.y() + p0.x() * (p0.x() - p1.x()) < 0.0 {
+            return;
+        }
+        if (p2.y() - p3.y()) * -p3.y() + p3.x() * (p3.x() - p2.x()) < 0.0 {
+            return;
+        }
+
+        // Nearest point to the ray along the chord, as a parameter in the
+        // piece
+        let chord = Vec3(p3.x() - p0.x(), p3.y() - p0.y(), 0.0);
+        let chord_length_squared = chord.length_squared();
+        if chord_length_squared == 0.0 {
+            return;
+        }
+        let w = (-(p0.x() * chord.x() + p0.y() * chord.y()) / chord_length_squared).clamp(0.0, 1.0);
+        let u = (1.0 - w) * u0 + w * u1;
+
+        let mut hit_width = self.width(u);
+        if let CurveType::Ribbon(normals) = self.common.kind {
+            // Seen edge on, a ribbon has no width at all
+            hit_width *= dot(ribbon_normal(normals, u), frame.w).abs();
+        }
+        let (pc, tangent) = eval_bezier(points, w);
+        let across_length = (tangent.x() * tangent.x() + tangent.y() * tangent.y()).sqrt();
+        if pc.x() * pc.x() + pc.y() * pc.y() > 0.25 * hit_width * hit_width || across_length == 0.0
+        {
+            return;
+        }
+        if pc.z() <= z_range.min || pc.z() >= z_max {
+            return;
+        }
+        // Rays leaving a fiber would find it again right where they start
+        if pc.length() < hit_width {
+            return;
+        }
+
+        let h = (-pc.x() * tangent.y() + pc.y() * tangent.x()) / (across_length * 0.5 * hit_width);
+        *nearest = Some(CurveHit {
+            z: pc.z(),
+            u,
+            h: h.clamp(-1.0, 1.0),
+            tangent,
+        });
+    }
+}
+
+fn lerp(t: f64, a: Point3, b: Point3) -> Point3 {
+    (1.0 - t) * a + t * b
+}
+
+// Point on the curve and its derivative
+fn eval_bezier(points: &[Point3; 4], u: f64) -> (Point3, Vec3) {
+    let a = [
+        lerp(u, points[0], points[1]),
+        lerp(u, points[1], points[2]),
+        lerp(u, points[2], points[3]),
+    ];
+    let b = [lerp(u, a[0], a[1]), lerp(u, a[1], a[2])];
+    let derivative = if (b[1] - b[0]).length_squared() > 0.0 {
+        3.0 * (b[1] - b[0])
+    } else {
+        // Coincident control points at an end
+        points[3] - points[0]
+    };
+    (lerp(u, b[0], b[1]), derivative)
+}
+
+// Control points of the two halves, sharing the middle one
+fn subdivide(p: &[Point3; 4]) -> [Point3; 7] {
+    [
+        p[0],
+        (p[0] + p[1]) / 2.0,
+        (p[0] + 2.0 * p[1] + p[2]) / 4.0,
+        (p[0] + 3.0 * p[1] + 3.0 * p[2] + p[3]) / 8.0,
+        (p[1] + 2.0 * p[2] + p[3]) / 4.0,
+        (p[2] + p[3]) / 2.0,
+        p[3],
+    ]
+}
+
+// Polar form of the curve, from which the control points of any stretch
+// of it follow
+fn blossom(p: &[Point3; 4], u0: f64, u1: f64, u2: f64) -> Point3 {
+    let a = [
+        lerp(u0, p[0], p[1]),
+        lerp(u0, p[1], p[2]),
+        lerp(u0, p[2], p[3]),
+    ];
+    let b = [lerp(u1, a[0], a[1]), lerp(u1, a[1], a[2])];
+    lerp(u2, b[0], b[1])
+}
+
+// Turns evenly from one normal to the other
+fn ribbon_normal(normals: [Vec3; 2], u: f64) -> Vec3 {
+    let cos_angle = dot(normals[0], normals[1]).clamp(-1.0, 1.0);
+    if cos_angle > 0.9995 {
+        return unit_vector((1.0 - u) * normals[0] + u * normals[1]);
+    }
+    let angle = cos_angle.acos();
+    ((angle * (1.0 - u)).sin() * normals[0] + (angle * u).sin() * normals[1]) / angle.sin()
+}
+
+// A curve through the four control points, its width going from the first
+// of `widths` at the start to the second at the end. It comes back as
+// separate segments, ready to go into a hierarchy with everything else.
+pub fn make_curve(
+    points: [Point3; 4],
+    widths: [f64; 2],
+    kind: CurveType,
+    mat: Box<dyn Material>,
+) -> Vec<Box<dyn Hittable>> {
+    let kind = match kind {
+        CurveType::Ribbon(normals) => {
+            CurveType::Ribbon([unit_vector(normals[0]), unit_vector(normals[1])])
+        }
+        CurveType::Cylinder => CurveType::Cylinder,
+    };
+    let common = Rc::new(CurveCommon { kind, widths, mat });
+
+    let count = 1 << SPLIT_DEPTH;
+    let mut segments: Vec<Box<dyn Hittable>> = Vec::with_capacity(count);
+    let mut i = 0;
+    while i < count {
+        let u_min = i as f64 / count as f64;
+        let u_max = (i + 1) as f64 / count as f64;
+        let segment_points = [
+            blossom(&points, u_min, u_min, u_min),
+            blossom(&points, u_min, u_min, u_max),
+            blossom(&points, u_min, u_max, u_max),
+            blossom(&points, u_max, u_max, u_max),
+        ];
+        let pad = 0.5 * widths[0].max(widths[1]);
+        let mut low = segment_points[0];
+        let mut high = segment_points[0];
+        for p in &segment_points[1..] {
+            low = make_point(low.x().min(p.x()), low.y().min(p.y()), low.z().min(p.z()));
+            high = make_point(
+                high.x().max(p.x()),
+                high.y().max(p.y()),
+                high.z().max(p.z()),
+            );
+        }
+        let padding = Vec3(pad, pad, pad);
+        segments.push(Box::new(Curve {
+            common: common.clone(),
+            points: segment_points,
+            u_min,
+            u_max,
+            bbox: make_aabb(low - padding, high + padding),
+        }));
+        i += 1;
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_curve, CurveType};
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_shades_a_straight_curve_like_a_cylinder() {
+        // Along z from -1 to 1, with a radius of 0.1
+        let curve = HittableList {
+            objects: make_curve(
+                [
+                    make_point(0.0, 0.0, -1.0),
+                    make_point(0.0, 0.0, -1.0 / 3.0),
+                    make_point(0.0, 0.0, 1.0 / 3.0),
+                    make_point(0.0, 0.0, 1.0),
+                ],
+                [0.2, 0.2],
+                CurveType::Cylinder,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            ),
+        };
+
+        let mut i = 0;
+        while i < 11 {
+            let offset = -0.12 + 0.024 * i as f64;
+            let r = Ray {
+                origin: make_point(-5.0, offset, 0.3),
+                direction: Vec3(2.0, 0.0, 0.0),
+            };
+            let mut rec = HitRecord::default();
+            let hit = curve.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec);
+            assert_eq!(hit, offset.abs() <= 0.1);
+            if hit {
+                // On the plane through the axis facing the ray, with the
+                // normal a round tube would have there
+                let h = offset / 0.1;
+                assert!((rec.t - 2.5).abs() < 1e-9);
+                assert!((rec.u - 0.65).abs() < 1e-9);
+                assert!((rec.v - (0.5 + 0.5 * h)).abs() < 1e-9);
+                assert!((rec.normal.x() + (1.0 - h * h).sqrt()).abs() < 1e-9);
+                assert!((rec.normal.y() - h).abs() < 1e-9);
+                assert!(rec.front_face && (rec.tangent.z() - 1.0).abs() < 1e-9);
+
+                // Leaving from there, the ray doesn't find the curve again
+                let out = Ray {
+                    origin: rec.p,
+                    direction: Vec3(1.0, 0.3, 0.2),
+                };
+                let mut again = HitRecord::default();
+                assert!(!curve.hit(&out, interval::new(0.001, f64::INFINITY), &mut again));
+            }
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::bvh::{node_visits, reset_node_visits};
+use crate::color::{make_color, Color};
+use crate::film::Film;
+use crate::hit_record::HitRecord;
+use crate::integrator::{occluded, trace_path, Integrator, Scene};
+use crate::interval;
+use crate::material::outward_normal;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{sample_unit_sphere, unit_vector};
+
+// Diagnostic views of a scene. They all look only at the first hit of the
+// camera ray, except the bounce heatmap which runs the full path tracer.
+
+// Fraction of the hemisphere above the first hit that is open out to
+// `radius`, estimated from `samples` cosine weighted rays
+pub struct AmbientOcclusionIntegrator {
+    radius: f64,
+    samples: i32,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        _max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        let Some(rec) = first_hit(&r, scene) else {
+            return make_color(1.0, 1.0, 1.0);
+        };
+        let mut open = 0;
+        let mut sample = 0;
+        while sample < self.samples {
+            let mut direction = rec.normal + sample_unit_sphere(sampler.get_2d());
+            if direction.near_zero() {
+                direction = rec.normal;
+            }
+            let ray = Ray {
+                origin: rec.p,
+                direction: unit_vector(direction),
+            };
+            if !occluded(&ray, self.radius, scene.world) {
+                open += 1;
+            }
+            sample += 1;
+        }
+        let visibility = open as f64 / self.samples as f64;
+        make_color(visibility, visibility, visibility)
+    }
+}
+
+pub fn make_ambient_occlusion_integrator(radius: f64, samples: i32) -> AmbientOcclusionIntegrator {
+    AmbientOcclusionIntegrator {
+        radius,
+        samples: samples.max(1),
+    }
+}
+
+// Outward surface normal mapped to a color, black for misses
+pub struct NormalsIntegrator {}
+
+impl Integrator for NormalsIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        _max_depth: i32,
+        _sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        match first_hit(&r, scene) {
+            Some(rec) => 0.5 * (outward_normal(&rec) + make_color(1.0, 1.0, 1.0)),
+            None => make_color(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+pub fn make_normals_integrator() -> NormalsIntegrator {
+    NormalsIntegrator {}
+}
+
+// Surface coordinates as red (u) and green (v)
+pub struct UvIntegrator {}
+
+impl Integrator for UvIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        _max_depth: i32,
+        _sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        match first_hit(&r, scene) {
+            Some(rec) => make_color(rec.u, rec.v, 0.0),
+            None => make_color(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+pub fn make_uv_integrator() -> UvIntegrator {
+    UvIntegrator {}
+}
+
+// Distance to the first hit, white at `near` fading to black at `far`
+pub struct DepthIntegrator {
+    near: f64,
+    far: f64,
+}
+
+impl Integrator for DepthIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        _max_depth: i32,
+        _sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        let Some(rec) = first_hit(&r, scene) else {
+            return make_color(0.0, 0.0, 0.0);
+        };
+        let distance = rec.t * r.direction().length();
+        let shade = 1.0 - ((distance - self.near) / (self.far - self.near)).clamp(0.0, 1.0);
+        make_color(shade, shade, shade)
+    }
+}
+
+pub fn make_depth_integrator(near: f64, far: f64) -> DepthIntegrator {
+    DepthIntegrator { near, far }
+}
+
+// Number of bounces the path tracer made before the path ended, from blue
+// (none) to red (max_depth)
+pub struct BounceHeatmapIntegrator {
+    russian_roulette_depth: i32,
+}
+
+impl Integrator for BounceHeatmapIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        let (_, bounces) = trace_path(
+            r,
+            scene,
+            max_depth,
+            self.russian_roulette_depth,
+            false,
+            None,
+            sampler,
+        );
+        heatmap(bounces as f64 / max_depth.max(1) as f64)
+    }
+}
+
+pub fn make_bounce_heatmap_integrator(russian_roulette_depth: i32) -> BounceHeatmapIntegrator {
+    BounceHeatmapIntegrator {
+        russian_roulette_depth,
+    }
+}
+
+// BVH nodes visited while finding the first hit, from blue (none) to red
+// (max_visits or more)
+pub struct BvhCostIntegrator {
+    max_visits: f64,
+}
+
+impl Integrator for BvhCostIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        _max_depth: i32,
+        _sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        reset_node_visits();
+        first_hit(&r, scene);
+        heatmap(node_visits() as f64 / self.max_visits)
+    }
+}
+
+pub fn make_bvh_cost_integrator(max_visits: f64) -> BvhCostIntegrator {
+    BvhCostIntegrator {
+        max_visits: max_visits.max(1.0),
+    }
+}
+
+fn first_hit(r: &Ray, scene: &Scene) -> Option<HitRecord> {
+    let mut rec = HitRecord::default();
+    if scene
+        .world
+        .hit(r, interval::new(0.001, f64::INFINITY), &mut rec)
+    {
+        Some(rec)
+    } else {
+        None
+    }
+}
+
+// Blue through cyan, green and yellow to red for t from 0 to 1
+fn heatmap(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    make_color(
+        (1.5 - (4.0 * t - 3.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * t - 2.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * t - 1.0).abs()).clamp(0.0, 1.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::make_gradient_sky;
+    use crate::bvh::make_bvh;
+    use crate::camera::Camera;
+    use crate::film::make_film;
+    use crate::hittable::Hittable;
+    use crate::light::make_light_list;
+    use crate::material::make_lambertian;
+    use crate::sampler::make_stratified_sampler;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_counts_the_nodes_a_ray_visits() {
+        // Four spheres in a row give a root over two nodes of two spheres
+        let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+        let mut i = 0;
+        while i < 4 {
+            objects.push(Box::new(make_sphere(
+                make_point(i as f64 * 2.0, 0.0, 0.0),
+                0.5,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )));
+            i += 1;
+        }
+        let world = make_bvh(objects);
+        let lights = make_light_list();
+        let background = make_gradient_sky(make_color(0.0, 0.0, 0.0), make_color(0.0, 0.0, 0.0));
+        let camera = Camera::default();
+        let scene = Scene {
+            world: world.as_ref(),
+            lights: &lights,
+            background: &background,
+            camera: &camera,
+        };
+        let integrator = make_bvh_cost_integrator(4.0);
+        let mut sampler = make_stratified_sampler(true);
+        let mut film = make_film(1, 1);
+        let mut cost = |origin: Vec3, direction: Vec3| {
+            let r = Ray { origin, direction };
+            integrator.li(r, &scene, 10, &mut sampler, &mut film)
+        };
+        // Missing the root's box stops at the root
+        let miss = cost(make_point(0.0, 5.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!((miss - heatmap(0.25)).length() < 1e-12);
+        // Hitting the first sphere opens the root and both of its children,
+        // and the count starts over for every ray
+        let hit = cost(make_point(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!((hit - heatmap(0.75)).length() < 1e-12);
+        let miss = cost(make_point(0.0, 5.0, 5.0), Vec3(0.0, 0.0, -1.0));
+        assert!((miss - heatmap(0.25)).length() < 1e-12);
+    }
+}
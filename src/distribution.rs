@@ -0,0 +1,145 @@
+use crate::rtweekend::ONE_MINUS_EPSILON;
+
+// Piecewise constant distribution over [0, 1) built from tabulated values,
+// sampled by inverting its CDF.
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64,
+}
+
+impl Distribution1D {
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.func_int
+    }
+
+    // Returns the sampled position in [0, 1), its density and the bucket it fell in
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.count();
+        let offset = self.find_interval(u);
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+        let pdf = if self.func_int > 0.0 {
+            self.func[offset] / self.func_int
+        } else {
+            0.0
+        };
+        let x = ((offset as f64 + du) / n as f64).min(ONE_MINUS_EPSILON);
+        (x, pdf, offset)
+    }
+
+    // Largest index whose CDF value is <= u
+    fn find_interval(&self, u: f64) -> usize {
+        let index = self.cdf.partition_point(|&c| c <= u);
+        index.saturating_sub(1).min(self.count() - 1)
+    }
+}
+
+pub fn make_distribution_1d(f: &[f64]) -> Distribution1D {
+    let n = f.len().max(1);
+    let func: Vec<f64> = if f.is_empty() {
+        vec![0.0]
+    } else {
+        f.iter().map(|v| v.abs()).collect()
+    };
+    let mut cdf = vec![0.0; n + 1];
+    let mut i = 1;
+    while i <= n {
+        cdf[i] = cdf[i - 1] + func[i - 1] / n as f64;
+        i += 1;
+    }
+    let func_int = cdf[n];
+    i = 1;
+    while i <= n {
+        cdf[i] = if func_int == 0.0 {
+            i as f64 / n as f64
+        } else {
+            cdf[i] / func_int
+        };
+        i += 1;
+    }
+    Distribution1D {
+        func,
+        cdf,
+        func_int,
+    }
+}
+
+// Piecewise constant distribution over [0, 1)^2: a marginal distribution
+// picks the row, then that row's conditional distribution picks the column.
+#[derive(Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn sample_continuous(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p: (f64, f64)) -> f64 {
+        if self.marginal.integral() <= 0.0 {
+            return 0.0;
+        }
+        let rows = self.conditional.len();
+        let columns = self.conditional[0].count();
+        let row = ((p.1 * rows as f64) as usize).min(rows - 1);
+        let column = ((p.0 * columns as f64) as usize).min(columns - 1);
+        self.conditional[row].func[column] / self.marginal.integral()
+    }
+}
+
+// Values are given row by row, width values per row
+pub fn make_distribution_2d(values: &[f64], width: usize, height: usize) -> Distribution2D {
+    let conditional: Vec<Distribution1D> = (0..height)
+        .map(|row| make_distribution_1d(&values[row * width..(row + 1) * width]))
+        .collect();
+    let marginal_func: Vec<f64> = conditional.iter().map(|d| d.integral()).collect();
+    Distribution2D {
+        conditional,
+        marginal: make_distribution_1d(&marginal_func),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_the_density_it_samples_with() {
+        let values = [0.0, 1.0, 2.0, 3.0, 0.5, 4.0, 1.0, 0.0, 2.0, 6.0, 0.0, 1.0];
+        let distribution = make_distribution_2d(&values, 4, 3);
+        // The density integrates to one over the unit square
+        let n = 120;
+        let mut total = 0.0;
+        let mut counts = [0usize; 12];
+        for j in 0..n {
+            for i in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                total += distribution.pdf(u);
+                let (p, pdf) = distribution.sample_continuous(u);
+                assert!((pdf - distribution.pdf(p)).abs() < 1e-12);
+                assert!(pdf > 0.0);
+                counts[(p.1 * 3.0) as usize * 4 + (p.0 * 4.0) as usize] += 1;
+            }
+        }
+        assert!((total / (n * n) as f64 - 1.0).abs() < 1e-12);
+        // Each cell is picked in proportion to its value
+        let sum: f64 = values.iter().sum();
+        for (count, value) in counts.iter().zip(values) {
+            let share = *count as f64 / (n * n) as f64;
+            assert!((share - value / sum).abs() < 0.01);
+        }
+    }
+}
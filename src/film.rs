@@ -0,0 +1,67 @@
+use crate::color::{make_color, write_color, Color};
+
+// Image being rendered. Camera samples are averaged per pixel with their
+// filter weights, while splats from light paths can land on any pixel and
+// are scaled separately when the image is written.
+pub struct Film {
+    pub width: i32,
+    pub height: i32,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    splats: Vec<Color>,
+}
+
+impl Film {
+    pub fn add_sample(&mut self, i: i32, j: i32, color: Color, weight: f64) {
+        let index = (j * self.width + i) as usize;
+        self.sums[index] += weight * color;
+        self.weights[index] += weight;
+    }
+
+    // Adds to the pixel containing raster position (x, y), ignoring
+    // positions outside the image
+    pub fn add_splat(&mut self, x: f64, y: f64, color: Color) {
+        let (i, j) = (x.floor() as i32, y.floor() as i32);
+        if i < 0 || j < 0 || i >= self.width || j >= self.height {
+            return;
+        }
+        self.splats[(j * self.width + i) as usize] += color;
+    }
+
+    // Final value of pixel (i, j), with splats scaled by splat_scale
+    pub fn pixel(&self, i: i32, j: i32, splat_scale: f64) -> Color {
+        let index = (j * self.width + i) as usize;
+        // Filter weights can cancel out entirely with negative lobes
+        let average = if self.weights[index] > 0.0 {
+            self.sums[index] / self.weights[index]
+        } else {
+            make_color(0.0, 0.0, 0.0)
+        };
+        average + splat_scale * self.splats[index]
+    }
+
+    // Writes the image as a PPM to stdout
+    pub fn write(&self, splat_scale: f64) {
+        println!("P3\n{0} {1}\n255", self.width, self.height);
+        let mut j = 0;
+        while j < self.height {
+            let mut i = 0;
+            while i < self.width {
+                write_color(self.pixel(i, j, splat_scale));
+                i += 1;
+            }
+            j += 1;
+        }
+    }
+}
+
+pub fn make_film(width: i32, height: i32) -> Film {
+    let size = (width * height) as usize;
+    Film {
+        width,
+        height,
+        sums: vec![make_color(0.0, 0.0, 0.0); size],
+        weights: vec![0.0; size],
+        splats: vec![make_color(0.0, 0.0, 0.0); size],
+    }
+}
@@ -0,0 +1,301 @@
+use crate::distribution::{make_distribution_2d, Distribution2D};
+use crate::rtweekend::PI;
+
+// Reconstruction filters weight each camera sample by its offset from the
+// pixel center. Rather than splatting, the camera importance samples the
+// offset from the filter and keeps the leftover weight f / pdf, which is 1 for
+// positive filters that can be sampled exactly.
+pub trait Filter {
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+    fn sample(&self, u: (f64, f64)) -> FilterSample;
+}
+
+pub struct FilterSample {
+    pub x: f64,
+    pub y: f64,
+    pub weight: f64,
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl Filter for BoxFilter {
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        FilterSample {
+            x: (2.0 * u.0 - 1.0) * self.radius,
+            y: (2.0 * u.1 - 1.0) * self.radius,
+            weight: 1.0,
+        }
+    }
+}
+
+pub fn make_box_filter(radius: f64) -> BoxFilter {
+    BoxFilter { radius }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl Filter for TentFilter {
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        FilterSample {
+            x: sample_tent(u.0, self.radius),
+            y: sample_tent(u.1, self.radius),
+            weight: 1.0,
+        }
+    }
+}
+
+// Inverts the CDF of a tent of the given radius centered at zero
+fn sample_tent(u: f64, radius: f64) -> f64 {
+    if u < 0.5 {
+        radius * ((2.0 * u).sqrt() - 1.0)
+    } else {
+        radius * (1.0 - (2.0 - 2.0 * u).sqrt())
+    }
+}
+
+pub fn make_tent_filter(radius: f64) -> TentFilter {
+    TentFilter { radius }
+}
+
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl Filter for GaussianFilter {
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        gaussian_1d(x, self.radius, self.sigma) * gaussian_1d(y, self.radius, self.sigma)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.sampler.sample(u, self)
+    }
+}
+
+// Gaussian shifted down so that it reaches zero at the radius
+fn gaussian_1d(x: f64, radius: f64, sigma: f64) -> f64 {
+    let g = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+    (g(x) - g(radius)).max(0.0)
+}
+
+pub fn make_gaussian_filter(radius: f64, sigma: f64) -> GaussianFilter {
+    let sampler = make_tabulated_filter_sampler(radius, |x, y| {
+        gaussian_1d(x, radius, sigma) * gaussian_1d(y, radius, sigma)
+    });
+    GaussianFilter {
+        radius,
+        sigma,
+        sampler,
+    }
+}
+
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl Filter for MitchellFilter {
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        mitchell_1d(2.0 * x / self.radius, self.b, self.c)
+            * mitchell_1d(2.0 * y / self.radius, self.b, self.c)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.sampler.sample(u, self)
+    }
+}
+
+// Mitchell-Netravali cubic, nonzero on [-2, 2]
+fn mitchell_1d(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+pub fn make_mitchell_filter(radius: f64, b: f64, c: f64) -> MitchellFilter {
+    let sampler = make_tabulated_filter_sampler(radius, |x, y| {
+        mitchell_1d(2.0 * x / radius, b, c) * mitchell_1d(2.0 * y / radius, b, c)
+    });
+    MitchellFilter {
+        radius,
+        b,
+        c,
+        sampler,
+    }
+}
+
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl Filter for LanczosFilter {
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        windowed_sinc(x, self.radius, self.tau) * windowed_sinc(y, self.radius, self.tau)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> FilterSample {
+        self.sampler.sample(u, self)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn windowed_sinc(x: f64, radius: f64, tau: f64) -> f64 {
+    if x.abs() > radius {
+        0.0
+    } else {
+        sinc(x) * sinc(x / tau)
+    }
+}
+
+pub fn make_lanczos_filter(radius: f64, tau: f64) -> LanczosFilter {
+    let sampler = make_tabulated_filter_sampler(radius, |x, y| {
+        windowed_sinc(x, radius, tau) * windowed_sinc(y, radius, tau)
+    });
+    LanczosFilter {
+        radius,
+        tau,
+        sampler,
+    }
+}
+
+// Samples filters without an analytic inverse from a table of |f| over the
+// filter's extent; the returned weight carries the sign of negative lobes.
+struct TabulatedFilterSampler {
+    radius: f64,
+    distribution: Distribution2D,
+}
+
+impl TabulatedFilterSampler {
+    fn sample(&self, u: (f64, f64), filter: &dyn Filter) -> FilterSample {
+        let (p, pdf) = self.distribution.sample_continuous(u);
+        // Map from [0, 1)^2 to [-radius, radius]^2
+        let x = (2.0 * p.0 - 1.0) * self.radius;
+        let y = (2.0 * p.1 - 1.0) * self.radius;
+        let area_pdf = pdf / (4.0 * self.radius * self.radius);
+        let weight = if area_pdf > 0.0 {
+            filter.evaluate(x, y) / area_pdf
+        } else {
+            0.0
+        };
+        FilterSample { x, y, weight }
+    }
+}
+
+fn make_tabulated_filter_sampler(
+    radius: f64,
+    f: impl Fn(f64, f64) -> f64,
+) -> TabulatedFilterSampler {
+    let size = ((32.0 * radius) as usize).max(8);
+    let mut values = Vec::with_capacity(size * size);
+    let mut j = 0;
+    while j < size {
+        let y = ((j as f64 + 0.5) / size as f64 * 2.0 - 1.0) * radius;
+        let mut i = 0;
+        while i < size {
+            let x = ((i as f64 + 0.5) / size as f64 * 2.0 - 1.0) * radius;
+            values.push(f(x, y));
+            i += 1;
+        }
+        j += 1;
+    }
+    TabulatedFilterSampler {
+        radius,
+        distribution: make_distribution_2d(&values, size, size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Midpoint rule over the filter's square extent
+    fn integrate(filter: &dyn Filter, radius: f64) -> f64 {
+        let n = 400;
+        let step = 2.0 * radius / n as f64;
+        let mut sum = 0.0;
+        for j in 0..n {
+            let y = -radius + (j as f64 + 0.5) * step;
+            for i in 0..n {
+                let x = -radius + (i as f64 + 0.5) * step;
+                sum += filter.evaluate(x, y);
+            }
+        }
+        sum * step * step
+    }
+
+    #[test]
+    fn it_integrates_filters_to_their_analytic_values() {
+        assert!((integrate(&make_box_filter(0.5), 0.5) - 1.0).abs() < 1e-9);
+        // A tent of radius r has area r^2 in each dimension
+        assert!((integrate(&make_tent_filter(1.5), 1.5) - 1.5f64.powi(4)).abs() < 1e-4);
+        // The Mitchell cubic integrates to one over [-2, 2], so to r / 2
+        // once stretched onto [-r, r]
+        let mitchell = make_mitchell_filter(2.0, 1.0 / 3.0, 1.0 / 3.0);
+        assert!((integrate(&mitchell, 2.0) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn it_weights_tabulated_samples_to_the_filter_integral() {
+        // f / pdf averages to the integral of f, negative lobes included
+        let filters: [(Box<dyn Filter>, f64); 3] = [
+            (Box::new(make_gaussian_filter(1.5, 0.5)), 1.5),
+            (Box::new(make_mitchell_filter(2.0, 0.0, 0.5)), 2.0),
+            (Box::new(make_lanczos_filter(2.0, 3.0)), 2.0),
+        ];
+        for (filter, radius) in &filters {
+            let n = 1024;
+            let mut sum = 0.0;
+            for j in 0..n {
+                for i in 0..n {
+                    let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                    let s = filter.sample(u);
+                    assert!(s.x.abs() <= *radius && s.y.abs() <= *radius);
+                    sum += s.weight;
+                }
+            }
+            let expected = integrate(filter.as_ref(), *radius);
+            let mean = sum / (n * n) as f64;
+            assert!((mean - expected).abs() < 0.01 * expected.abs());
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use crate::{
+    background::luminance,
+    color::{make_color, Color},
+    hit_record::HitRecord,
+    material::{scatter_local, Material},
+    microfacet::fr_dielectric,
+    onb::{make_onb, Onb},
+    ray::Ray,
+    rtweekend::{degrees_to_radians, PI},
+    sampler::Sampler,
+    vec3::{cross, dot, unit_vector, Vec3},
+};
+
+// Lobes followed separately: reflection off the surface, light passing
+// straight through, light reflected once inside. Everything that bounces
+// more often is lumped into one last lobe.
+const P_MAX: usize = 3;
+
+// Hair fiber scattering after d'Eon et al. and Chiang et al. as in pbrt: a
+// rough dielectric cylinder with absorbing pigment inside and cuticle scales
+// tilted by alpha degrees. beta_m and beta_n in [0, 1] set how far light
+// spreads along and around the fiber. It needs a hit on a curve, which
+// gives the direction of the fiber and how far off center it was hit.
+// Since that offset belongs to the ray that found the fiber, the model isn't
+// reciprocal, and integrators tracing light from the lights (bdpt, photon)
+// make pale hair somewhat brighter than path tracing does.
+#[derive(Clone)]
+pub struct Hair {
+    // Absorption per unit of fiber radius
+    pub sigma_a: Color,
+    pub eta: f64,
+    pub beta_m: f64,
+    pub beta_n: f64,
+    pub alpha: f64,
+}
+
+// The parameters resolved for one hit
+struct Fiber {
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Color,
+    // Variance of the longitudinal lobes
+    v: [f64; P_MAX + 1],
+    // Scale of the azimuthal lobes
+    s: f64,
+    // Sine and cosine of 2^k alpha for k = 0, 1, 2
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Material for Hair {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let u0 = sampler.get_2d();
+        let u1 = sampler.get_2d();
+        let fiber = self.fiber(rec);
+        let onb = fiber_frame(rec);
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        let wi = fiber.sample(wo, u0, u1);
+        scatter_local(self, &r_in, rec, &onb, wi, attenuation, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let onb = fiber_frame(rec);
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        let wi = onb.world_to_local(unit_vector(scattered.direction()));
+        self.fiber(rec).eval(wo, wi)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let onb = fiber_frame(rec);
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        let wi = onb.world_to_local(unit_vector(scattered.direction()));
+        self.fiber(rec).pdf(wo, wi)
+    }
+}
+
+impl Hair {
+    fn fiber(&self, rec: &HitRecord) -> Fiber {
+        let h = (2.0 * rec.v - 1.0).clamp(-1.0, 1.0);
+        let beta_m = self.beta_m.clamp(0.01, 1.0);
+        let beta_n = self.beta_n.clamp(0.01, 1.0);
+        let v0 = sqr(0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20));
+        let mut sin_2k_alpha = [degrees_to_radians(self.alpha).sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sqr(sin_2k_alpha[0])), 0.0, 0.0];
+        let mut i = 1;
+        while i < 3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = sqr(cos_2k_alpha[i - 1]) - sqr(sin_2k_alpha[i - 1]);
+            i += 1;
+        }
+        Fiber {
+            h,
+            gamma_o: h.asin(),
+            eta: self.eta,
+            sigma_a: self.sigma_a,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s: (PI / 8.0).sqrt()
+                * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22)),
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+}
+
+impl Fiber {
+    // BSDF times cosine, in the frame with x along the fiber
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+        let ap = self.attenuation(cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut sum = make_color(0.0, 0.0, 0.0);
+        let mut p = 0;
+        while p < P_MAX {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            sum += mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            ) * np(phi, p, self.s, self.gamma_o, gamma_t)
+                * ap[p];
+            p += 1;
+        }
+        sum += mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) / (2.0 * PI)
+            * ap[P_MAX];
+        sum
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let (sin_theta_i, cos_theta_i, phi_i) = angles(wi);
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+        let lobe_pdfs = self.lobe_pdfs(cos_theta_o);
+        let phi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        let mut p = 0;
+        while p < P_MAX {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            ) * lobe_pdfs[p]
+                * np(phi, p, self.s, self.gamma_o, gamma_t);
+            p += 1;
+        }
+        pdf + mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * lobe_pdfs[P_MAX]
+            / (2.0 * PI)
+    }
+
+    // Picks a lobe by its share of the light, then the longitudinal and
+    // azimuthal angles from that lobe
+    fn sample(&self, wo: Vec3, u0: (f64, f64), u1: (f64, f64)) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = angles(wo);
+        let lobe_pdfs = self.lobe_pdfs(cos_theta_o);
+        let mut u_lobe = u0.0;
+        let mut p = 0;
+        while p < P_MAX {
+            if u_lobe < lobe_pdfs[p] {
+                break;
+            }
+            u_lobe -= lobe_pdfs[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u = u1.0.max(1e-5);
+        let cos_theta = 1.0 + self.v[p] * (u + (1.0 - u) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - sqr(cos_theta));
+        let cos_phi = (2.0 * PI * u1.1).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+
+        let dphi = if p < P_MAX {
+            let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(u0.1, self.s)
+        } else {
+            2.0 * PI * u0.1
+        };
+        let phi_i = phi_o + dphi;
+        Vec3(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+
+    // Angle of the refracted ray from the fiber's center as seen along it
+    fn gamma_t(&self, sin_theta_o: f64, cos_theta_o: f64) -> f64 {
+        let etap = (sqr(self.eta) - sqr(sin_theta_o)).sqrt() / cos_theta_o;
+        (self.h / etap).clamp(-1.0, 1.0).asin()
+    }
+
+    // Fraction of the light leaving through each lobe
+    fn attenuation(&self, cos_theta_o: f64) -> [Color; P_MAX + 1] {
+        let sin_theta_o = safe_sqrt(1.0 - sqr(cos_theta_o));
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sqr(sin_theta_t));
+        let cos_gamma_t = self.gamma_t(sin_theta_o, cos_theta_o).cos();
+        // Once across the fiber
+        let length = 2.0 * cos_gamma_t / cos_theta_t;
+        let t = Vec3(
+            (-self.sigma_a.x() * length).exp(),
+            (-self.sigma_a.y() * length).exp(),
+            (-self.sigma_a.z() * length).exp(),
+        );
+
+        let f = fr_dielectric(cos_theta_o * safe_sqrt(1.0 - sqr(self.h)), self.eta);
+        let white = make_color(1.0, 1.0, 1.0);
+        let mut ap = [white * f, white * sqr(1.0 - f) * t, white, white];
+        ap[2] = ap[1] * t * f;
+        // The remaining bounces sum to a geometric series
+        let rest = ap[2] * t * f;
+        ap[P_MAX] = Vec3(
+            rest.x() / (1.0 - t.x() * f),
+            rest.y() / (1.0 - t.y() * f),
+            rest.z() / (1.0 - t.z() * f),
+        );
+        ap
+    }
+
+    fn lobe_pdfs(&self, cos_theta_o: f64) -> [f64; P_MAX + 1] {
+        let ap = self.attenuation(cos_theta_o).map(luminance);
+        let total: f64 = ap.iter().sum();
+        if total <= 0.0 {
+            return [1.0, 0.0, 0.0, 0.0];
+        }
+        ap.map(|a| a / total)
+    }
+
+    // Turns the outgoing direction by the cuticle scales' tilt, which shifts
+    // each lobe by a multiple of it
+    fn tilt(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_op, cos_theta_op.abs())
+    }
+}
+
+// Frame with x along the fiber and z facing the ray that found it, the
+// curve's normal being turned away from that by how far off center it was
+// hit
+fn fiber_frame(rec: &HitRecord) -> Onb {
+    let x = if rec.tangent.near_zero() {
+        make_onb(rec.normal).u
+    } else {
+        rec.tangent
+    };
+    let n = unit_vector(rec.normal - dot(rec.normal, x) * x);
+    let h = (2.0 * rec.v - 1.0).clamp(-1.0, 1.0);
+    let z = safe_sqrt(1.0 - h * h) * n + h * cross(x, n);
+    Onb {
+        u: x,
+        v: cross(z, x),
+        w: z,
+    }
+}
+
+// Sine and cosine of the angle from the plane square to the fiber, and the
+// angle around it
+fn angles(w: Vec3) -> (f64, f64, f64) {
+    let sin_theta = w.x().clamp(-1.0, 1.0);
+    (
+        sin_theta,
+        safe_sqrt(1.0 - sqr(sin_theta)),
+        w.z().atan2(w.y()),
+    )
+}
+
+fn sqr(x: f64) -> f64 {
+    x * x
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+// Modified Bessel function of the first kind
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial: f64 = 1.0;
+    let mut four_i = 1.0;
+    let mut i = 0;
+    while i < 10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * sqr(factorial));
+        x2i *= x * x;
+        four_i *= 4.0;
+        i += 1;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+// Longitudinal scattering
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // Worked out in logs, where the terms would overflow
+        (log_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Azimuth at which lobe p leaves a smooth fiber
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * sqr(1.0 + (-x / s).exp()))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// Logistic distribution cut down to [-pi, pi]
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+// Azimuthal scattering
+fn np(phi_difference: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_difference - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s)
+}
+
+pub fn make_hair(sigma_a: Color) -> Hair {
+    Hair {
+        sigma_a,
+        eta: 1.55,
+        beta_m: 0.3,
+        beta_n: 0.3,
+        alpha: 2.0,
+    }
+}
+
+// Hair colored by its concentrations of the dark brown eumelanin and the
+// reddish pheomelanin; about 8 is black, 1.3 brown and 0.3 blonde
+pub fn make_hair_from_melanin(eumelanin: f64, pheomelanin: f64) -> Hair {
+    let eumelanin_sigma_a = make_color(0.419, 0.697, 1.37);
+    let pheomelanin_sigma_a = make_color(0.187, 0.4, 1.05);
+    make_hair(eumelanin * eumelanin_sigma_a + pheomelanin * pheomelanin_sigma_a)
+}
+
+// Hair that looks about the given color once light has bounced around in
+// many fibers, for an azimuthal roughness of beta_n
+pub fn make_hair_from_color(color: Color, beta_n: f64) -> Hair {
+    let falloff = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+        + 5.574 * beta_n.powi(4)
+        + 0.245 * beta_n.powi(5);
+    let channel = |c: f64| sqr(c.clamp(1e-4, 1.0).ln() / falloff);
+    let mut hair = make_hair(make_color(
+        channel(color.x()),
+        channel(color.y()),
+        channel(color.z()),
+    ));
+    hair.beta_n = beta_n;
+    hair
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_hair;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::sampler::{make_independent_sampler, Sampler};
+    use crate::vec3::{make_point, sample_unit_sphere, Vec3};
+
+    #[test]
+    fn it_keeps_all_light_in_a_fiber_without_pigment() {
+        // A white furnace: with nothing absorbed, all light arriving at the
+        // fiber leaves it again, whichever way it is estimated
+        let mut hair = make_hair(make_color(0.0, 0.0, 0.0));
+        hair.beta_m = 0.4;
+        hair.beta_n = 0.4;
+        let mut sampler = make_independent_sampler();
+        let mut rec = HitRecord {
+            normal: Vec3(0.0, 0.0, 1.0),
+            tangent: Vec3(1.0, 0.0, 0.0),
+            front_face: true,
+            ..Default::default()
+        };
+
+        let samples = 100000;
+        let mut uniform = 0.0;
+        let mut sampled = 0.0;
+        let mut i = 0;
+        while i < samples {
+            rec.v = sampler.get_1d();
+            let r_in = Ray {
+                origin: make_point(0.0, 0.0, 0.0),
+                direction: -sample_unit_sphere(sampler.get_2d()),
+            };
+            let scattered = Ray {
+                origin: rec.p,
+                direction: sample_unit_sphere(sampler.get_2d()),
+            };
+            uniform += hair.eval(&r_in, &rec, &scattered).y() * 4.0 * std::f64::consts::PI;
+
+            let mut attenuation = make_color(0.0, 0.0, 0.0);
+            let mut out = scattered;
+            if hair.scatter(r_in, &rec, &mut attenuation, &mut out, &mut sampler) {
+                sampled += attenuation.y();
+            }
+            i += 1;
+        }
+        assert!((uniform / samples as f64 - 1.0).abs() < 0.05);
+        assert!((sampled / samples as f64 - 1.0).abs() < 0.02);
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs;
+use std::io;
+
+use crate::color::{make_color, Color};
+
+// Floating point image in linear color, stored row by row from the top
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+}
+
+// Loads a Radiance .hdr (RGBE) or a PFM image, told apart by their magic bytes
+pub fn load_hdr_image(path: &str) -> io::Result<HdrImage> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(b"PF") || bytes.starts_with(b"Pf") {
+        parse_pfm(&bytes)
+    } else if bytes.starts_with(b"#?") {
+        parse_radiance(&bytes)
+    } else {
+        Err(invalid_data(&format!(
+            "{path} is not a Radiance or PFM image"
+        )))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Number of pixels in an image of the given size, which must not be empty
+fn pixel_count(width: usize, height: usize) -> io::Result<usize> {
+    if width == 0 || height == 0 {
+        return Err(invalid_data(&format!("empty image size {width}x{height}")));
+    }
+    width
+        .checked_mul(height)
+        .ok_or_else(|| invalid_data(&format!("image size {width}x{height} is too large")))
+}
+
+// Reads one newline terminated header line starting at *pos
+fn read_line<'a>(bytes: &'a [u8], pos: &mut usize) -> io::Result<&'a str> {
+    let start = *pos;
+    while *pos < bytes.len() && bytes[*pos] != b'\n' {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() {
+        return Err(invalid_data("unexpected end of header"));
+    }
+    let line =
+        std::str::from_utf8(&bytes[start..*pos]).map_err(|_| invalid_data("header is not text"))?;
+    *pos += 1;
+    Ok(line.trim())
+}
+
+fn parse_radiance(bytes: &[u8]) -> io::Result<HdrImage> {
+    let mut pos = 0;
+    loop {
+        let line = read_line(bytes, &mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(&format!("unsupported format {format}")));
+            }
+        }
+    }
+
+    // Only the standard top to bottom, left to right orientation is supported
+    let resolution = read_line(bytes, &mut pos)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(invalid_data(&format!(
+            "unsupported resolution {resolution}"
+        )));
+    }
+    let height: usize = fields[1]
+        .parse()
+        .map_err(|_| invalid_data("bad image height"))?;
+    let width: usize = fields[3]
+        .parse()
+        .map_err(|_| invalid_data("bad image width"))?;
+    // Runs pack at most 128 pixels into two bytes for each of the four
+    // channels, so a file can't hold more than 16 pixels per byte
+    let count = pixel_count(width, height)?;
+    if count / 16 > bytes.len() {
+        return Err(invalid_data("pixel data is truncated"));
+    }
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut y = 0;
+    while y < height {
+        read_rgbe_scanline(bytes, &mut pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_color(rgbe)));
+        y += 1;
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn next_byte(bytes: &[u8], pos: &mut usize) -> io::Result<u8> {
+    match bytes.get(*pos) {
+        Some(&b) => {
+            *pos += 1;
+            Ok(b)
+        }
+        None => Err(invalid_data("unexpected end of pixel data")),
+    }
+}
+
+fn read_rgbe_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let is_rle = (8..32768).contains(&width)
+        && bytes.len() >= *pos + 4
+        && bytes[*pos] == 2
+        && bytes[*pos + 1] == 2
+        && bytes[*pos + 2] & 0x80 == 0;
+    if !is_rle {
+        // Flat RGBE pixels
+        for pixel in scanline.iter_mut() {
+            for channel in pixel.iter_mut() {
+                *channel = next_byte(bytes, pos)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let encoded_width = ((bytes[*pos + 2] as usize) << 8) | bytes[*pos + 3] as usize;
+    if encoded_width != width {
+        return Err(invalid_data("scanline width mismatch"));
+    }
+    *pos += 4;
+
+    // Each of the four channels is run length encoded separately
+    let mut channel = 0;
+    while channel < 4 {
+        let mut x = 0;
+        while x < width {
+            let count = next_byte(bytes, pos)? as usize;
+            if count > 128 {
+                let run = count - 128;
+                let value = next_byte(bytes, pos)?;
+                if x + run > width {
+                    return Err(invalid_data("run overflows scanline"));
+                }
+                for pixel in scanline[x..x + run].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid_data("bad literal run"));
+                }
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[channel] = next_byte(bytes, pos)?;
+                }
+                x += count;
+            }
+        }
+        channel += 1;
+    }
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return make_color(0.0, 0.0, 0.0);
+    }
+    let f = 2.0_f64.powi(rgbe[3] as i32 - (128 + 8));
+    make_color(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f,
+    )
+}
+
+fn parse_pfm(bytes: &[u8]) -> io::Result<HdrImage> {
+    let mut pos = 0;
+    let channels = match read_line(bytes, &mut pos)? {
+        "PF" => 3,
+        "Pf" => 1,
+        magic => return Err(invalid_data(&format!("unknown PFM type {magic}"))),
+    };
+    let size = read_line(bytes, &mut pos)?;
+    let mut fields = size.split_whitespace().map(|v| v.parse::<usize>());
+    let (width, height) = match (fields.next(), fields.next()) {
+        (Some(Ok(w)), Some(Ok(h))) => (w, h),
+        _ => return Err(invalid_data(&format!("bad PFM size {size}"))),
+    };
+    let scale: f64 = read_line(bytes, &mut pos)?
+        .parse()
+        .map_err(|_| invalid_data("bad PFM scale"))?;
+    // A negative scale marks little endian data
+    let little_endian = scale < 0.0;
+
+    let count = pixel_count(width, height)?
+        .checked_mul(channels)
+        .ok_or_else(|| invalid_data(&format!("PFM size {size} is too large")))?;
+    if (bytes.len() - pos) / 4 < count {
+        return Err(invalid_data("PFM pixel data is truncated"));
+    }
+    let values: Vec<f64> = bytes[pos..pos + 4 * count]
+        .chunks_exact(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            let v = if little_endian {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            };
+            v as f64 * scale.abs()
+        })
+        .collect();
+
+    // PFM rows run from the bottom of the image up
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut y = height;
+    while y > 0 {
+        y -= 1;
+        let row = &values[y * width * channels..(y + 1) * width * channels];
+        pixels.extend(row.chunks_exact(channels).map(|c| {
+            if channels == 3 {
+                make_color(c[0], c[1], c[2])
+            } else {
+                make_color(c[0], c[0], c[0])
+            }
+        }));
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_run_length_encoded_scanlines() {
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 8]);
+        // Red: a run of 8, green: 8 literals, blue and exponent: runs
+        bytes.extend([128 + 8, 128]);
+        bytes.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend([128 + 8, 0]);
+        bytes.extend([128 + 8, 129]);
+        let image = parse_radiance(&bytes).unwrap();
+        assert_eq!(image.width, 8);
+        assert_eq!(image.pixels.len(), 8);
+        assert!((image.pixel(0, 0).x() - 128.5 / 128.0).abs() < 1e-12);
+        assert!((image.pixel(3, 0).y() - 48.5 / 128.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_flips_pfm_rows_to_top_down() {
+        let mut bytes = b"Pf\n1 2\n-1.0\n".to_vec();
+        bytes.extend(1.0_f32.to_le_bytes());
+        bytes.extend(2.0_f32.to_le_bytes());
+        let image = parse_pfm(&bytes).unwrap();
+        assert_eq!(image.pixel(0, 0).x(), 2.0);
+        assert_eq!(image.pixel(0, 1).z(), 1.0);
+    }
+
+    #[test]
+    fn it_rejects_empty_and_oversized_images() {
+        assert!(parse_pfm(b"PF\n0 4\n-1.0\n").is_err());
+        assert!(parse_pfm(b"PF\n4294967296 4294967296\n-1.0\n").is_err());
+        assert!(parse_radiance(b"#?RADIANCE\n\n-Y 0 +X 8\n").is_err());
+        assert!(parse_radiance(b"#?RADIANCE\n\n-Y 100000 +X 100000\n").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::io;
+
+use crate::voxel_grid::fractal_noise;
+
+// Heights on a regular grid of points, stored with x varying fastest, then
+// z. Heights are nominally in [0, 1] and get scaled by whatever uses them.
+pub struct HeightGrid {
+    pub nx: usize,
+    pub nz: usize,
+    pub values: Vec<f64>,
+}
+
+impl HeightGrid {
+    pub fn height(&self, x: usize, z: usize) -> f64 {
+        self.values[z * self.nx + x]
+    }
+}
+
+// Loads a grayscale image in binary (P5) or plain (P2) PGM format, with
+// black as 0 and white as 1. The top row of the image ends up at the
+// smallest z.
+pub fn load_height_grid(path: &str) -> io::Result<HeightGrid> {
+    let bytes = fs::read(path)?;
+    parse_pgm(&bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse_pgm(bytes: &[u8]) -> io::Result<HeightGrid> {
+    // Header fields are separated by whitespace, with comments from # to
+    // the end of the line
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    while fields.len() < 4 {
+        while offset < bytes.len() && (bytes[offset].is_ascii_whitespace() || bytes[offset] == b'#')
+        {
+            if bytes[offset] == b'#' {
+                while offset < bytes.len() && bytes[offset] != b'\n' {
+                    offset += 1;
+                }
+            } else {
+                offset += 1;
+            }
+        }
+        let start = offset;
+        while offset < bytes.len() && !bytes[offset].is_ascii_whitespace() {
+            offset += 1;
+        }
+        if start == offset {
+            return Err(invalid_data("truncated PGM header"));
+        }
+        fields.push(String::from_utf8_lossy(&bytes[start..offset]).to_string());
+    }
+    let number = |field: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| invalid_data("bad number in PGM header"))
+    };
+    let (nx, nz, max_value) = (
+        number(&fields[1])?,
+        number(&fields[2])?,
+        number(&fields[3])?,
+    );
+    if nx < 2 || nz < 2 || max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("bad PGM size or maximum value"));
+    }
+    let count = nx
+        .checked_mul(nz)
+        .ok_or_else(|| invalid_data("bad PGM size or maximum value"))?;
+
+    let samples: Vec<usize> = match fields[0].as_str() {
+        "P5" => {
+            // A single whitespace byte separates the header from the data
+            let data = bytes.get(offset + 1..).unwrap_or(&[]);
+            let size = if max_value < 256 { 1 } else { 2 };
+            if data.len() / size < count {
+                return Err(invalid_data("truncated PGM data"));
+            }
+            if size == 1 {
+                data[..count].iter().map(|b| *b as usize).collect()
+            } else {
+                data[..2 * count]
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                    .collect()
+            }
+        }
+        "P2" => {
+            let samples = String::from_utf8_lossy(&bytes[offset..])
+                .split_ascii_whitespace()
+                .take(count)
+                .map(number)
+                .collect::<io::Result<Vec<usize>>>()?;
+            if samples.len() < count {
+                return Err(invalid_data("truncated PGM data"));
+            }
+            samples
+        }
+        _ => return Err(invalid_data("not a P2 or P5 PGM file")),
+    };
+
+    let values = samples
+        .iter()
+        .map(|s| (*s as f64 / max_value as f64).min(1.0))
+        .collect();
+    Ok(HeightGrid { nx, nz, values })
+}
+
+// Rolling hills: fractal noise of the given base frequency over the unit
+// square, sampled at resolution points along each side
+pub fn make_noise_height_grid(resolution: usize, frequency: f64) -> HeightGrid {
+    let n = resolution.max(2);
+    let mut values = Vec::with_capacity(n * n);
+    let mut z = 0;
+    while z < n {
+        let mut x = 0;
+        while x < n {
+            let p = [
+                x as f64 / (n - 1) as f64 * frequency,
+                z as f64 / (n - 1) as f64 * frequency,
+                0.5,
+            ];
+            values.push((0.5 + 0.7 * fractal_noise(p)).clamp(0.0, 1.0));
+            x += 1;
+        }
+        z += 1;
+    }
+    HeightGrid {
+        nx: n,
+        nz: n,
+        values,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_plain_and_binary_pgm() {
+        // Comments can sit anywhere in the header
+        let plain = b"P2\n# a ramp\n3 2 # width and depth\n4\n0 1 2\n3 4 5\n";
+        let grid = parse_pgm(plain).unwrap();
+        assert_eq!((grid.nx, grid.nz), (3, 2));
+        assert_eq!(grid.height(1, 0), 0.25);
+        assert_eq!(grid.height(0, 1), 0.75);
+        // Samples above the maximum are clamped
+        assert_eq!(grid.height(2, 1), 1.0);
+
+        let mut binary = b"P5 2 2 255\n".to_vec();
+        binary.extend([0, 51, 102, 255]);
+        let grid = parse_pgm(&binary).unwrap();
+        assert_eq!(grid.values, vec![0.0, 0.2, 0.4, 1.0]);
+
+        // Maximum values past 255 take two bytes per sample, most
+        // significant first
+        let mut wide = b"P5 2 2 1000\n".to_vec();
+        for sample in [0_u16, 250, 500, 1000] {
+            wide.extend(sample.to_be_bytes());
+        }
+        let grid = parse_pgm(&wide).unwrap();
+        assert_eq!(grid.values, vec![0.0, 0.25, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn it_rejects_truncated_and_oversized_pgm() {
+        assert!(parse_pgm(b"P2 2 2 255\n0 1 2\n").is_err());
+        assert!(parse_pgm(b"P5 2 2 255\n\x00\x01\x02").is_err());
+        assert!(parse_pgm(b"P5 2 2 1000\n\x00\x01\x02\x03\x04\x05\x06").is_err());
+        assert!(parse_pgm(b"P5 2 2").is_err());
+        // Sizes whose sample count, or its length in bytes, overflows
+        assert!(parse_pgm(b"P5 4294967296 4294967297 255\n\x00").is_err());
+        assert!(parse_pgm(b"P5 4294967296 2147483648 65535\n\x00").is_err());
+        // A single row or column doesn't make a surface
+        assert!(parse_pgm(b"P2 1 3 255\n0 1 2\n").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use crate::aabb::{make_aabb, Aabb};
+use crate::height_grid::HeightGrid;
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::{self, Interval};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::{hit_triangle, TriangleHit};
+use crate::vec3::{dot, make_point, unit_vector, Point3, Vec3};
+
+// Terrain over a rectangle in the xz plane: the grid's points spread evenly
+// across it, raised by their heights, with every cell split into two
+// triangles. Rays walk the cells they pass over in order (a 2D DDA),
+// skipping cells whose range of heights they pass above or below. Normals
+// are interpolated from normals at the grid points, and u and v run across
+// the rectangle in x and z.
+pub struct Heightfield {
+    grid: HeightGrid,
+    corner: Point3,
+    cell_x: f64,
+    cell_z: f64,
+    height_scale: f64,
+    // Shading normals at the grid points, stored like the heights
+    normals: Vec<Vec3>,
+    // Range of world heights within each cell, x varying fastest
+    cell_heights: Vec<Interval>,
+    mat: Box<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let Some(span) = self.bbox.clip(r, ray_t) else {
+            return false;
+        };
+        let (o, d) = (r.origin(), r.direction());
+        let cells_x = (self.grid.nx - 1) as i64;
+        let cells_z = (self.grid.nz - 1) as i64;
+
+        // The cell the ray starts in, and where it crosses into the next
+        // cell along each axis
+        let start = r.at(span.min);
+        let mut ix =
+            (((start.x() - self.corner.x()) / self.cell_x).floor() as i64).clamp(0, cells_x - 1);
+        let mut iz =
+            (((start.z() - self.corner.z()) / self.cell_z).floor() as i64).clamp(0, cells_z - 1);
+        let (step_x, delta_x, mut next_x) =
+            axis_steps(o.x(), d.x(), ix, self.corner.x(), self.cell_x);
+        let (step_z, delta_z, mut next_z) =
+            axis_steps(o.z(), d.z(), iz, self.corner.z(), self.cell_z);
+
+        let mut t = span.min;
+        loop {
+            let t_exit = next_x.min(next_z).min(span.max);
+            let heights = self.cell_heights[(iz * cells_x + ix) as usize];
+            let (y0, y1) = (o.y() + t * d.y(), o.y() + t_exit * d.y());
+            if y0.max(y1) >= heights.min && y0.min(y1) <= heights.max {
+                if let Some((corners, hit)) = self.hit_cell(r, ray_t, ix as usize, iz as usize) {
+                    self.fill_record(r, corners, &hit, rec);
+                    return true;
+                }
+            }
+            if t_exit >= span.max {
+                return false;
+            }
+            t = t_exit;
+            if next_x < next_z {
+                ix += step_x;
+                next_x += delta_x;
+            } else {
+                iz += step_z;
+                next_z += delta_z;
+            }
+            if ix < 0 || ix >= cells_x || iz < 0 || iz >= cells_z {
+                return false;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl Heightfield {
+    fn point(&self, x: usize, z: usize) -> Point3 {
+        self.corner
+            + Vec3(
+                x as f64 * self.cell_x,
+                self.height_scale * self.grid.height(x, z),
+                z as f64 * self.cell_z,
+            )
+    }
+
+    // The nearer of the cell's two triangles the ray hits, with the grid
+    // points at its corners
+    fn hit_cell(
+        &self,
+        r: &Ray,
+        ray_t: Interval,
+        x: usize,
+        z: usize,
+    ) -> Option<([(usize, usize); 3], TriangleHit)> {
+        let triangles = [
+            [(x, z), (x + 1, z), (x + 1, z + 1)],
+            [(x, z), (x + 1, z + 1), (x, z + 1)],
+        ];
+        let mut nearest: Option<([(usize, usize); 3], TriangleHit)> = None;
+        for corners in triangles {
+            let max = nearest.as_ref().map_or(ray_t.max, |(_, hit)| hit.t);
+            let points = corners.map(|(x, z)| self.point(x, z));
+            if let Some(hit) = hit_triangle(r, interval::new(ray_t.min, max), points) {
+                nearest = Some((corners, hit));
+            }
+        }
+        nearest
+    }
+
+    fn fill_record(
+        &self,
+        r: &Ray,
+        corners: [(usize, usize); 3],
+        hit: &TriangleHit,
+        rec: &mut HitRecord,
+    ) {
+        let normal = |(x, z): (usize, usize)| self.normals[z * self.grid.nx + x];
+        let shading_normal = unit_vector(
+            (1.0 - hit.b1 - hit.b2) * normal(corners[0])
+                + hit.b1 * normal(corners[1])
+                + hit.b2 * normal(corners[2]),
+        );
+        rec.t = hit.t;
+        rec.p = r.at(hit.t);
+        // Which side was hit comes from the triangle itself, and the
+        // smooth normal is turned to that side
+        let up = if hit.geometric_normal.y() < 0.0 {
+            -hit.geometric_normal
+        } else {
+            hit.geometric_normal
+        };
+        rec.front_face = dot(r.direction(), up) < 0.0;
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+        let width = self.cell_x * (self.grid.nx - 1) as f64;
+        let depth = self.cell_z * (self.grid.nz - 1) as f64;
+        rec.u = (rec.p.x() - self.corner.x()) / width;
+        rec.v = (rec.p.z() - self.corner.z()) / depth;
+        rec.mat = self.mat.clone();
+        rec.clear_extras();
+        rec.tangent = Vec3(1.0, 0.0, 0.0);
+    }
+}
+
+// Direction of cell steps along an axis, the distance along the ray between
+// cell boundaries, and where it crosses the first one
+fn axis_steps(origin: f64, direction: f64, cell: i64, start: f64, size: f64) -> (i64, f64, f64) {
+    if direction == 0.0 {
+        return (0, f64::INFINITY, f64::INFINITY);
+    }
+    let step = if direction > 0.0 { 1 } else { -1 };
+    let boundary = start + (cell + (step + 1) / 2) as f64 * size;
+    (
+        step,
+        size / direction.abs(),
+        (boundary - origin) / direction,
+    )
+}
+
+// Heightfield over the rectangle from `corner` spanning width in x and
+// depth in z, with heights of 1 raised height_scale above it. The grid
+// needs at least two points along each side.
+pub fn make_heightfield(
+    grid: HeightGrid,
+    corner: Point3,
+    width: f64,
+    depth: f64,
+    height_scale: f64,
+    mat: Box<dyn Material>,
+) -> Result<Heightfield, String> {
+    let (nx, nz) = (grid.nx, grid.nz);
+    if nx < 2 || nz < 2 || grid.values.len() != nx * nz {
+        return Err(format!(
+            "A {nx} by {nz} height grid can't make a heightfield"
+        ));
+    }
+    let cell_x = width / (nx - 1) as f64;
+    let cell_z = depth / (nz - 1) as f64;
+
+    // Central differences, one sided at the edges
+    let mut normals = Vec::with_capacity(nx * nz);
+    let mut z = 0;
+    while z < nz {
+        let mut x = 0;
+        while x < nx {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(nx - 1));
+            let (z0, z1) = (z.saturating_sub(1), (z + 1).min(nz - 1));
+            let slope_x = height_scale * (grid.height(x1, z) - grid.height(x0, z))
+                / ((x1 - x0) as f64 * cell_x);
+            let slope_z = height_scale * (grid.height(x, z1) - grid.height(x, z0))
+                / ((z1 - z0) as f64 * cell_z);
+            normals.push(unit_vector(Vec3(-slope_x, 1.0, -slope_z)));
+            x += 1;
+        }
+        z += 1;
+    }
+
+    let mut cell_heights = Vec::with_capacity((nx - 1) * (nz - 1));
+    let mut z = 0;
+    while z < nz - 1 {
+        let mut x = 0;
+        while x < nx - 1 {
+            let heights = [
+                grid.height(x, z),
+                grid.height(x + 1, z),
+                grid.height(x, z + 1),
+                grid.height(x + 1, z + 1),
+            ];
+            let low = heights.iter().fold(f64::INFINITY, |a, b| a.min(*b));
+            let high = heights.iter().fold(-f64::INFINITY, |a, b| a.max(*b));
+            cell_heights.push(interval::new(
+                corner.y() + height_scale * low,
+                corner.y() + height_scale * high,
+            ));
+            x += 1;
+        }
+        z += 1;
+    }
+
+    // Padded so that flat terrain still has a box rays can hit
+    let low = cell_heights.iter().fold(f64::INFINITY, |a, b| a.min(b.min));
+    let high = cell_heights
+        .iter()
+        .fold(-f64::INFINITY, |a, b| a.max(b.max));
+    let bbox = make_aabb(
+        make_point(corner.x(), low - 1e-4, corner.z()),
+        make_point(corner.x() + width, high + 1e-4, corner.z() + depth),
+    );
+
+    Ok(Heightfield {
+        grid,
+        corner,
+        cell_x,
+        cell_z,
+        height_scale,
+        normals,
+        cell_heights,
+        mat,
+        bbox,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_heightfield;
+    use crate::color::make_color;
+    use crate::height_grid::HeightGrid;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::{dot, make_point, unit_vector, Vec3};
+
+    #[test]
+    fn it_matches_a_sloped_plane() {
+        // Rising by half a unit per unit of x over a 4 by 4 square
+        let n = 9;
+        let mut values = Vec::new();
+        let mut z = 0;
+        while z < n {
+            let mut x = 0;
+            while x < n {
+                values.push(x as f64 / (n - 1) as f64);
+                x += 1;
+            }
+            z += 1;
+        }
+        let terrain = make_heightfield(
+            HeightGrid {
+                nx: n,
+                nz: n,
+                values,
+            },
+            make_point(-2.0, 0.0, -2.0),
+            4.0,
+            4.0,
+            2.0,
+            Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+        )
+        .unwrap();
+        // The plane y = (x + 2) / 2
+        let normal = unit_vector(Vec3(-0.5, 1.0, 0.0));
+
+        let mut i = 0;
+        while i < 50 {
+            let a = i as f64 * 0.37;
+            let r = Ray {
+                origin: make_point(a.cos() * 3.0, 4.0, a.sin() * 3.0),
+                direction: Vec3(-a.cos() * (0.5 + 0.01 * i as f64), -1.5, -a.sin()),
+            };
+            let mut rec = HitRecord::default();
+            let hit = terrain.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec);
+
+            // Where the ray meets the infinite plane
+            let o = r.origin();
+            let d = r.direction();
+            let t = (1.0 + 0.5 * o.x() - o.y()) / (d.y() - 0.5 * d.x());
+            let p = r.at(t);
+            let inside = p.x().abs() <= 2.0 && p.z().abs() <= 2.0;
+            assert_eq!(hit, inside);
+            if hit {
+                assert!((rec.t - t).abs() < 1e-9);
+                assert!(rec.front_face);
+                assert!(dot(rec.normal, normal) > 1.0 - 1e-9);
+                assert!((rec.u - (p.x() + 2.0) / 4.0).abs() < 1e-9);
+            }
+            i += 1;
+        }
+    }
+
+    #[test]
+    fn it_needs_two_points_along_each_side() {
+        let make = |nx: usize, nz: usize| {
+            make_heightfield(
+                HeightGrid {
+                    nx,
+                    nz,
+                    values: vec![0.5; nx * nz],
+                },
+                make_point(0.0, 0.0, 0.0),
+                1.0,
+                1.0,
+                1.0,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )
+        };
+        assert!(make(1, 4).is_err());
+        assert!(make(4, 1).is_err());
+        assert!(make(2, 2).is_ok());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    color::make_color,
-    material::{make_lambertian, Material},
+    color::{make_color, Color},
+    material::{make_lambertian, Material, TransportMode},
     ray, vec3,
 };
 
@@ -8,8 +8,21 @@ pub struct HitRecord {
     pub p: vec3::Point3,
     pub normal: vec3::Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    // Direction along the surface in which u grows, which orients fibers and
+    // anisotropic highlights; zero on shapes without one
+    pub tangent: vec3::Vec3,
+    // Color the shape itself gives the point, such as a mesh's vertex
+    // colors, multiplying the diffuse color of materials; white elsewhere
+    pub color: Color,
     pub mat: Box<dyn Material>,
+    // Wavelength in nm of the light the path carries in spectral rendering,
+    // set by the integrator after the hit
+    pub wavelength: Option<f64>,
+    // What the path carries, set by the integrator after the hit
+    pub transport: TransportMode,
 }
 
 impl Default for HitRecord {
@@ -18,13 +31,26 @@ impl Default for HitRecord {
             p: vec3::zero_vector(),
             normal: vec3::zero_vector(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
+            tangent: vec3::zero_vector(),
+            color: make_color(1.0, 1.0, 1.0),
             mat: Box::new(make_lambertian(make_color(0.0, 0.0, 0.0))),
+            wavelength: None,
+            transport: TransportMode::Radiance,
         }
     }
 }
 
 impl HitRecord {
+    // Resets what only some shapes report, so nothing is left over from
+    // another shape tested along the same ray
+    pub fn clear_extras(&mut self) {
+        self.tangent = vec3::zero_vector();
+        self.color = make_color(1.0, 1.0, 1.0);
+    }
+
     pub fn set_face_normal(&mut self, r: &ray::Ray, outward_normal: vec3::Vec3) {
         self.front_face = vec3::dot(r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face {
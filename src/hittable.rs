@@ -1,7 +1,25 @@
+use crate::aabb::Aabb;
 use crate::hit_record::HitRecord;
 use crate::interval::Interval;
 use crate::ray::Ray;
 
 pub trait Hittable {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // Every stretch of the whole line through r that lies inside the shape,
+    // in order along the ray, including ones behind its origin. Only closed
+    // shapes have an inside; others report none, and so can't take part in
+    // CSG.
+    fn intervals(&self, _r: &Ray) -> Vec<Span> {
+        Vec::new()
+    }
+}
+
+// Where a ray enters and leaves the inside of a shape. The records' normals
+// face against the ray as usual.
+pub struct Span {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
 }
@@ -1,3 +1,6 @@
+use crate::aabb::{surrounding_box, Aabb};
+use crate::csg::{combine, CsgOperation};
+use crate::hittable::Span;
 use crate::interval;
 use crate::{hit_record::HitRecord, hittable::Hittable, ray::Ray};
 pub struct HittableList {
@@ -11,7 +14,7 @@ impl Hittable for HittableList {
         let mut closest_so_far = ray_t.max;
 
         for object in self.objects.iter() {
-            if object.hit(&r, interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+            if object.hit(r, interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
             }
@@ -20,9 +23,23 @@ impl Hittable for HittableList {
         *rec = temp_rec;
         hit_anything
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::default(), |bbox, object| {
+            surrounding_box(&bbox, &object.bounding_box())
+        })
+    }
+
+    // A list is the union of its objects
+    fn intervals(&self, r: &Ray) -> Vec<Span> {
+        self.objects.iter().fold(Vec::new(), |spans, object| {
+            combine(CsgOperation::Union, spans, object.intervals(r))
+        })
+    }
 }
 
 impl HittableList {
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.objects.clear();
     }
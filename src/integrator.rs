@@ -0,0 +1,471 @@
+use crate::aabb::{make_aabb, surrounding_box};
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::color::{make_color, Color};
+use crate::film::Film;
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval;
+use crate::light::LightList;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::{at_wavelength, sample_wavelength, wavelength_to_rgb};
+use crate::vec3::{unit_vector, zero_vector, Point3};
+
+// Everything light transport needs to know about the scene
+pub struct Scene<'a> {
+    pub world: &'a dyn Hittable,
+    pub lights: &'a LightList,
+    pub background: &'a dyn Background,
+    pub camera: &'a Camera,
+}
+
+impl Scene<'_> {
+    // Sphere around everything the camera sees, and the camera itself, that
+    // methods tracing paths from infinitely far away start them on
+    pub fn bounding_sphere(&self) -> (Point3, f64) {
+        let eye = make_aabb(self.camera.lookfrom, self.camera.lookfrom);
+        surrounding_box(&self.world.bounding_box(), &eye).bounding_sphere()
+    }
+}
+
+// Computes the radiance arriving along a camera ray. Paths are cut off after
+// max_depth segments. Integrators that also trace paths from the lights add
+// what those paths bring to other pixels to the film as splats.
+//
+// The image is rendered in passes of one sample per pixel, and start_pass is
+// called before each of them with its number, counting from 0.
+pub trait Integrator {
+    fn start_pass(&self, _scene: &Scene, _pass: i32, _max_depth: i32) {}
+
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        film: &mut Film,
+    ) -> Color;
+}
+
+// The first book's path tracer: follows the BSDF's own samples and only
+// picks up light that a path happens to hit. Delta lights are never seen.
+pub struct SimplePathIntegrator {
+    russian_roulette_depth: i32,
+    // Trace each camera sample at one wavelength instead of in RGB
+    pub spectral: bool,
+}
+
+impl Integrator for SimplePathIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        spectral_sample(self.spectral, sampler, |wavelength, sampler| {
+            self.trace(r, scene, max_depth, wavelength, sampler)
+        })
+    }
+}
+
+impl SimplePathIntegrator {
+    fn trace(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        wavelength: Option<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut color = make_color(0.0, 0.0, 0.0);
+        let mut throughput = make_color(1.0, 1.0, 1.0);
+        let mut ray = r;
+        let mut depth = 0;
+        while depth < max_depth {
+            let mut rec = HitRecord::default();
+            if !scene
+                .world
+                .hit(&ray, interval::new(0.001, f64::INFINITY), &mut rec)
+            {
+                color +=
+                    throughput * at_wavelength(scene.background.value(ray.direction()), wavelength);
+                break;
+            }
+            rec.wavelength = wavelength;
+            color += throughput * at_wavelength(rec.mat.emitted(&rec), wavelength);
+
+            let Some((scattered, attenuation)) = scatter(&ray, &rec, sampler) else {
+                break;
+            };
+            throughput *= at_wavelength(attenuation, wavelength);
+            ray = scattered;
+            depth += 1;
+            if !russian_roulette(&mut throughput, depth, self.russian_roulette_depth, sampler) {
+                break;
+            }
+        }
+        color
+    }
+}
+
+pub fn make_simple_path_integrator(russian_roulette_depth: i32) -> SimplePathIntegrator {
+    SimplePathIntegrator {
+        russian_roulette_depth,
+        spectral: false,
+    }
+}
+
+// Path tracer with next event estimation. At every bounce the lights and
+// the background are sampled directly, and rays picked by the BSDF that
+// happen to reach an area light or the background count as well; the two
+// estimates are combined with the power heuristic.
+pub struct PathIntegrator {
+    russian_roulette_depth: i32,
+    // Trace each camera sample at one wavelength instead of in RGB
+    pub spectral: bool,
+}
+
+impl Integrator for PathIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        spectral_sample(self.spectral, sampler, |wavelength, sampler| {
+            let (color, _) = trace_path(
+                r,
+                scene,
+                max_depth,
+                self.russian_roulette_depth,
+                false,
+                wavelength,
+                sampler,
+            );
+            color
+        })
+    }
+}
+
+pub fn make_path_integrator(russian_roulette_depth: i32) -> PathIntegrator {
+    PathIntegrator {
+        russian_roulette_depth,
+        spectral: false,
+    }
+}
+
+// Light reaching the first diffuse or glossy surface straight from the light
+// sources, seen directly or through a chain of mirrors and glass
+pub struct DirectLightingIntegrator {}
+
+impl Integrator for DirectLightingIntegrator {
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        let (color, _) = trace_path(r, scene, max_depth, i32::MAX, true, None, sampler);
+        color
+    }
+}
+
+pub fn make_direct_lighting_integrator() -> DirectLightingIntegrator {
+    DirectLightingIntegrator {}
+}
+
+// The MIS path tracer loop, returning the radiance and the number of
+// bounces the path made. With direct_only set, paths end once the first
+// non-specular bounce has been checked for light. Given a wavelength, the
+// path carries light of that wavelength only and returns it as gray.
+pub fn trace_path(
+    r: Ray,
+    scene: &Scene,
+    max_depth: i32,
+    russian_roulette_depth: i32,
+    direct_only: bool,
+    wavelength: Option<f64>,
+    sampler: &mut dyn Sampler,
+) -> (Color, i32) {
+    let mut color = make_color(0.0, 0.0, 0.0);
+    let mut throughput = make_color(1.0, 1.0, 1.0);
+    let mut ray = r;
+    // Density with which the last bounce picked `ray`. Zero for camera rays
+    // and specular bounces, which light sampling can't reproduce.
+    let mut scattering_pdf = 0.0;
+    let mut last_bounce = false;
+    let mut depth = 0;
+    while depth < max_depth {
+        let mut rec = HitRecord::default();
+        if !scene
+            .world
+            .hit(&ray, interval::new(0.001, f64::INFINITY), &mut rec)
+        {
+            let direction = unit_vector(ray.direction());
+            let weight = if scattering_pdf > 0.0 {
+                power_heuristic(scattering_pdf, scene.background.pdf(direction))
+            } else {
+                1.0
+            };
+            color +=
+                weight * throughput * at_wavelength(scene.background.value(direction), wavelength);
+            break;
+        }
+        rec.wavelength = wavelength;
+
+        let emitted = at_wavelength(rec.mat.emitted(&rec), wavelength);
+        if !emitted.near_zero() {
+            let weight = if scattering_pdf > 0.0 {
+                let light_pdf = scene
+                    .lights
+                    .lights
+                    .iter()
+                    .map(|light| light.pdf_li(ray.origin(), rec.p))
+                    .sum();
+                power_heuristic(scattering_pdf, light_pdf)
+            } else {
+                1.0
+            };
+            color += weight * throughput * emitted;
+        }
+        if last_bounce {
+            break;
+        }
+
+        color += throughput * direct_light(&ray, &rec, scene, wavelength, sampler);
+
+        let Some((scattered, attenuation)) = scatter(&ray, &rec, sampler) else {
+            break;
+        };
+        scattering_pdf = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+        last_bounce = direct_only && scattering_pdf > 0.0;
+        throughput *= at_wavelength(attenuation, wavelength);
+        ray = scattered;
+        depth += 1;
+        if !russian_roulette(&mut throughput, depth, russian_roulette_depth, sampler) {
+            break;
+        }
+    }
+    (color, depth)
+}
+
+// Runs trace for one camera sample. In spectral mode it picks the sample's
+// wavelength and turns the gray radiance trace finds at it into RGB.
+fn spectral_sample(
+    spectral: bool,
+    sampler: &mut dyn Sampler,
+    trace: impl FnOnce(Option<f64>, &mut dyn Sampler) -> Color,
+) -> Color {
+    if !spectral {
+        return trace(None, sampler);
+    }
+    let (wavelength, pdf) = sample_wavelength(sampler.get_1d());
+    let radiance = trace(Some(wavelength), sampler);
+    radiance.x() * wavelength_to_rgb(wavelength) / pdf
+}
+
+// The material's scattered ray and attenuation, none when it absorbs
+pub fn scatter(r: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Ray, Color)> {
+    let mut scattered = Ray {
+        origin: zero_vector(),
+        direction: zero_vector(),
+    };
+    let mut attenuation = make_color(0.0, 0.0, 0.0);
+    if rec
+        .mat
+        .scatter(*r, rec, &mut attenuation, &mut scattered, sampler)
+    {
+        Some((scattered, attenuation))
+    } else {
+        None
+    }
+}
+
+// Randomly ends paths that carry little energy past start_depth, boosting
+// the survivors to make up for the ones that were cut. False when the path
+// should stop. Paths whose throughput stays at one or more, such as those
+// bouncing between clear glass, are never cut here, so the max_depth cap is
+// kept on purpose as a backstop; it biases only paths that long.
+fn russian_roulette(
+    throughput: &mut Color,
+    depth: i32,
+    start_depth: i32,
+    sampler: &mut dyn Sampler,
+) -> bool {
+    if depth < start_depth {
+        return true;
+    }
+    let max_component = throughput.x().max(throughput.y()).max(throughput.z());
+    if max_component >= 1.0 {
+        return true;
+    }
+    let q = (1.0 - max_component).max(0.0);
+    if sampler.get_1d() < q {
+        return false;
+    }
+    *throughput /= 1.0 - q;
+    true
+}
+
+// Next event estimation: light from each of the scene's lights and from
+// the background that reaches the hit point unblocked. Only surfaces that
+// can evaluate their BSDF pick anything up.
+fn direct_light(
+    r: &Ray,
+    rec: &HitRecord,
+    scene: &Scene,
+    wavelength: Option<f64>,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let mut direct = make_color(0.0, 0.0, 0.0);
+    for light in scene.lights.lights.iter() {
+        let u = sampler.get_2d();
+        let Some(sample) = light.sample_li(rec.p, u) else {
+            continue;
+        };
+        let shadow_ray = Ray {
+            origin: rec.p,
+            direction: sample.wi,
+        };
+        let f = at_wavelength(rec.mat.eval(r, rec, &shadow_ray), wavelength);
+        if f.near_zero() || occluded(&shadow_ray, sample.distance, scene.world) {
+            continue;
+        }
+        // Rays never hit delta lights, so only area lights share the
+        // estimate with BSDF sampling
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(sample.pdf, rec.mat.scattering_pdf(r, rec, &shadow_ray))
+        };
+        direct += weight * f * at_wavelength(sample.radiance, wavelength) / sample.pdf;
+    }
+
+    let u = sampler.get_2d();
+    if let Some(wi) = scene.background.sample(u) {
+        let light_pdf = scene.background.pdf(wi);
+        let shadow_ray = Ray {
+            origin: rec.p,
+            direction: wi,
+        };
+        let f = at_wavelength(rec.mat.eval(r, rec, &shadow_ray), wavelength);
+        if light_pdf > 0.0 && !f.near_zero() && !occluded(&shadow_ray, f64::INFINITY, scene.world) {
+            let weight = power_heuristic(light_pdf, rec.mat.scattering_pdf(r, rec, &shadow_ray));
+            let radiance = at_wavelength(scene.background.value(wi), wavelength);
+            direct += weight * f * radiance / light_pdf;
+        }
+    }
+    direct
+}
+
+// Whether anything blocks the ray before it has travelled `distance`
+pub fn occluded(shadow_ray: &Ray, distance: f64, world: &dyn Hittable) -> bool {
+    let mut rec = HitRecord::default();
+    world.hit(
+        shadow_ray,
+        interval::new(0.001, distance * (1.0 - 1e-4)),
+        &mut rec,
+    )
+}
+
+// Veach's power heuristic (beta = 2) weight for a sample drawn with density
+// f_pdf when g_pdf is the density of the other strategy
+fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    if f_pdf + g_pdf == 0.0 {
+        return 0.0;
+    }
+    if f_pdf.is_infinite() && g_pdf.is_infinite() {
+        return 0.5;
+    }
+    // Written in terms of the smaller density over the larger one, so huge
+    // or infinite densities give 0 or 1 rather than inf / inf
+    if f_pdf >= g_pdf {
+        let r = g_pdf / f_pdf;
+        1.0 / (1.0 + r * r)
+    } else {
+        let r = f_pdf / g_pdf;
+        r * r / (1.0 + r * r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::make_gradient_sky;
+    use crate::film::make_film;
+    use crate::light::{make_light_list, make_point_light};
+    use crate::material::make_lambertian;
+    use crate::plane::make_plane;
+    use crate::rtweekend::PI;
+    use crate::sampler::make_stratified_sampler;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn it_weights_infinite_densities_without_nan() {
+        assert_eq!(power_heuristic(f64::INFINITY, 3.0), 1.0);
+        assert_eq!(power_heuristic(3.0, f64::INFINITY), 0.0);
+        assert_eq!(power_heuristic(1e200, 1e-200), 1.0);
+        assert!((power_heuristic(1.0, 3.0) - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn it_keeps_the_expected_throughput_through_roulette() {
+        let mut sampler = make_stratified_sampler(true);
+        let start = make_color(0.3, 0.2, 0.05);
+        let n = 1000;
+        let mut sum = make_color(0.0, 0.0, 0.0);
+        let mut survivors = 0;
+        for s in 0..n {
+            sampler.start_pixel_sample(0, 0, s, n);
+            let mut throughput = start;
+            if russian_roulette(&mut throughput, 5, 3, &mut sampler) {
+                sum += throughput;
+                survivors += 1;
+            }
+        }
+        // Only about the brightest channel's share of the paths go on
+        assert!((survivors as f64 / n as f64 - 0.3).abs() < 0.01);
+        assert!((sum / n as f64 - start).length() < 1e-2 * start.length());
+    }
+
+    #[test]
+    fn it_lights_a_diffuse_plane_from_a_point_light() {
+        let plane = make_plane(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+        );
+        let mut lights = make_light_list();
+        lights.add(Box::new(make_point_light(
+            Vec3(0.0, 2.0, 0.0),
+            make_color(4.0, 4.0, 4.0),
+        )));
+        let background = make_gradient_sky(make_color(0.0, 0.0, 0.0), make_color(0.0, 0.0, 0.0));
+        let camera = Camera::default();
+        let scene = Scene {
+            world: &plane,
+            lights: &lights,
+            background: &background,
+            camera: &camera,
+        };
+        let mut film = make_film(1, 1);
+        let mut sampler = make_stratified_sampler(true);
+        sampler.start_pixel_sample(0, 0, 0, 1);
+        // Looking straight down at the point under the light, which arrives
+        // head on from 2 units away: albedo / pi * I / d^2
+        let r = Ray {
+            origin: Vec3(0.0, 1.0, 0.0),
+            direction: Vec3(0.0, -1.0, 0.0),
+        };
+        let color = make_direct_lighting_integrator().li(r, &scene, 10, &mut sampler, &mut film);
+        let expected = 0.5 / PI * 4.0 / 4.0;
+        assert!((color - make_color(expected, expected, expected)).length() < 1e-9);
+    }
+}
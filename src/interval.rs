@@ -1,15 +1,15 @@
-use std::f64::INFINITY;
-
 const EMPTY: Interval = Interval {
-    min: INFINITY,
-    max: -INFINITY,
+    min: f64::INFINITY,
+    max: -f64::INFINITY,
 };
 
+#[allow(dead_code)]
 const UNIVERSE: Interval = Interval {
-    min: -INFINITY,
-    max: INFINITY,
+    min: -f64::INFINITY,
+    max: f64::INFINITY,
 };
 
+#[derive(Clone, Copy)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -17,14 +17,12 @@ pub struct Interval {
 
 impl Default for Interval {
     fn default() -> Self {
-        Self {
-            min: INFINITY,
-            max: -INFINITY,
-        }
+        EMPTY
     }
 }
 
 impl Interval {
+    #[allow(dead_code)]
     pub fn contains(&self, x: f64) -> bool {
         self.min <= x && x <= self.max
     }
@@ -33,6 +31,10 @@ impl Interval {
         self.min < x && x < self.max
     }
 
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min {
             return self.min;
@@ -47,3 +49,11 @@ impl Interval {
 pub fn new(min: f64, max: f64) -> Interval {
     Interval { min, max }
 }
+
+// Smallest interval containing both a and b
+pub fn union(a: Interval, b: Interval) -> Interval {
+    Interval {
+        min: a.min.min(b.min),
+        max: a.max.max(b.max),
+    }
+}
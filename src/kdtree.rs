@@ -0,0 +1,141 @@
+use crate::vec3::Point3;
+
+// Anything stored in a kd-tree, located by a single point
+pub trait KdItem {
+    fn position(&self) -> Point3;
+}
+
+// Balanced kd-tree kept implicitly in one array: every range of items has
+// its median in the middle, smaller coordinates on the split axis to the
+// left and larger ones to the right. Nothing is stored besides the items
+// and one split axis per item.
+pub struct KdTree<T: KdItem> {
+    items: Vec<T>,
+    axes: Vec<u8>,
+}
+
+impl<T: KdItem> KdTree<T> {
+    // Calls f with every item within `radius` of p
+    pub fn for_each_within(&self, p: Point3, radius: f64, f: &mut dyn FnMut(&T)) {
+        self.visit(0, self.items.len(), p, radius * radius, f);
+    }
+
+    fn visit(
+        &self,
+        start: usize,
+        end: usize,
+        p: Point3,
+        radius_squared: f64,
+        f: &mut dyn FnMut(&T),
+    ) {
+        if start >= end {
+            return;
+        }
+        let middle = start + (end - start) / 2;
+        let item = &self.items[middle];
+        let position = item.position();
+        if (position - p).length_squared() <= radius_squared {
+            f(item);
+        }
+        let axis = self.axes[middle] as i32;
+        let offset = p[axis] - position[axis];
+        // Visit the side p is on first, and the other only when the sphere
+        // reaches across the splitting plane
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        self.visit(near.0, near.1, p, radius_squared, f);
+        if offset * offset <= radius_squared {
+            self.visit(far.0, far.1, p, radius_squared, f);
+        }
+    }
+}
+
+pub fn make_kd_tree<T: KdItem>(items: Vec<T>) -> KdTree<T> {
+    let mut items = items;
+    let mut axes = vec![0; items.len()];
+    build(&mut items, &mut axes);
+    KdTree { items, axes }
+}
+
+fn build<T: KdItem>(items: &mut [T], axes: &mut [u8]) {
+    if items.is_empty() {
+        return;
+    }
+    let axis = widest_axis(items);
+    let middle = items.len() / 2;
+    items.select_nth_unstable_by(middle, |a, b| {
+        a.position()[axis].total_cmp(&b.position()[axis])
+    });
+    axes[middle] = axis as u8;
+    let (left_items, rest) = items.split_at_mut(middle);
+    let (left_axes, rest_axes) = axes.split_at_mut(middle);
+    build(left_items, left_axes);
+    build(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+fn widest_axis<T: KdItem>(items: &[T]) -> i32 {
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for item in items.iter() {
+        let p = item.position();
+        let mut axis = 0;
+        while axis < 3 {
+            min[axis] = min[axis].min(p[axis as i32]);
+            max[axis] = max[axis].max(p[axis as i32]);
+            axis += 1;
+        }
+    }
+    let extent = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+    if extent[0] > extent[1] && extent[0] > extent[2] {
+        0
+    } else if extent[1] > extent[2] {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_kd_tree, KdItem};
+    use crate::rtweekend::random_f64;
+    use crate::vec3::{make_point, Point3};
+
+    struct Item(Point3);
+
+    impl KdItem for Item {
+        fn position(&self) -> Point3 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn it_finds_the_same_items_as_a_linear_scan() {
+        let points: Vec<Point3> = (0..500)
+            .map(|_| make_point(random_f64(), 4.0 * random_f64(), random_f64()))
+            .collect();
+        let tree = make_kd_tree(points.iter().map(|p| Item(*p)).collect());
+
+        let mut query = 0;
+        while query < 50 {
+            let p = make_point(random_f64(), 4.0 * random_f64(), random_f64());
+            let radius = 0.3 * random_f64();
+            let mut expected = points
+                .iter()
+                .filter(|q| (**q - p).length_squared() <= radius * radius)
+                .map(|q| (q.x(), q.y(), q.z()))
+                .collect::<Vec<_>>();
+            let mut found = Vec::new();
+            tree.for_each_within(p, radius, &mut |item: &Item| {
+                found.push((item.0.x(), item.0.y(), item.0.z()))
+            });
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert!(expected == found);
+            query += 1;
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use crate::color::Color;
+use crate::onb::make_onb;
+use crate::ray::Ray;
+use crate::rtweekend::PI;
+use crate::vec3::{
+    dot, sample_unit_disk, sample_unit_sphere, unit_vector, zero_vector, Point3, Vec3,
+};
+
+// Light arriving at a point from a light source
+pub struct LightSample {
+    // Unit direction from the receiving point towards the light
+    pub wi: Vec3,
+    // Distance to the light, infinite for directional lights
+    pub distance: f64,
+    // Incident radiance. For delta lights it is already integrated over the
+    // light's (zero) extent.
+    pub radiance: Color,
+    // Solid angle density of wi, 1 for delta lights
+    pub pdf: f64,
+    // Surface normal at the sampled point, zero for delta lights
+    pub normal: Vec3,
+}
+
+// A ray leaving a light, for methods that trace paths from the lights
+pub struct LightEmission {
+    pub ray: Ray,
+    // Surface normal at the ray's origin, or its direction for lights
+    // without a surface
+    pub normal: Vec3,
+    pub radiance: Color,
+    // Area density of the origin and solid angle density of the direction
+    pub pdf_pos: f64,
+    pub pdf_dir: f64,
+}
+
+// Light sources sampled by casting shadow rays towards them (next event
+// estimation). Delta lights have no geometry, so this is the only way they
+// contribute; area lights also sit in the world as emissive objects.
+pub trait Light {
+    fn sample_li(&self, p: Point3, u: (f64, f64)) -> Option<LightSample>;
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    // Whether the light sits infinitely far away, outside the scene
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    // Density with which sample_li from p would have picked the direction
+    // towards `hit`, a point found on the light by tracing a ray
+    fn pdf_li(&self, _p: Point3, _hit: Point3) -> f64 {
+        0.0
+    }
+
+    // Whether p lies on the light's surface
+    fn on_surface(&self, _p: Point3) -> bool {
+        false
+    }
+
+    // Picks a ray leaving the light. Lights infinitely far away start their
+    // rays on a disk just outside the sphere bounding the scene.
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        scene_center: Point3,
+        scene_radius: f64,
+    ) -> Option<LightEmission>;
+
+    // Densities with which sample_le picks the ray, see LightEmission
+    fn pdf_le(&self, r: &Ray, normal: Vec3, scene_radius: f64) -> (f64, f64);
+}
+
+pub struct LightList {
+    pub lights: Vec<Box<dyn Light>>,
+}
+
+impl LightList {
+    pub fn add(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light)
+    }
+}
+
+pub fn make_light_list() -> LightList {
+    LightList { lights: Vec::new() }
+}
+
+// Emits intensity equally in all directions, falling off with distance squared
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            wi: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+            pdf: 1.0,
+            normal: zero_vector(),
+        })
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        _scene_center: Point3,
+        _scene_radius: f64,
+    ) -> Option<LightEmission> {
+        let direction = sample_unit_sphere(u1);
+        Some(LightEmission {
+            ray: Ray {
+                origin: self.position,
+                direction,
+            },
+            normal: direction,
+            radiance: self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (4.0 * PI),
+        })
+    }
+
+    fn pdf_le(&self, _r: &Ray, _normal: Vec3, _scene_radius: f64) -> (f64, f64) {
+        (0.0, 1.0 / (4.0 * PI))
+    }
+}
+
+pub fn make_point_light(position: Point3, intensity: Color) -> PointLight {
+    PointLight {
+        position,
+        intensity,
+    }
+}
+
+// Point light restricted to a cone. Full intensity inside falloff_start
+// degrees from the axis, smoothly fading to nothing at total_width degrees.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_falloff_start: f64,
+    cos_total_width: f64,
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let wi = to_light / distance;
+        let falloff = self.falloff(dot(-wi, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            distance,
+            radiance: falloff * self.intensity / distance_squared,
+            pdf: 1.0,
+            normal: zero_vector(),
+        })
+    }
+
+    // Directions are spread uniformly over the cone of total_width
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        _scene_center: Point3,
+        _scene_radius: f64,
+    ) -> Option<LightEmission> {
+        let cos_theta = 1.0 - u1.0 * (1.0 - self.cos_total_width);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u1.1;
+        let direction = make_onb(self.direction).local(Vec3(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        Some(LightEmission {
+            ray: Ray {
+                origin: self.position,
+                direction,
+            },
+            normal: direction,
+            radiance: self.falloff(cos_theta) * self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: cone_pdf(1.0 - self.cos_total_width),
+        })
+    }
+
+    fn pdf_le(&self, r: &Ray, _normal: Vec3, _scene_radius: f64) -> (f64, f64) {
+        let inside = dot(unit_vector(r.direction()), self.direction) >= self.cos_total_width;
+        (
+            0.0,
+            if inside {
+                cone_pdf(1.0 - self.cos_total_width)
+            } else {
+                0.0
+            },
+        )
+    }
+}
+
+impl SpotLight {
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        // A spot without a soft rim cuts off sharply at its edge
+        if self.cos_falloff_start <= self.cos_total_width {
+            return if cos_theta >= self.cos_total_width {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let t = ((cos_theta - self.cos_total_width)
+            / (self.cos_falloff_start - self.cos_total_width))
+            .clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// A falloff starting outside the cone is taken to start at its edge, which
+// gives a hard-edged spot
+pub fn make_spot_light(
+    position: Point3,
+    target: Point3,
+    intensity: Color,
+    falloff_start: f64,
+    total_width: f64,
+) -> SpotLight {
+    let falloff_start = falloff_start.clamp(0.0, total_width.max(0.0));
+    SpotLight {
+        position,
+        direction: unit_vector(target - position),
+        intensity,
+        cos_falloff_start: falloff_start.to_radians().cos(),
+        cos_total_width: total_width.to_radians().cos(),
+    }
+}
+
+// Light from infinitely far away arriving from a single direction, such as
+// sunlight. Irradiance is measured on a surface facing the light.
+pub struct DirectionalLight {
+    to_light: Vec3,
+    irradiance: Color,
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3, _u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample {
+            wi: self.to_light,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+            normal: zero_vector(),
+        })
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        _u2: (f64, f64),
+        scene_center: Point3,
+        scene_radius: f64,
+    ) -> Option<LightEmission> {
+        let direction = -self.to_light;
+        let frame = make_onb(direction);
+        let disk = sample_unit_disk(u1);
+        let on_disk = scene_center + scene_radius * frame.local(Vec3(disk.x(), disk.y(), 0.0));
+        Some(LightEmission {
+            ray: Ray {
+                origin: on_disk - scene_radius * direction,
+                direction,
+            },
+            normal: direction,
+            radiance: self.irradiance,
+            pdf_pos: 1.0 / (PI * scene_radius * scene_radius),
+            pdf_dir: 1.0,
+        })
+    }
+
+    fn pdf_le(&self, _r: &Ray, _normal: Vec3, scene_radius: f64) -> (f64, f64) {
+        (1.0 / (PI * scene_radius * scene_radius), 0.0)
+    }
+}
+
+// direction is the way the light travels
+pub fn make_directional_light(direction: Vec3, irradiance: Color) -> DirectionalLight {
+    DirectionalLight {
+        to_light: -unit_vector(direction),
+        irradiance,
+    }
+}
+
+// Spherical area light, sampled uniformly over the cone it subtends. Pair it
+// with a sphere of the same size carrying a matching DiffuseLight in the world.
+pub struct SphereLight {
+    center: Point3,
+    radius: f64,
+    radiance: Color,
+}
+
+impl Light for SphereLight {
+    fn sample_li(&self, p: Point3, u: (f64, f64)) -> Option<LightSample> {
+        let to_center = self.center - p;
+        let cone = self.cone_extent(p)?;
+        // sin^2 = (1 - cos)(1 + cos) keeps precision for tiny cones
+        let one_minus_cos = u.0 * cone;
+        let cos_theta = 1.0 - one_minus_cos;
+        let sin_theta = (one_minus_cos * (2.0 - one_minus_cos)).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        let wi = make_onb(unit_vector(to_center)).local(Vec3(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        // Nearest intersection of the sampled direction with the sphere
+        let b = dot(wi, to_center);
+        let c = to_center.length_squared() - self.radius * self.radius;
+        let distance = b - (b * b - c).max(0.0).sqrt();
+        Some(LightSample {
+            wi,
+            distance,
+            radiance: self.radiance,
+            pdf: cone_pdf(cone),
+            normal: unit_vector(p + distance * wi - self.center),
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn pdf_li(&self, p: Point3, hit: Point3) -> f64 {
+        match self.cone_extent(p) {
+            Some(cone) if self.on_surface(hit) => cone_pdf(cone),
+            _ => 0.0,
+        }
+    }
+
+    fn on_surface(&self, p: Point3) -> bool {
+        ((p - self.center).length() - self.radius).abs() < 1e-4 * self.radius
+    }
+
+    // A uniformly chosen point on the sphere, emitting in a cosine weighted
+    // direction
+    fn sample_le(
+        &self,
+        u1: (f64, f64),
+        u2: (f64, f64),
+        _scene_center: Point3,
+        _scene_radius: f64,
+    ) -> Option<LightEmission> {
+        let normal = sample_unit_sphere(u1);
+        let mut direction = normal + sample_unit_sphere(u2);
+        if direction.near_zero() {
+            direction = normal;
+        }
+        let direction = unit_vector(direction);
+        Some(LightEmission {
+            ray: Ray {
+                origin: self.center + self.radius * normal,
+                direction,
+            },
+            normal,
+            radiance: self.radiance,
+            pdf_pos: 1.0 / self.area(),
+            pdf_dir: dot(normal, direction).max(0.0) / PI,
+        })
+    }
+
+    fn pdf_le(&self, r: &Ray, normal: Vec3, _scene_radius: f64) -> (f64, f64) {
+        let cos_theta = dot(normal, unit_vector(r.direction())).max(0.0);
+        (1.0 / self.area(), cos_theta / PI)
+    }
+}
+
+impl SphereLight {
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    // One minus the cosine of the half angle of the cone the sphere covers
+    // as seen from p, none from inside the sphere. Written as
+    // sin^2 / (1 + cos) so that it stays above zero for far away points,
+    // where 1 - sqrt(1 - sin^2) rounds to nothing.
+    fn cone_extent(&self, p: Point3) -> Option<f64> {
+        let distance_squared = (self.center - p).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        let sin2_theta_max = radius_squared / distance_squared;
+        Some(sin2_theta_max / (1.0 + (1.0 - sin2_theta_max).sqrt()))
+    }
+}
+
+// Density of directions spread uniformly over a cone, given one minus the
+// cosine of its half angle
+fn cone_pdf(one_minus_cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * one_minus_cos_theta_max)
+}
+
+pub fn make_sphere_light(center: Point3, radius: f64, radiance: Color) -> SphereLight {
+    SphereLight {
+        center,
+        radius,
+        radiance,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::make_color;
+    use crate::vec3::make_point;
+
+    #[test]
+    fn it_cuts_a_hard_edged_spot_off_at_its_width() {
+        let spot = make_spot_light(
+            make_point(0.0, 0.0, 0.0),
+            make_point(0.0, -1.0, 0.0),
+            make_color(1.0, 1.0, 1.0),
+            20.0,
+            20.0,
+        );
+        let inside = 19.0_f64.to_radians().cos();
+        let outside = 21.0_f64.to_radians().cos();
+        assert_eq!(spot.falloff(inside), 1.0);
+        assert_eq!(spot.falloff(outside), 0.0);
+
+        // Falloff past the edge of the cone is cut back to it
+        let spot = make_spot_light(
+            make_point(0.0, 0.0, 0.0),
+            make_point(0.0, -1.0, 0.0),
+            make_color(1.0, 1.0, 1.0),
+            30.0,
+            20.0,
+        );
+        assert_eq!(spot.falloff(inside), 1.0);
+        assert_eq!(spot.falloff(outside), 0.0);
+    }
+
+    #[test]
+    fn it_keeps_the_cone_of_a_far_sphere_finite() {
+        let light = make_sphere_light(make_point(0.0, 1.0, 0.0), 0.5, make_color(1.0, 1.0, 1.0));
+        // So far away that sqrt(1 - r^2 / d^2) rounds to one
+        let p = make_point(-2e6, 0.0, -1.4e7);
+        let d2 = (light.center - p).length_squared();
+        let sample = light.sample_li(p, (0.5, 0.5)).unwrap();
+        // The cone's solid angle tends to pi r^2 / d^2
+        let expected = d2 / (PI * 0.25);
+        assert!((sample.pdf - expected).abs() < 1e-6 * expected);
+        assert!(sample.wi.length().is_finite());
+    }
+}
@@ -1,34 +1,224 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::process;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use aabb::make_aabb;
+use background::{make_environment_map, make_gradient_sky};
+use bdpt::make_bdpt_integrator;
+use bvh::make_bvh;
 use camera::Camera;
 use color::make_color;
-use material::{make_dielectric, make_lambertian, make_metal, Material};
+use csg::{make_csg, CsgOperation};
+use curve::{make_curve, CurveType};
+use debug_integrator::{
+    make_ambient_occlusion_integrator, make_bounce_heatmap_integrator, make_bvh_cost_integrator,
+    make_depth_integrator, make_normals_integrator, make_uv_integrator,
+};
+use filter::{
+    make_box_filter, make_gaussian_filter, make_lanczos_filter, make_mitchell_filter,
+    make_tent_filter, Filter,
+};
+use hair::{make_hair_from_color, make_hair_from_melanin};
+use hdr_image::load_hdr_image;
+use height_grid::{load_height_grid, make_noise_height_grid, HeightGrid};
+use heightfield::make_heightfield;
+use integrator::{
+    make_direct_lighting_integrator, make_path_integrator, make_simple_path_integrator, Integrator,
+};
+use light::{
+    make_directional_light, make_light_list, make_point_light, make_sphere_light, make_spot_light,
+    LightList,
+};
+use material::{
+    make_conductor, make_dielectric, make_diffuse_light, make_dispersive_dielectric,
+    make_lambertian, make_metal, make_rough_dielectric, make_tinted_dielectric, Dispersion,
+    Material,
+};
+use medium::make_heterogeneous_medium;
+use mesh::{load_mesh, TriangleMesh};
+use photon::make_photon_integrator;
+use plane::make_plane;
+use principled::make_principled;
+use quadric::{make_cone, make_cylinder, make_disk, make_paraboloid};
 use rtweekend::{random_f64, random_f64_in_range};
+use sampler::{
+    make_halton_sampler, make_independent_sampler, make_sobol_sampler, make_stratified_sampler,
+    Sampler,
+};
+use sdf::{
+    make_mandelbulb, make_repetition, make_rounded_box, make_sdf_object, make_sdf_sphere,
+    make_sdf_torus, make_smooth_union, make_translate, make_twist,
+};
+use sky::make_preetham_sky;
 use sphere::make_sphere;
-use vec3::Vec3;
+use subsurface::make_subsurface_from_albedo;
+use texture::{
+    make_checker_texture, make_gradient_texture, make_solid_color, make_uv_checker_texture,
+};
+use thin_film::make_thin_film;
+use torus::make_torus;
+use triangle::make_triangle_mesh;
+use vec3::{cross, sample_unit_sphere, unit_vector, Vec3};
+use voxel_grid::{load_voxel_grid, make_noise_grid, VoxelGrid};
 
 use crate::{hittable_list::HittableList, vec3::make_point};
 
+mod aabb;
+mod background;
+mod bdpt;
+mod bvh;
 mod camera;
 mod color;
+mod csg;
+mod curve;
+mod debug_integrator;
+mod distribution;
+mod film;
+mod filter;
+mod hair;
+mod hdr_image;
+mod height_grid;
+mod heightfield;
 mod hit_record;
 mod hittable;
 mod hittable_list;
+mod integrator;
 mod interval;
+mod kdtree;
+mod light;
 mod material;
+mod medium;
+mod mesh;
+mod microfacet;
+mod onb;
+mod photon;
+mod plane;
+mod principled;
+mod quadric;
 mod ray;
 mod rtweekend;
+mod sampler;
+mod sdf;
+mod sky;
+mod spectrum;
 mod sphere;
+mod subsurface;
+mod texture;
+mod thin_film;
+mod torus;
+mod triangle;
 mod vec3;
+mod voxel_grid;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Err(message) = run(&args) {
+        eprintln!("{message}");
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut camera = Camera::default();
+    let scene = arg_value(args, "--scene").unwrap_or(String::from("spheres"));
+    let (world, lights) = match scene.as_str() {
+        "spheres" => (random_spheres(&mut camera), make_light_list()),
+        "materials" => (microfacet_materials(&mut camera), make_light_list()),
+        "principled" => (principled_materials(&mut camera), make_light_list()),
+        "lights" => lit_spheres(&mut camera),
+        "mis" => glossy_highlights(&mut camera),
+        "caustics" => glass_caustics(&mut camera),
+        "dispersion" => dispersive_glass(&mut camera),
+        "iridescence" => (thin_films(&mut camera), make_light_list()),
+        "smoke" => {
+            let grid = match arg_value(args, "--voxels") {
+                Some(path) => Some(
+                    load_voxel_grid(&path)
+                        .map_err(|e| format!("Could not load voxel grid {path}: {e}"))?,
+                ),
+                None => None,
+            };
+            smoke_cloud(&mut camera, grid)
+        }
+        "subsurface" => translucent_spheres(&mut camera),
+        "tinted" => (tinted_glass(&mut camera), make_light_list()),
+        "csg" => (solid_geometry(&mut camera), make_light_list()),
+        "quadrics" => (quadric_shapes(&mut camera), make_light_list()),
+        "terrain" => {
+            let grid = match arg_value(args, "--heightmap") {
+                Some(path) => Some(
+                    load_height_grid(&path)
+                        .map_err(|e| format!("Could not load height map {path}: {e}"))?,
+                ),
+                None => None,
+            };
+            terrain(&mut camera, grid)?
+        }
+        "sdf" => distance_fields(&mut camera),
+        "fur" => fur(&mut camera),
+        "mesh" => {
+            let mesh = match arg_value(args, "--mesh") {
+                Some(path) => {
+                    Some(load_mesh(&path).map_err(|e| format!("Could not load mesh {path}: {e}"))?)
+                }
+                None => None,
+            };
+            mesh_model(&mut camera, mesh, parse_arg(args, "--smooth-angle")?)
+        }
+        _ => return Err(format!("Unknown scene {scene}")),
+    };
+
+    // The scenes pick a size and sample count fit for a final image
+    if let Some(width) = parse_positive_arg(args, "--width")? {
+        camera.image_width = width;
+    }
+    if let Some(spp) = parse_positive_arg(args, "--spp")? {
+        camera.samples_per_pixel = spp;
+    }
+    camera.integrator = make_integrator(args)?;
+    if let Some(name) = arg_value(args, "--sampler") {
+        camera.sampler = make_sampler(&name)?;
+    }
+    if let Some(name) = arg_value(args, "--filter") {
+        camera.filter = make_filter(&name, parse_arg(args, "--filter-radius")?)?;
+    }
+    if arg_value(args, "--env").is_some() && arg_value(args, "--sky").is_some() {
+        return Err("--env and --sky both set the background; use only one".to_string());
+    }
+    if let Some(path) = arg_value(args, "--env") {
+        let image = load_hdr_image(&path)
+            .map_err(|e| format!("Could not load environment map {path}: {e}"))?;
+        let intensity = parse_arg(args, "--env-intensity")?.unwrap_or(1.0);
+        let rotation = parse_arg(args, "--env-rotation")?.unwrap_or(0.0);
+        camera.background = Box::new(make_environment_map(image, intensity, rotation));
+    }
+    if let Some(name) = arg_value(args, "--sky") {
+        if name != "preetham" {
+            return Err(format!("Unknown sky model {name}"));
+        }
+        let elevation = parse_arg(args, "--sun-elevation")?.unwrap_or(30.0);
+        let azimuth = parse_arg(args, "--sun-azimuth")?.unwrap_or(0.0);
+        let turbidity = parse_arg(args, "--turbidity")?.unwrap_or(3.0);
+        camera.background = Box::new(make_preetham_sky(elevation, azimuth, turbidity));
+    }
+
+    let world = make_bvh(world.objects);
+    camera.render(world.as_ref(), &lights);
+    Ok(())
+}
+
+fn random_spheres(camera: &mut Camera) -> HittableList {
     // World Setup
     let mut world = HittableList {
         objects: Vec::new(),
     };
 
     let ground_material = make_lambertian(make_color(0.5, 0.5, 0.5));
-    world.add(Box::new(make_sphere(
-        make_point(0.0, -1000.0, 0.0),
-        1000.0,
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
         Box::new(ground_material),
     )));
     let mut a = -11.0;
@@ -77,8 +267,6 @@ fn main() {
         Box::new(material_3),
     )));
 
-    let mut camera = Camera::default();
-
     camera.aspect_ratio = 16.0 / 9.0;
     camera.image_width = 1200;
     camera.samples_per_pixel = 100;
@@ -92,5 +280,1394 @@ fn main() {
     camera.defocus_angle = 0.6;
     camera.focus_dist = 10.0;
 
-    camera.render(&world);
+    world
+}
+
+fn microfacet_materials(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+    )));
+
+    let gold = make_conductor(
+        make_color(0.143, 0.374, 1.442),
+        make_color(3.983, 2.385, 1.603),
+        0.3,
+        0.3,
+    );
+    let copper = make_conductor(
+        make_color(0.200, 0.924, 1.102),
+        make_color(3.912, 2.452, 2.142),
+        0.1,
+        0.1,
+    );
+    let brushed_aluminium = make_conductor(
+        make_color(1.657, 0.880, 0.521),
+        make_color(9.224, 6.270, 4.837),
+        0.05,
+        0.4,
+    );
+    let frosted_glass = make_rough_dielectric(1.5, 0.2, 0.2);
+    let glass = make_rough_dielectric(1.5, 0.0, 0.0);
+
+    let materials: Vec<Box<dyn Material>> = vec![
+        Box::new(gold),
+        Box::new(copper),
+        Box::new(brushed_aluminium),
+        Box::new(frosted_glass),
+        Box::new(glass),
+    ];
+    for (index, mat) in materials.into_iter().enumerate() {
+        world.add(Box::new(make_sphere(
+            make_point(-4.4 + 2.2 * index as f64, 1.0, 0.0),
+            1.0,
+            mat,
+        )));
+    }
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 3.0, 14.0);
+    camera.lookat = make_point(0.0, 1.0, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+fn principled_materials(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.3, 0.1))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    let gold = make_color(1.0, 0.78, 0.34);
+    let red = make_color(0.8, 0.1, 0.1);
+    let mut index = 0;
+    while index < 5 {
+        let t = index as f64 / 4.0;
+        let x = -4.4 + 2.2 * index as f64;
+
+        // Back row: metallic from 0 to 1
+        let mut metal = make_principled(Box::new(make_solid_color(gold)));
+        metal.metallic = Box::new(make_solid_color(make_color(t, t, t)));
+        metal.roughness = Box::new(make_solid_color(make_color(0.3, 0.3, 0.3)));
+        world.add(Box::new(make_sphere(
+            make_point(x, 1.0, -2.5),
+            1.0,
+            Box::new(metal),
+        )));
+
+        // Middle row: roughness from 0 to 1
+        let mut plastic = make_principled(Box::new(make_solid_color(red)));
+        let roughness = t.max(0.05);
+        plastic.roughness = Box::new(make_solid_color(make_color(
+            roughness, roughness, roughness,
+        )));
+        world.add(Box::new(make_sphere(
+            make_point(x, 1.0, 0.0),
+            1.0,
+            Box::new(plastic),
+        )));
+        index += 1;
+    }
+
+    // Front row: clearcoat, sheen, transmission and a textured metallic mask
+    let mut varnish = make_principled(Box::new(make_solid_color(make_color(0.1, 0.2, 0.6))));
+    varnish.roughness = Box::new(make_solid_color(make_color(0.6, 0.6, 0.6)));
+    varnish.clearcoat = Box::new(make_solid_color(make_color(1.0, 1.0, 1.0)));
+    let mut velvet = make_principled(Box::new(make_solid_color(make_color(0.5, 0.1, 0.4))));
+    velvet.roughness = Box::new(make_solid_color(make_color(1.0, 1.0, 1.0)));
+    velvet.sheen = Box::new(make_solid_color(make_color(1.0, 1.0, 1.0)));
+    let mut glass = make_principled(Box::new(make_solid_color(make_color(0.9, 1.0, 0.9))));
+    glass.roughness = Box::new(make_solid_color(make_color(0.05, 0.05, 0.05)));
+    glass.transmission = Box::new(make_solid_color(make_color(1.0, 1.0, 1.0)));
+    let mut frosted = make_principled(Box::new(make_solid_color(make_color(1.0, 1.0, 1.0))));
+    frosted.roughness = Box::new(make_solid_color(make_color(0.3, 0.3, 0.3)));
+    frosted.transmission = Box::new(make_solid_color(make_color(1.0, 1.0, 1.0)));
+    let mut inlay = make_principled(Box::new(make_solid_color(gold)));
+    inlay.metallic = Box::new(make_checker_texture(
+        0.25,
+        Box::new(make_solid_color(make_color(1.0, 1.0, 1.0))),
+        Box::new(make_solid_color(make_color(0.0, 0.0, 0.0))),
+    ));
+    inlay.roughness = Box::new(make_solid_color(make_color(0.2, 0.2, 0.2)));
+
+    let front: Vec<Box<dyn Material>> = vec![
+        Box::new(varnish),
+        Box::new(velvet),
+        Box::new(glass),
+        Box::new(frosted),
+        Box::new(inlay),
+    ];
+    for (index, mat) in front.into_iter().enumerate() {
+        world.add(Box::new(make_sphere(
+            make_point(-4.4 + 2.2 * index as f64, 1.0, 2.5),
+            1.0,
+            mat,
+        )));
+    }
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 8.0, 16.0);
+    camera.lookat = make_point(0.0, 0.5, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+// Lit only by a point, a spot and a directional light under a dim sky
+fn lit_spheres(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.3, 0.1))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(-2.2, 1.0, 0.0),
+        1.0,
+        Box::new(make_lambertian(make_color(0.8, 0.3, 0.2))),
+    )));
+    let mut plastic = make_principled(Box::new(make_solid_color(make_color(0.2, 0.4, 0.8))));
+    plastic.roughness = Box::new(make_solid_color(make_color(0.3, 0.3, 0.3)));
+    world.add(Box::new(make_sphere(
+        make_point(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(plastic),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(2.2, 1.0, 0.0),
+        1.0,
+        Box::new(make_conductor(
+            make_color(0.143, 0.374, 1.442),
+            make_color(3.983, 2.385, 1.603),
+            0.3,
+            0.3,
+        )),
+    )));
+
+    let mut lights = make_light_list();
+    lights.add(Box::new(make_point_light(
+        make_point(-3.0, 4.0, 3.0),
+        make_color(20.0, 18.0, 15.0),
+    )));
+    lights.add(Box::new(make_spot_light(
+        make_point(3.0, 6.0, 2.0),
+        make_point(2.2, 0.0, 0.0),
+        make_color(60.0, 60.0, 80.0),
+        15.0,
+        25.0,
+    )));
+    lights.add(Box::new(make_directional_light(
+        Vec3(1.0, -1.0, -0.5),
+        make_color(0.3, 0.25, 0.2),
+    )));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.02, 0.02, 0.03),
+        make_color(0.01, 0.01, 0.02),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 4.0, 12.0);
+    camera.lookat = make_point(0.0, 0.8, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// Rows of increasingly rough metal reflecting spherical lights of
+// increasingly large size but equal power, after Veach's MIS test scene
+fn glossy_highlights(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.2, 0.2, 0.2))),
+    )));
+
+    let roughness = [0.05, 0.1, 0.2, 0.35];
+    let light_radius = [0.05, 0.15, 0.4, 0.8];
+    let light_color = [
+        make_color(1.0, 0.4, 0.3),
+        make_color(1.0, 0.9, 0.4),
+        make_color(0.4, 1.0, 0.5),
+        make_color(0.4, 0.6, 1.0),
+    ];
+    let mut index = 0;
+    while index < 4 {
+        let x = -3.3 + 2.2 * index as f64;
+        world.add(Box::new(make_sphere(
+            make_point(x, 1.0, 0.0),
+            1.0,
+            Box::new(make_conductor(
+                make_color(1.657, 0.880, 0.521),
+                make_color(9.224, 6.270, 4.837),
+                roughness[index],
+                roughness[index],
+            )),
+        )));
+
+        let center = make_point(x, 5.0, 5.0);
+        let radius = light_radius[index];
+        let radiance = 2.0 / (radius * radius) * light_color[index];
+        world.add(Box::new(make_sphere(
+            center,
+            radius,
+            Box::new(make_diffuse_light(radiance)),
+        )));
+        lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+        index += 1;
+    }
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.02, 0.02, 0.03),
+        make_color(0.01, 0.01, 0.02),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.5, 12.0);
+    camera.lookat = make_point(0.0, 1.0, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// A glass sphere on a pale floor under a small, bright light. Nearly all of
+// the light in the caustic below the sphere arrives through the glass, which
+// paths from the camera only find by chance.
+fn glass_caustics(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.7, 0.7, 0.65))),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(make_dielectric(1.5)),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(-2.2, 0.6, -1.2),
+        0.6,
+        Box::new(make_lambertian(make_color(0.6, 0.15, 0.1))),
+    )));
+
+    let center = make_point(1.5, 5.0, -1.0);
+    let radius = 0.2;
+    let radiance = make_color(300.0, 280.0, 250.0);
+    world.add(Box::new(make_sphere(
+        center,
+        radius,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+
+    // No sky. Photons from the background start on a disk as wide as the
+    // ground sphere, so the few that land near the glass come out as fireflies.
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.0, 0.0, 0.0),
+        make_color(0.0, 0.0, 0.0),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 4.0, 9.0);
+    camera.lookat = make_point(0.0, 0.6, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// Three glass spheres with increasing dispersion, lit from behind by a
+// small light. Their caustics only split into colors with --spectral.
+fn dispersive_glass(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    let checker = make_uv_checker_texture(
+        0.4,
+        Box::new(make_solid_color(make_color(0.05, 0.05, 0.05))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    // Schott N-BK7 and N-SF11, and a Cauchy glass far more dispersive than
+    // any real one
+    let crown = Dispersion::Sellmeier(
+        [1.03961212, 0.231792344, 1.01046945],
+        [0.00600069867, 0.0200179144, 103.560653],
+    );
+    let flint = Dispersion::Sellmeier(
+        [1.73759695, 0.313747346, 1.89878101],
+        [0.013188707, 0.0623068142, 155.23629],
+    );
+    let exaggerated = Dispersion::Cauchy(1.45, 0.04);
+    for (index, glass) in [crown, flint, exaggerated].into_iter().enumerate() {
+        world.add(Box::new(make_sphere(
+            make_point(-2.4 + 2.4 * index as f64, 1.0, 0.0),
+            1.0,
+            Box::new(make_dispersive_dielectric(glass)),
+        )));
+    }
+
+    let center = make_point(0.0, 3.0, -8.0);
+    let radius = 0.6;
+    let radiance = make_color(60.0, 60.0, 60.0);
+    world.add(Box::new(make_sphere(
+        center,
+        radius,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.1, 0.1, 0.1),
+        make_color(0.05, 0.05, 0.1),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 400;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.0, 10.0);
+    camera.lookat = make_point(0.0, 1.2, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// A soap bubble whose film thins towards the top, and metal spheres under
+// oxide layers of different thickness, under a bright sky
+fn thin_films(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.2, 0.2))),
+        Box::new(make_solid_color(make_color(0.8, 0.8, 0.8))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    // Soap water on air, 900 nm thick at the bottom and 200 nm at the top
+    let mut bubble = make_dielectric(1.0);
+    bubble.film = Some(make_thin_film(
+        Box::new(make_gradient_texture(
+            make_color(900.0, 900.0, 900.0),
+            make_color(200.0, 200.0, 200.0),
+        )),
+        1.33,
+    ));
+    world.add(Box::new(make_sphere(
+        make_point(0.0, 1.2, 0.0),
+        1.2,
+        Box::new(bubble),
+    )));
+
+    // Titanium dioxide on steel, as in heat tinted or anodized metal
+    for (index, thickness) in [150.0, 250.0, 350.0].into_iter().enumerate() {
+        let mut metal = make_metal(make_color(0.55, 0.55, 0.55), 0.05);
+        metal.film = Some(make_thin_film(
+            Box::new(make_solid_color(make_color(
+                thickness, thickness, thickness,
+            ))),
+            2.4,
+        ));
+        world.add(Box::new(make_sphere(
+            make_point(-2.2 + 2.2 * index as f64, 0.6, 2.0),
+            0.6,
+            Box::new(metal),
+        )));
+    }
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(1.0, 1.0, 1.0),
+        make_color(0.5, 0.7, 1.0),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.5, 10.0);
+    camera.lookat = make_point(0.0, 1.0, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+// A cloud over a floor, lit by a low, warm light and a blue sky. The cloud
+// comes from a Mitsuba .vol file when one is given, and from noise otherwise.
+fn smoke_cloud(camera: &mut Camera, voxels: Option<VoxelGrid>) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+    )));
+
+    let grid = voxels.unwrap_or_else(|| make_noise_grid(64, 4.0));
+    world.add(Box::new(make_heterogeneous_medium(
+        make_aabb(make_point(-2.0, 0.3, -2.0), make_point(2.0, 3.3, 2.0)),
+        grid,
+        10.0,
+        make_color(0.9, 0.9, 0.9),
+    )));
+
+    let center = make_point(-6.0, 6.0, 2.0);
+    let radius = 1.0;
+    let radiance = make_color(40.0, 32.0, 24.0);
+    world.add(Box::new(make_sphere(
+        center,
+        radius,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.5, 0.6, 0.7),
+        make_color(0.2, 0.3, 0.6),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+
+    camera.vfov = 35;
+    camera.lookfrom = make_point(0.0, 2.0, 10.0);
+    camera.lookat = make_point(0.0, 1.6, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// Marble, jade and skin-like spheres next to a Lambertian one, lit from
+// behind so that light shines through their edges
+fn translucent_spheres(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.4, 0.4, 0.4))),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(-3.3, 0.8, 0.0),
+        0.8,
+        Box::new(make_lambertian(make_color(0.9, 0.85, 0.8))),
+    )));
+
+    // Albedo and mean free path per channel. Skin lets red light travel
+    // furthest.
+    let translucent = [
+        (make_color(0.999, 0.998, 0.995), make_color(0.1, 0.1, 0.1)),
+        (make_color(0.8, 0.99, 0.85), make_color(0.3, 0.3, 0.3)),
+        (make_color(0.99, 0.9, 0.8), make_color(0.3, 0.12, 0.06)),
+    ];
+    for (index, (albedo, mean_free_path)) in translucent.into_iter().enumerate() {
+        let center = make_point(-1.1 + 2.2 * index as f64, 0.8, 0.0);
+        let boundary = Rc::new(make_sphere(center, 0.8, Box::new(make_dielectric(1.4))));
+        world.add(Box::new(make_sphere(
+            center,
+            0.8,
+            Box::new(make_subsurface_from_albedo(
+                boundary,
+                1.4,
+                albedo,
+                mean_free_path,
+            )),
+        )));
+    }
+
+    let center = make_point(1.0, 4.0, -7.0);
+    let radius = 2.5;
+    let radiance = make_color(8.0, 7.6, 7.0);
+    world.add(Box::new(make_sphere(
+        center,
+        radius,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.3, 0.3, 0.35),
+        make_color(0.15, 0.2, 0.3),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.0, 10.0);
+    camera.lookat = make_point(0.0, 0.8, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// Spheres of one kind of green glass: a small and a large solid one, and a
+// hollow one as large with a thin wall, which comes out the palest
+fn tinted_glass(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.2, 0.2))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    let glass = make_tinted_dielectric(1.5, make_color(0.3, 0.8, 0.5), 1.0);
+    world.add(Box::new(make_sphere(
+        make_point(-2.6, 0.5, 0.0),
+        0.5,
+        Box::new(glass.clone()),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(glass.clone()),
+    )));
+    world.add(Box::new(make_sphere(
+        make_point(2.6, 1.0, 0.0),
+        1.0,
+        Box::new(glass.clone()),
+    )));
+    // A negative radius turns the normals inwards, making the inner sphere
+    // the inside surface of the wall
+    world.add(Box::new(make_sphere(
+        make_point(2.6, 1.0, 0.0),
+        -0.9,
+        Box::new(glass),
+    )));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(1.0, 1.0, 1.0),
+        make_color(0.5, 0.7, 1.0),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.5, 10.0);
+    camera.lookat = make_point(0.0, 0.8, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+// A biconvex lens cut from two spheres, a metal ball with three bites taken
+// out of it, and two glass spheres fused without a wall between them
+fn solid_geometry(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.3, 0.1))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    let lens_center = make_point(-2.6, 1.4, 0.0);
+    let offset = Vec3(0.0, 0.0, 2.7);
+    world.add(Box::new(make_csg(
+        CsgOperation::Intersection,
+        Box::new(make_sphere(
+            lens_center - offset,
+            3.0,
+            Box::new(make_dielectric(1.5)),
+        )),
+        Box::new(make_sphere(
+            lens_center + offset,
+            3.0,
+            Box::new(make_dielectric(1.5)),
+        )),
+    )));
+
+    // The cut faces take the material of the shapes cut away
+    let mut bites = HittableList {
+        objects: Vec::new(),
+    };
+    for center in [
+        make_point(0.0, 1.9, 0.6),
+        make_point(-0.8, 1.0, 0.9),
+        make_point(0.8, 0.8, 0.9),
+    ] {
+        bites.add(Box::new(make_sphere(
+            center,
+            0.55,
+            Box::new(make_lambertian(make_color(0.8, 0.3, 0.1))),
+        )));
+    }
+    world.add(Box::new(make_csg(
+        CsgOperation::Difference,
+        Box::new(make_sphere(
+            make_point(0.0, 1.0, 0.0),
+            1.0,
+            Box::new(make_metal(make_color(0.8, 0.8, 0.85), 0.1)),
+        )),
+        Box::new(bites),
+    )));
+
+    let glass = make_tinted_dielectric(1.5, make_color(0.4, 0.6, 0.9), 1.0);
+    world.add(Box::new(make_csg(
+        CsgOperation::Union,
+        Box::new(make_sphere(
+            make_point(2.3, 0.7, 0.0),
+            0.7,
+            Box::new(glass.clone()),
+        )),
+        Box::new(make_sphere(make_point(2.9, 1.3, 0.0), 0.6, Box::new(glass))),
+    )));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(1.0, 1.0, 1.0),
+        make_color(0.5, 0.7, 1.0),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.5, 10.0);
+    camera.lookat = make_point(0.0, 1.0, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+// Mechanical shapes made of quadrics: a metal sleeve cut from two
+// cylinders, a cone, a washer, a mirrored dish, an open pipe and a glass
+// torus
+fn quadric_shapes(camera: &mut Camera) -> HittableList {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+
+    let checker = make_uv_checker_texture(
+        0.5,
+        Box::new(make_solid_color(make_color(0.2, 0.3, 0.1))),
+        Box::new(make_solid_color(make_color(0.9, 0.9, 0.9))),
+    );
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_principled(Box::new(checker))),
+    )));
+
+    let up = Vec3(0.0, 1.0, 0.0);
+    let steel = make_metal(make_color(0.8, 0.8, 0.85), 0.15);
+    world.add(Box::new(make_csg(
+        CsgOperation::Difference,
+        Box::new(make_cylinder(
+            make_point(-3.0, 0.0, 0.0),
+            up,
+            0.6,
+            1.4,
+            true,
+            Box::new(steel.clone()),
+        )),
+        Box::new(make_cylinder(
+            make_point(-3.0, -0.1, 0.0),
+            up,
+            0.4,
+            1.6,
+            true,
+            Box::new(steel),
+        )),
+    )));
+
+    world.add(Box::new(make_cone(
+        make_point(-1.5, 0.0, 0.2),
+        up,
+        0.5,
+        1.3,
+        true,
+        Box::new(make_lambertian(make_color(0.8, 0.3, 0.1))),
+    )));
+
+    world.add(Box::new(make_disk(
+        make_point(-0.4, 0.01, 1.8),
+        up,
+        0.5,
+        0.25,
+        Box::new(make_metal(make_color(0.9, 0.7, 0.3), 0.1)),
+    )));
+
+    world.add(Box::new(make_paraboloid(
+        make_point(0.2, 0.0, -0.3),
+        up,
+        0.8,
+        0.7,
+        Box::new(make_metal(make_color(0.9, 0.9, 0.9), 0.0)),
+    )));
+
+    world.add(Box::new(make_cylinder(
+        make_point(1.1, 0.3, 1.3),
+        Vec3(1.0, 0.0, -0.3),
+        0.3,
+        1.2,
+        false,
+        Box::new(make_lambertian(make_color(0.2, 0.4, 0.8))),
+    )));
+
+    world.add(Box::new(make_torus(
+        make_point(2.8, 0.75, -0.2),
+        Vec3(0.4, 0.0, 1.0),
+        0.5,
+        0.2,
+        Box::new(make_tinted_dielectric(1.5, make_color(0.4, 0.8, 0.6), 1.0)),
+    )));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(1.0, 1.0, 1.0),
+        make_color(0.5, 0.7, 1.0),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 3.5, 10.0);
+    camera.lookat = make_point(0.0, 0.6, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    world
+}
+
+// Rolling hills from a height map, or from noise when there is none, with a
+// lake filling the valleys, in low evening sun
+fn terrain(
+    camera: &mut Camera,
+    height_map: Option<HeightGrid>,
+) -> Result<(HittableList, LightList), String> {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    let grid = height_map.unwrap_or_else(|| make_noise_height_grid(256, 5.0));
+    world.add(Box::new(make_heightfield(
+        grid,
+        make_point(-10.0, 0.0, -10.0),
+        20.0,
+        20.0,
+        3.0,
+        Box::new(make_lambertian(make_color(0.45, 0.5, 0.3))),
+    )?));
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.9, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_tinted_dielectric(1.33, make_color(0.3, 0.6, 0.7), 1.0)),
+    )));
+
+    lights.add(Box::new(make_directional_light(
+        Vec3(-1.0, -0.35, -0.6),
+        make_color(3.0, 2.4, 1.6),
+    )));
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.5, 0.5, 0.5),
+        make_color(0.25, 0.35, 0.5),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 40;
+    camera.lookfrom = make_point(0.0, 7.0, 14.0);
+    camera.lookat = make_point(0.0, 0.5, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    Ok((world, lights))
+}
+
+// Two furry balls, one brown and one dyed teal, in a patch of grass under a
+// warm light
+fn fur(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.35, 0.3, 0.25))),
+    )));
+
+    let mut brown = make_hair_from_melanin(1.3, 0.2);
+    brown.beta_m = 0.25;
+    let teal = make_hair_from_color(make_color(0.2, 0.6, 0.6), 0.3);
+    let balls: [(_, Box<dyn Material>); 2] = [
+        (make_point(-1.1, 0.8, 0.0), Box::new(brown)),
+        (make_point(1.1, 0.8, 0.0), Box::new(teal)),
+    ];
+    let radius = 0.6;
+    let length = 0.3;
+    for (center, hair) in balls {
+        world.add(Box::new(make_sphere(
+            center,
+            radius,
+            Box::new(make_lambertian(make_color(0.1, 0.08, 0.07))),
+        )));
+        // Strands stick out from the skin and droop a little under their
+        // own weight
+        let mut i = 0;
+        while i < 12000 {
+            let normal = sample_unit_sphere((random_f64(), random_f64()));
+            let root = center + radius * normal;
+            let droop = Vec3(
+                0.1 * (random_f64() - 0.5),
+                -0.25 - 0.1 * random_f64(),
+                0.1 * (random_f64() - 0.5),
+            ) * length;
+            world.objects.extend(make_curve(
+                [
+                    root,
+                    root + length / 3.0 * normal,
+                    root + 2.0 * length / 3.0 * normal + 0.5 * droop,
+                    root + length * normal + droop,
+                ],
+                [0.008, 0.002],
+                CurveType::Cylinder,
+                hair.clone(),
+            ));
+            i += 1;
+        }
+    }
+
+    // Blades of grass lean out and curl over, each facing its own way
+    let grass = make_lambertian(make_color(0.2, 0.45, 0.1));
+    let mut i = 0;
+    while i < 6000 {
+        let root = make_point(
+            random_f64_in_range(-4.0, 4.0),
+            0.0,
+            random_f64_in_range(-2.5, 2.5),
+        );
+        let height = random_f64_in_range(0.2, 0.45);
+        let angle = random_f64_in_range(0.0, 2.0 * std::f64::consts::PI);
+        let lean = Vec3(angle.cos(), 0.0, angle.sin()) * height * random_f64_in_range(0.1, 0.5);
+        let facing = Vec3(-angle.sin(), 0.0, angle.cos());
+        world.objects.extend(make_curve(
+            [
+                root,
+                root + Vec3(0.0, height / 2.0, 0.0),
+                root + Vec3(0.0, height, 0.0) + 0.5 * lean,
+                root + Vec3(0.0, height, 0.0) + lean,
+            ],
+            [0.03, 0.0],
+            CurveType::Ribbon([facing, facing + lean]),
+            Box::new(grass.clone()),
+        ));
+        i += 1;
+    }
+
+    let center = make_point(-3.0, 5.0, 4.0);
+    let radiance = make_color(12.0, 10.0, 8.0);
+    world.add(Box::new(make_sphere(
+        center,
+        0.8,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, 0.8, radiance)));
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.3, 0.3, 0.35),
+        make_color(0.2, 0.3, 0.45),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.0, 7.0);
+    camera.lookat = make_point(0.0, 0.6, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// A model from a PLY or STL file, or a knotted tube colored along its length
+// when there is none, standing on the floor. Models without normals of
+// their own get smooth ones, keeping edges sharper than the smoothing angle
+// (60 degrees unless given).
+fn mesh_model(
+    camera: &mut Camera,
+    mesh: Option<TriangleMesh>,
+    smooth_angle: Option<f64>,
+) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+    )));
+
+    let mut mesh = mesh.unwrap_or_else(torus_knot);
+    if mesh.normals.is_empty() || smooth_angle.is_some() {
+        mesh.smooth_normals(smooth_angle.unwrap_or(60.0));
+    }
+
+    // Scaled to 2.5 units across its largest side, centered over the
+    // origin and resting on the floor, y being up
+    let mut low = mesh.positions[0];
+    let mut high = mesh.positions[0];
+    for p in &mesh.positions {
+        low = make_point(low.x().min(p.x()), low.y().min(p.y()), low.z().min(p.z()));
+        high = make_point(
+            high.x().max(p.x()),
+            high.y().max(p.y()),
+            high.z().max(p.z()),
+        );
+    }
+    let size = high - low;
+    let scale = 2.5 / size.x().max(size.y()).max(size.z());
+    let base = make_point(
+        0.5 * (low.x() + high.x()),
+        low.y(),
+        0.5 * (low.z() + high.z()),
+    );
+    for p in mesh.positions.iter_mut() {
+        *p = scale * (*p - base);
+    }
+
+    let mut mat = make_principled(Box::new(make_solid_color(make_color(0.8, 0.8, 0.8))));
+    mat.roughness = Box::new(make_solid_color(make_color(0.35, 0.35, 0.35)));
+    world
+        .objects
+        .extend(make_triangle_mesh(mesh, Box::new(mat)));
+
+    let center = make_point(-2.0, 5.0, 3.0);
+    let radiance = make_color(10.0, 10.0, 10.0);
+    world.add(Box::new(make_sphere(
+        center,
+        1.0,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, 1.0, radiance)));
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.4, 0.4, 0.45),
+        make_color(0.2, 0.25, 0.35),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 30;
+    camera.lookfrom = make_point(0.0, 2.5, 7.0);
+    camera.lookat = make_point(0.0, 1.1, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// A tube around a trefoil knot, faceted with ten sides, its vertex colors
+// running through the hues along it
+fn torus_knot() -> TriangleMesh {
+    let (segments, sides) = (240, 10);
+    let knot = |s: f64| {
+        let r = 2.0 + (3.0 * s).cos();
+        make_point(r * (2.0 * s).cos(), r * (2.0 * s).sin(), (3.0 * s).sin())
+    };
+    let mut mesh = TriangleMesh {
+        positions: Vec::new(),
+        colors: Vec::new(),
+        faces: Vec::new(),
+        normals: Vec::new(),
+    };
+    let mut i = 0;
+    while i < segments {
+        let s = 2.0 * std::f64::consts::PI * i as f64 / segments as f64;
+        let tangent = unit_vector(knot(s + 1e-4) - knot(s - 1e-4));
+        let side = unit_vector(cross(tangent, Vec3(0.0, 0.0, 1.0)));
+        let normal = cross(side, tangent);
+        let color = make_color(
+            0.5 + 0.5 * s.cos(),
+            0.5 + 0.5 * (s - 2.1).cos(),
+            0.5 + 0.5 * (s + 2.1).cos(),
+        );
+        let mut j = 0;
+        while j < sides {
+            let a = 2.0 * std::f64::consts::PI * j as f64 / sides as f64;
+            mesh.positions
+                .push(knot(s) + 0.4 * (a.cos() * normal + a.sin() * side));
+            mesh.colors.push(color);
+
+            // Wound so that the outside faces away from the knot's center line
+            let next_i = (i + 1) % segments;
+            let next_j = (j + 1) % sides;
+            let index = |i: usize, j: usize| i * sides + j;
+            mesh.faces
+                .push([index(i, j), index(i, next_j), index(next_i, next_j)]);
+            mesh.faces
+                .push([index(i, j), index(next_i, next_j), index(next_i, j)]);
+            j += 1;
+        }
+        i += 1;
+    }
+    mesh
+}
+
+// Shapes made of distance fields: a torus, a twisted rounded box, two
+// blobs of glass melted together, a row of beads and a Mandelbulb
+fn distance_fields(camera: &mut Camera) -> (HittableList, LightList) {
+    let mut world = HittableList {
+        objects: Vec::new(),
+    };
+    let mut lights = make_light_list();
+
+    world.add(Box::new(make_plane(
+        make_point(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+    )));
+
+    world.add(Box::new(make_sdf_object(
+        Box::new(make_translate(
+            Box::new(make_sdf_torus(0.7, 0.25)),
+            Vec3(-3.4, 0.25, 0.0),
+        )),
+        Box::new(make_metal(make_color(0.9, 0.7, 0.3), 0.05)),
+    )));
+
+    world.add(Box::new(make_sdf_object(
+        Box::new(make_translate(
+            Box::new(make_twist(
+                Box::new(make_rounded_box(Vec3(0.4, 1.0, 0.4), 0.08)),
+                1.2,
+            )),
+            Vec3(-1.4, 1.0, 0.0),
+        )),
+        Box::new(make_lambertian(make_color(0.2, 0.4, 0.8))),
+    )));
+
+    world.add(Box::new(make_sdf_object(
+        Box::new(make_smooth_union(
+            Box::new(make_translate(
+                Box::new(make_sdf_sphere(0.5)),
+                Vec3(0.2, 0.5, 0.0),
+            )),
+            Box::new(make_translate(
+                Box::new(make_sdf_sphere(0.35)),
+                Vec3(0.7, 1.1, 0.0),
+            )),
+            0.3,
+        )),
+        Box::new(make_tinted_dielectric(1.5, make_color(0.9, 0.5, 0.4), 1.0)),
+    )));
+
+    world.add(Box::new(make_sdf_object(
+        Box::new(make_translate(
+            Box::new(make_repetition(
+                Box::new(make_sdf_sphere(0.12)),
+                Vec3(0.4, 0.0, 0.0),
+                [5, 0, 0],
+            )),
+            Vec3(0.0, 0.12, 2.0),
+        )),
+        Box::new(make_metal(make_color(0.8, 0.8, 0.8), 0.0)),
+    )));
+
+    world.add(Box::new(make_sdf_object(
+        Box::new(make_translate(
+            Box::new(make_mandelbulb(8.0, 6)),
+            Vec3(2.8, 1.2, 0.0),
+        )),
+        Box::new(make_lambertian(make_color(0.8, 0.6, 0.5))),
+    )));
+
+    let center = make_point(-4.0, 7.0, 5.0);
+    let radius = 1.0;
+    let radiance = make_color(15.0, 14.0, 13.0);
+    world.add(Box::new(make_sphere(
+        center,
+        radius,
+        Box::new(make_diffuse_light(radiance)),
+    )));
+    lights.add(Box::new(make_sphere_light(center, radius, radiance)));
+
+    camera.background = Box::new(make_gradient_sky(
+        make_color(0.6, 0.6, 0.6),
+        make_color(0.3, 0.4, 0.6),
+    ));
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 1200;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 35;
+    camera.lookfrom = make_point(0.0, 2.5, 10.0);
+    camera.lookat = make_point(0.0, 1.0, 0.0);
+    camera.vup = Vec3(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+    camera.focus_dist = 10.0;
+
+    (world, lights)
+}
+
+// Value following a `--flag value` pair on the command line
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// Value following a flag, parsed; none when the flag isn't given
+fn parse_arg<T: FromStr>(args: &[String], flag: &str) -> Result<Option<T>, String> {
+    match arg_value(args, flag) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value {value} for {flag}")),
+        None => Ok(None),
+    }
+}
+
+// Like parse_arg, for values that must be above zero (and so not NaN)
+fn parse_positive_arg<T: FromStr + PartialOrd + Default + Display>(
+    args: &[String],
+    flag: &str,
+) -> Result<Option<T>, String> {
+    match parse_arg::<T>(args, flag)? {
+        Some(value) if value.partial_cmp(&T::default()) != Some(Ordering::Greater) => Err(format!(
+            "Invalid value {value} for {flag}, it must be positive"
+        )),
+        value => Ok(value),
+    }
+}
+
+fn make_sampler(name: &str) -> Result<Box<dyn Sampler>, String> {
+    Ok(match name {
+        "independent" => Box::new(make_independent_sampler()),
+        "stratified" => Box::new(make_stratified_sampler(true)),
+        "uniform" => Box::new(make_stratified_sampler(false)),
+        "halton" => Box::new(make_halton_sampler()),
+        "sobol" => Box::new(make_sobol_sampler()),
+        _ => return Err(format!("Unknown sampler {name}")),
+    })
+}
+
+fn make_filter(name: &str, radius: Option<f64>) -> Result<Box<dyn Filter>, String> {
+    Ok(match name {
+        "box" => Box::new(make_box_filter(radius.unwrap_or(0.5))),
+        "tent" => Box::new(make_tent_filter(radius.unwrap_or(1.0))),
+        "gaussian" => Box::new(make_gaussian_filter(radius.unwrap_or(1.5), 0.5)),
+        "mitchell" => Box::new(make_mitchell_filter(
+            radius.unwrap_or(2.0),
+            1.0 / 3.0,
+            1.0 / 3.0,
+        )),
+        "lanczos" => Box::new(make_lanczos_filter(radius.unwrap_or(3.0), 3.0)),
+        _ => return Err(format!("Unknown filter {name}")),
+    })
+}
+
+fn make_integrator(args: &[String]) -> Result<Box<dyn Integrator>, String> {
+    let name = arg_value(args, "--integrator").unwrap_or(String::from("path"));
+    let number = |flag: &str, default: f64| -> Result<f64, String> {
+        Ok(parse_arg(args, flag)?.unwrap_or(default))
+    };
+    let positive = |flag: &str, default: f64| -> Result<f64, String> {
+        Ok(parse_positive_arg(args, flag)?.unwrap_or(default))
+    };
+    let count = |flag: &str, default: i32| -> Result<i32, String> {
+        Ok(parse_positive_arg(args, flag)?.unwrap_or(default))
+    };
+    let rr_depth = count("--rr-depth", 3)?;
+    let spectral = args.iter().any(|arg| arg == "--spectral");
+    if spectral && name != "path" && name != "simple" {
+        return Err(String::from(
+            "Spectral rendering needs the path or simple integrator",
+        ));
+    }
+    Ok(match name.as_str() {
+        "path" => {
+            let mut integrator = make_path_integrator(rr_depth);
+            integrator.spectral = spectral;
+            Box::new(integrator)
+        }
+        "simple" => {
+            let mut integrator = make_simple_path_integrator(rr_depth);
+            integrator.spectral = spectral;
+            Box::new(integrator)
+        }
+        "direct" => Box::new(make_direct_lighting_integrator()),
+        "bdpt" => Box::new(make_bdpt_integrator()),
+        "photon" => Box::new(make_photon_integrator(
+            count("--photons", 100000)?,
+            positive("--photon-radius", 0.1)?,
+        )),
+        "ao" => Box::new(make_ambient_occlusion_integrator(
+            positive("--ao-radius", 1.0)?,
+            count("--ao-samples", 4)?,
+        )),
+        "normals" => Box::new(make_normals_integrator()),
+        "uv" => Box::new(make_uv_integrator()),
+        "depth" => Box::new(make_depth_integrator(
+            number("--depth-near", 0.0)?,
+            number("--depth-far", 20.0)?,
+        )),
+        "bounces" => Box::new(make_bounce_heatmap_integrator(rr_depth)),
+        "bvh-cost" => Box::new(make_bvh_cost_integrator(number("--heatmap-max", 64.0)?)),
+        _ => return Err(format!("Unknown integrator {name}")),
+    })
 }
@@ -1,11 +1,38 @@
 use crate::{
     color::{make_color, Color},
     hit_record::HitRecord,
+    microfacet::{
+        fr_complex_color, fr_dielectric, make_trowbridge_reitz, real, refract_local,
+        TrowbridgeReitz,
+    },
+    onb::{make_tangent_onb, Onb},
     ray::Ray,
-    rtweekend::random_f64,
-    vec3::{dot, random_unit, reflect, refract, unit_vector},
+    rtweekend::PI,
+    sampler::Sampler,
+    thin_film::{metal_ior, ThinFilm},
+    vec3::{dot, reflect, refract, sample_unit_sphere, unit_vector, Vec3},
 };
 
+// What a path carries: radiance when it is traced from the camera, power or
+// importance when it is traced from the lights. Refraction squeezes radiance
+// into the narrower cone of directions inside the denser side, scaling it by
+// 1 / eta^2, but leaves power and importance as they are, so BSDFs that
+// refract depend on the direction of travel (Veach's thesis, section 5.2).
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TransportMode {
+    #[default]
+    Radiance,
+    Importance,
+}
+
+// Factor refraction across the relative index etap applies to what a path carries
+pub fn refraction_scale(etap: f64, mode: TransportMode) -> f64 {
+    match mode {
+        TransportMode::Radiance => 1.0 / (etap * etap),
+        TransportMode::Importance => 1.0,
+    }
+}
+
 pub trait Material: MaterialClone {
     fn scatter(
         &self,
@@ -13,10 +40,28 @@ pub trait Material: MaterialClone {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool;
+
+    // BSDF times the cosine at the outgoing side for light arriving along
+    // `scattered` and leaving back along r_in. Delta lobes (mirrors, smooth
+    // glass) can't be evaluated for a given pair of directions and give black.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        make_color(0.0, 0.0, 0.0)
+    }
+
+    // Solid angle density with which scatter() picks `scattered`
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    // Radiance given off by the surface towards the incoming ray
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        make_color(0.0, 0.0, 0.0)
+    }
 }
 
-trait MaterialClone {
+pub trait MaterialClone {
     fn clone_box(&self) -> Box<dyn Material>;
 }
 
@@ -41,12 +86,13 @@ pub struct Lambertian(Color);
 impl Material for Lambertian {
     fn scatter(
         &self,
-        r_in: Ray,
+        _r_in: Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let mut scatter_direction = rec.normal + random_unit();
+        let mut scatter_direction = rec.normal + sample_unit_sphere(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
@@ -54,19 +100,65 @@ impl Material for Lambertian {
             origin: rec.p,
             direction: scatter_direction,
         };
-        *attenuation = self.0;
+        *attenuation = self.0 * rec.color;
         true
     }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.0 * rec.color * self.scattering_pdf(_r_in, rec, scattered)
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = dot(rec.normal, unit_vector(scattered.direction()));
+        cosine.max(0.0) / PI
+    }
 }
 
 pub fn make_lambertian(albedo: Color) -> Lambertian {
     Lambertian(albedo)
 }
 
+// Phase function scattering equally in all directions, for points inside
+// participating media. There is no surface, so no cosine either.
+#[derive(Clone)]
+pub struct Isotropic(Color);
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        *scattered = Ray {
+            origin: rec.p,
+            direction: sample_unit_sphere(sampler.get_2d()),
+        };
+        *attenuation = self.0;
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.0 * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+pub fn make_isotropic(albedo: Color) -> Isotropic {
+    Isotropic(albedo)
+}
+
 #[derive(Clone)]
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    // Optional coating, such as an oxide layer
+    pub film: Option<ThinFilm>,
 }
 
 impl Material for Metal {
@@ -76,20 +168,51 @@ impl Material for Metal {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let reflected = reflect(unit_vector(r_in.direction()), rec.normal);
+        let unit_direction = unit_vector(r_in.direction());
+        let reflected = reflect(unit_direction, rec.normal);
         *scattered = Ray {
             origin: rec.p,
-            direction: reflected + self.fuzz * random_unit(),
+            direction: reflected + self.fuzz * sample_unit_sphere(sampler.get_2d()),
+        };
+        *attenuation = match &self.film {
+            Some(film) => {
+                let cos_theta = dot(-unit_direction, rec.normal);
+                film.reflectance(rec, cos_theta, 1.0, &|_, channel| {
+                    metal_ior(self.albedo, rec, channel)
+                })
+            }
+            None => self.albedo,
         };
-        *attenuation = self.albedo;
         true
     }
 }
 
+// How a dielectric's index of refraction varies with wavelength. Both
+// models take the wavelength in micrometers.
+#[derive(Clone)]
+pub enum Dispersion {
+    None,
+    // n = a + b / wavelength^2
+    Cauchy(f64, f64),
+    // n^2 = 1 + sum of b wavelength^2 / (wavelength^2 - c), c in square
+    // micrometers
+    Sellmeier([f64; 3], [f64; 3]),
+}
+
 #[derive(Clone)]
 pub struct Dielectric {
+    // Index of refraction for RGB rendering
     ir: f64,
+    dispersion: Dispersion,
+    // Optional coating. With a film, reflection follows the full Fresnel
+    // equations rather than Schlick's approximation.
+    pub film: Option<ThinFilm>,
+    // Absorption coefficient per unit length inside, per channel. Light
+    // reaching the inside of the surface is dimmed by Beer-Lambert's law
+    // over the distance it travelled since entering.
+    pub absorption: Color,
 }
 
 impl Material for Dielectric {
@@ -99,34 +222,83 @@ impl Material for Dielectric {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         *attenuation = make_color(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        let ir = match rec.wavelength {
+            Some(wavelength) => self.index_of_refraction(wavelength),
+            None => self.ir,
         };
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = unit_vector(r_in.direction());
         let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction =
-            if cannot_refract || self.reflectance(cos_theta, refraction_ratio) > random_f64() {
-                reflect(unit_direction, rec.normal)
-            } else {
-                refract(unit_direction, rec.normal, refraction_ratio)
-            };
+        let u = sampler.get_1d();
+        let reflects = match &self.film {
+            Some(film) => {
+                // Colored reflectance: pick a lobe by its average and
+                // weight the path by the color over that probability
+                let reflectance = if cannot_refract {
+                    make_color(1.0, 1.0, 1.0)
+                } else {
+                    let (outside, inside) = if rec.front_face { (1.0, ir) } else { (ir, 1.0) };
+                    film.reflectance(rec, cos_theta, outside, &|_, _| real(inside))
+                };
+                let p = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+                if u < p {
+                    *attenuation = reflectance / p;
+                    true
+                } else {
+                    *attenuation = (make_color(1.0, 1.0, 1.0) - reflectance) / (1.0 - p);
+                    false
+                }
+            }
+            None => cannot_refract || self.reflectance(cos_theta, refraction_ratio) > u,
+        };
+        let direction = if reflects {
+            reflect(unit_direction, rec.normal)
+        } else {
+            refract(unit_direction, rec.normal, refraction_ratio)
+        };
         *scattered = Ray {
             origin: rec.p,
             direction,
         };
+        if !rec.front_face {
+            let distance = rec.t * r_in.direction().length();
+            *attenuation *= make_color(
+                (-self.absorption.x() * distance).exp(),
+                (-self.absorption.y() * distance).exp(),
+                (-self.absorption.z() * distance).exp(),
+            );
+        }
         true
     }
 }
 
 impl Dielectric {
+    // Index of refraction at a wavelength in nm
+    fn index_of_refraction(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let squared = micrometers * micrometers;
+        match &self.dispersion {
+            Dispersion::None => self.ir,
+            Dispersion::Cauchy(a, b) => a + b / squared,
+            Dispersion::Sellmeier(b, c) => {
+                let mut n2 = 1.0;
+                let mut term = 0;
+                while term < 3 {
+                    n2 += b[term] * squared / (squared - c[term]);
+                    term += 1;
+                }
+                n2.sqrt()
+            }
+        }
+    }
+
     fn reflectance(&self, cosine: f64, ref_idx: f64) -> f64 {
         // Schlick's approximation
         let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -135,10 +307,484 @@ impl Dielectric {
     }
 }
 
+// Emits light from its front side and reflects nothing
+#[derive(Clone)]
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            make_color(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+pub fn make_diffuse_light(emit: Color) -> DiffuseLight {
+    DiffuseLight { emit }
+}
+
 pub fn make_metal(albedo: Color, fuzz: f64) -> Metal {
-    Metal { albedo, fuzz }
+    Metal {
+        albedo,
+        fuzz,
+        film: None,
+    }
 }
 
 pub fn make_dielectric(ir: f64) -> Dielectric {
-    Dielectric { ir }
+    Dielectric {
+        ir,
+        dispersion: Dispersion::None,
+        film: None,
+        absorption: make_color(0.0, 0.0, 0.0),
+    }
+}
+
+// Colored glass that gives light travelling `distance` through it the color
+// `tint`, so thin parts come out paler and thick parts darker
+pub fn make_tinted_dielectric(ir: f64, tint: Color, distance: f64) -> Dielectric {
+    let mut dielectric = make_dielectric(ir);
+    dielectric.absorption = make_color(
+        -tint.x().max(1e-6).ln() / distance,
+        -tint.y().max(1e-6).ln() / distance,
+        -tint.z().max(1e-6).ln() / distance,
+    );
+    dielectric
+}
+
+// Dispersive dielectric. RGB rendering uses the index of refraction at the
+// yellow helium d line (587.6 nm), which glass catalogs quote as n_d.
+pub fn make_dispersive_dielectric(dispersion: Dispersion) -> Dielectric {
+    let mut dielectric = Dielectric {
+        ir: 1.0,
+        dispersion,
+        film: None,
+        absorption: make_color(0.0, 0.0, 0.0),
+    };
+    dielectric.ir = dielectric.index_of_refraction(587.6);
+    dielectric
+}
+
+// Local frame around `normal` with x along the surface tangent, in which
+// microfacet lobes are evaluated
+pub fn shading_frame(rec: &HitRecord, normal: Vec3) -> Onb {
+    make_tangent_onb(normal, rec.tangent)
+}
+
+// Directions of a scattering event in the shading frame around `normal`,
+// with wo pointing back along the incoming ray
+pub fn local_directions(
+    r_in: &Ray,
+    rec: &HitRecord,
+    normal: Vec3,
+    scattered: &Ray,
+) -> (Vec3, Vec3) {
+    let onb = shading_frame(rec, normal);
+    (
+        onb.world_to_local(-unit_vector(r_in.direction())),
+        onb.world_to_local(unit_vector(scattered.direction())),
+    )
+}
+
+fn mirror_local(w: Vec3) -> Vec3 {
+    Vec3(-w.x(), -w.y(), w.z())
+}
+
+// Scatters along a direction picked in the local frame, weighting it by the
+// material's own eval / pdf so sampling and evaluation always agree.
+pub fn scatter_local(
+    mat: &dyn Material,
+    r_in: &Ray,
+    rec: &HitRecord,
+    onb: &Onb,
+    wi: Vec3,
+    attenuation: &mut Color,
+    scattered: &mut Ray,
+) -> bool {
+    *scattered = Ray {
+        origin: rec.p,
+        direction: onb.local(wi),
+    };
+    let pdf = mat.scattering_pdf(r_in, rec, scattered);
+    if pdf <= 0.0 {
+        return false;
+    }
+    *attenuation = mat.eval(r_in, rec, scattered) / pdf;
+    true
+}
+
+// GGX microfacet conductor. Reflectance follows the Fresnel equations for the
+// metal's complex index of refraction eta + i k, given per color channel.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let u = sampler.get_2d();
+        let onb = shading_frame(rec, rec.normal);
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if self.distribution.effectively_smooth() {
+            *scattered = Ray {
+                origin: rec.p,
+                direction: onb.local(mirror_local(wo)),
+            };
+            *attenuation = fr_complex_color(wo.z(), self.eta, self.k);
+            return true;
+        }
+
+        let wm = self.distribution.sample_wm(wo, u);
+        let wi = reflect(-wo, wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+        scatter_local(self, &r_in, rec, &onb, wi, attenuation, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        if self.distribution.effectively_smooth() {
+            return make_color(0.0, 0.0, 0.0);
+        }
+        let (wo, wi) = local_directions(r_in, rec, rec.normal, scattered);
+        let cos_theta_o = wo.z();
+        let cos_theta_i = wi.z();
+        if cos_theta_i <= 0.0 || cos_theta_o <= 0.0 {
+            return make_color(0.0, 0.0, 0.0);
+        }
+        let wm = wi + wo;
+        if wm.length_squared() == 0.0 {
+            return make_color(0.0, 0.0, 0.0);
+        }
+        let wm = unit_vector(wm);
+        let fresnel = fr_complex_color(dot(wo, wm).abs(), self.eta, self.k);
+        self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * cos_theta_o) * fresnel
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let (wo, wi) = local_directions(r_in, rec, rec.normal, scattered);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = wo + wi;
+        if wm.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wm = unit_vector(wm);
+        self.distribution.visible_d(wo, wm) / (4.0 * dot(wo, wm).abs())
+    }
+}
+
+// Anisotropic roughness stretches highlights along the surface tangent (x),
+// the direction in which the shape's u grows, or across it (y).
+pub fn make_conductor(eta: Color, k: Color, roughness_x: f64, roughness_y: f64) -> Conductor {
+    Conductor {
+        eta,
+        k,
+        distribution: make_trowbridge_reitz(roughness_x, roughness_y),
+    }
+}
+
+// GGX microfacet dielectric that both reflects and transmits through a rough
+// interface. With zero roughness it behaves like smooth glass.
+#[derive(Clone)]
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let u_lobe = sampler.get_1d();
+        let u = sampler.get_2d();
+        let onb = shading_frame(rec, outward_normal(rec));
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        if wo.z() == 0.0 {
+            return false;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let reflectance = fr_dielectric(wo.z(), self.ir);
+            let direction = if u_lobe < reflectance {
+                *attenuation = make_color(1.0, 1.0, 1.0);
+                mirror_local(wo)
+            } else {
+                match refract_local(wo, Vec3(0.0, 0.0, 1.0), self.ir) {
+                    Some((wi, etap)) => {
+                        let scale = refraction_scale(etap, rec.transport);
+                        *attenuation = make_color(scale, scale, scale);
+                        wi
+                    }
+                    None => return false,
+                }
+            };
+            *scattered = Ray {
+                origin: rec.p,
+                direction: onb.local(direction),
+            };
+            return true;
+        }
+
+        match self.sample_local(wo, u_lobe, u) {
+            Some(wi) => scatter_local(self, &r_in, rec, &onb, wi, attenuation, scattered),
+            None => false,
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_directions(r_in, rec, outward_normal(rec), scattered);
+        let f = self.eval_local(wo, wi, rec.transport);
+        make_color(f, f, f)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_directions(r_in, rec, outward_normal(rec), scattered);
+        self.pdf_local(wo, wi)
+    }
+}
+
+// The rough interface in the local frame around the outward normal, where
+// wo.z() < 0 means the ray arrives from inside the object.
+impl RoughDielectric {
+    // Picks reflection or refraction by Fresnel, about a visible microfacet normal
+    pub fn sample_local(&self, wo: Vec3, u_lobe: f64, u: (f64, f64)) -> Option<Vec3> {
+        let wm = self.distribution.sample_wm(wo, u);
+        let reflectance = fr_dielectric(dot(wo, wm), self.ir);
+        if u_lobe < reflectance {
+            let wi = reflect(-wo, wm);
+            if wi.z() * wo.z() <= 0.0 {
+                return None;
+            }
+            Some(wi)
+        } else {
+            match refract_local(wo, wm, self.ir) {
+                Some((wi, _)) if wi.z() * wo.z() < 0.0 => Some(wi),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn eval_local(&self, wo: Vec3, wi: Vec3, mode: TransportMode) -> f64 {
+        match self.half_vector(wo, wi) {
+            Some((wm, etap)) => {
+                let cos_theta_o = wo.z();
+                let cos_theta_i = wi.z();
+                let reflectance = fr_dielectric(dot(wo, wm), self.ir);
+                let dg = self.distribution.d(wm) * self.distribution.g(wo, wi);
+                if cos_theta_i * cos_theta_o > 0.0 {
+                    dg * reflectance / (4.0 * cos_theta_o).abs()
+                } else {
+                    let denom = dot(wi, wm) + dot(wo, wm) / etap;
+                    let denom = denom * denom * cos_theta_o;
+                    dg * (1.0 - reflectance)
+                        * (dot(wi, wm) * dot(wo, wm) / denom).abs()
+                        * refraction_scale(etap, mode)
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    pub fn pdf_local(&self, wo: Vec3, wi: Vec3) -> f64 {
+        match self.half_vector(wo, wi) {
+            Some((wm, etap)) => {
+                let reflectance = fr_dielectric(dot(wo, wm), self.ir);
+                let visible = self.distribution.visible_d(wo, wm);
+                if wi.z() * wo.z() > 0.0 {
+                    visible / (4.0 * dot(wo, wm).abs()) * reflectance
+                } else {
+                    let denom = dot(wi, wm) + dot(wo, wm) / etap;
+                    let dwm_dwi = dot(wi, wm).abs() / (denom * denom);
+                    visible * dwm_dwi * (1.0 - reflectance)
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    // Generalized half vector for reflection or refraction between wo and wi,
+    // oriented to +z, and the relative index crossed. None for smooth glass
+    // and for configurations no microfacet could produce.
+    fn half_vector(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, f64)> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let cos_theta_o = wo.z();
+        let cos_theta_i = wi.z();
+        if cos_theta_i == 0.0 || cos_theta_o == 0.0 {
+            return None;
+        }
+        let etap = if cos_theta_i * cos_theta_o > 0.0 {
+            1.0
+        } else if cos_theta_o > 0.0 {
+            self.ir
+        } else {
+            1.0 / self.ir
+        };
+        let wm = wi * etap + wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+        let mut wm = unit_vector(wm);
+        if wm.z() < 0.0 {
+            wm = -wm;
+        }
+        if dot(wm, wi) * cos_theta_i < 0.0 || dot(wm, wo) * cos_theta_o < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+}
+
+// Normal pointing out of the object regardless of which side was hit
+pub fn outward_normal(rec: &HitRecord) -> Vec3 {
+    if rec.front_face {
+        rec.normal
+    } else {
+        -rec.normal
+    }
+}
+
+pub fn make_rough_dielectric(ir: f64, roughness_x: f64, roughness_y: f64) -> RoughDielectric {
+    RoughDielectric {
+        ir,
+        distribution: make_trowbridge_reitz(roughness_x, roughness_y),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::sampler::make_independent_sampler;
+    use crate::sphere::make_sphere;
+    use crate::vec3::make_point;
+
+    // Scatters off the far side of a glass ball of radius 1 and returns the
+    // attenuation, along with the hit and the scattered ray
+    fn scatter_inside(glass: &Dielectric, r: Ray) -> (Color, HitRecord, Ray) {
+        let ball = make_sphere(make_point(0.0, 0.0, 0.0), 1.0, Box::new(glass.clone()));
+        let mut rec = HitRecord::default();
+        assert!(ball.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+        let mut attenuation = make_color(0.0, 0.0, 0.0);
+        let mut scattered = r;
+        let mut sampler = make_independent_sampler();
+        assert!(glass.scatter(r, &rec, &mut attenuation, &mut scattered, &mut sampler));
+        (attenuation, rec, scattered)
+    }
+
+    fn beer_lambert(absorption: Color, distance: f64) -> Color {
+        make_color(
+            (-absorption.x() * distance).exp(),
+            (-absorption.y() * distance).exp(),
+            (-absorption.z() * distance).exp(),
+        )
+    }
+
+    #[test]
+    fn it_absorbs_light_along_the_path_inside() {
+        let mut glass = make_dielectric(1.5);
+        glass.absorption = make_color(0.1, 0.5, 2.0);
+
+        // Straight through the middle, entering at x = -1 with a direction
+        // that isn't of unit length
+        let r = Ray {
+            origin: make_point(-1.0, 0.0, 0.0),
+            direction: Vec3(0.5, 0.0, 0.0),
+        };
+        let (attenuation, _, _) = scatter_inside(&glass, r);
+        let expected = beer_lambert(glass.absorption, 2.0);
+        assert!((attenuation - expected).length() < 1e-12);
+
+        // A chord steep enough to be trapped by total internal reflection.
+        // Each reflection is dimmed by the chord it ends, measured from the
+        // previous one.
+        let half_chord = (1.0_f64 - 0.9 * 0.9).sqrt();
+        let mut r = Ray {
+            origin: make_point(0.0, 0.9, 0.0),
+            direction: Vec3(2.0, 0.0, 0.0),
+        };
+        let mut distance = half_chord;
+        let mut bounce = 0;
+        while bounce < 4 {
+            let (attenuation, rec, scattered) = scatter_inside(&glass, r);
+            let expected = beer_lambert(glass.absorption, distance);
+            assert!((attenuation - expected).length() < 1e-12);
+            // Reflected back inside
+            assert!(dot(scattered.direction(), rec.normal) > 0.0);
+            r = scattered;
+            distance = 2.0 * half_chord;
+            bounce += 1;
+        }
+    }
+
+    #[test]
+    fn it_evaluates_rough_glass_both_ways_in_importance_mode() {
+        // Paths from the lights see the adjoint BSDF, the BSDF with its
+        // directions swapped, for reflection and refraction alike
+        let glass = make_rough_dielectric(1.5, 0.3, 0.3);
+        let directions = [
+            unit_vector(Vec3(0.3, -0.2, 0.9)),
+            unit_vector(Vec3(-0.5, 0.1, 0.6)),
+            unit_vector(Vec3(0.1, 0.4, -0.8)),
+            unit_vector(Vec3(-0.2, -0.6, -0.4)),
+        ];
+        let mut refractions = 0;
+        for wo in directions {
+            for wi in directions {
+                let forward = glass.eval_local(wo, wi, TransportMode::Radiance) / wi.z().abs();
+                let adjoint = glass.eval_local(wi, wo, TransportMode::Importance) / wo.z().abs();
+                assert!((forward - adjoint).abs() <= 1e-9 * forward.abs().max(1.0));
+                if wo.z() * wi.z() < 0.0 && forward > 0.0 {
+                    refractions += 1;
+                    // Radiance is squeezed entering the glass and spread out
+                    // leaving it
+                    let etap = if wo.z() > 0.0 { 1.5 } else { 1.0 / 1.5 };
+                    let f = glass.eval_local(wo, wi, TransportMode::Importance);
+                    assert!((forward * wi.z().abs() * etap * etap - f).abs() < 1e-9 * f);
+                }
+            }
+        }
+        assert!(refractions > 0);
+    }
 }
@@ -0,0 +1,145 @@
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::{make_isotropic, Material};
+use crate::ray::Ray;
+use crate::rtweekend::random_f64;
+use crate::vec3::{make_point, zero_vector, Point3};
+use crate::voxel_grid::VoxelGrid;
+
+// Participating medium, like smoke or a cloud, whose density comes from a
+// voxel grid stretched over a box. Rays are "hit" where they scatter inside
+// it, found by delta tracking: tentative collisions are drawn as if the box
+// were filled with the densest part of the medium (the majorant), and each
+// is kept with the ratio of the real density to that. The others are null
+// collisions that leave the ray unchanged. Scattering points carry a zero
+// normal, marking them as having no surface.
+pub struct HeterogeneousMedium {
+    bounds: Aabb,
+    grid: VoxelGrid,
+    // Extinction coefficient where the grid's density is 1
+    sigma_t: f64,
+    majorant: f64,
+    phase: Box<dyn Material>,
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if self.majorant <= 0.0 {
+            return false;
+        }
+        let Some(inside) = self.bounds.clip(r, ray_t) else {
+            return false;
+        };
+        let speed = r.direction().length();
+        let mut t = inside.min;
+        loop {
+            t -= (1.0 - random_f64()).ln() / (self.majorant * speed);
+            if t >= inside.max {
+                return false;
+            }
+            if random_f64() * self.majorant < self.sigma_t * self.density(r.at(t)) {
+                break;
+            }
+        }
+
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.normal = zero_vector();
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.mat = self.phase.clone();
+        rec.clear_extras();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+impl HeterogeneousMedium {
+    fn density(&self, p: Point3) -> f64 {
+        let local = |x: f64, i: Interval| (x - i.min) / i.size();
+        self.grid.lookup(make_point(
+            local(p.x(), self.bounds.x),
+            local(p.y(), self.bounds.y),
+            local(p.z(), self.bounds.z),
+        ))
+    }
+}
+
+// Medium filling `bounds` with extinction sigma_t times the grid's density,
+// scattering the fraction `albedo` of the light it stops equally in all
+// directions
+pub fn make_heterogeneous_medium(
+    bounds: Aabb,
+    grid: VoxelGrid,
+    sigma_t: f64,
+    albedo: Color,
+) -> HeterogeneousMedium {
+    let majorant = sigma_t * grid.max_value();
+    HeterogeneousMedium {
+        bounds,
+        grid,
+        sigma_t,
+        majorant,
+        phase: Box::new(make_isotropic(albedo)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_heterogeneous_medium;
+    use crate::aabb::make_aabb;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::ray::Ray;
+    use crate::vec3::{make_point, Vec3};
+    use crate::voxel_grid::VoxelGrid;
+
+    #[test]
+    fn it_transmits_light_by_beers_law() {
+        // Half of the grid at density 1 and half at 0.5, so most tentative
+        // collisions in the thin half are null ones
+        let mut values = vec![1.0; 4];
+        values.extend([0.5; 4]);
+        let grid = VoxelGrid {
+            nx: 2,
+            ny: 2,
+            nz: 2,
+            values,
+        };
+        let medium = make_heterogeneous_medium(
+            make_aabb(make_point(0.0, 0.0, 0.0), make_point(1.0, 1.0, 2.0)),
+            grid,
+            1.0,
+            make_color(1.0, 1.0, 1.0),
+        );
+
+        // Straight through the middle along z, where the density falls
+        // linearly from 1 to 0.5 between the cell centers
+        let r = Ray {
+            origin: make_point(0.5, 0.5, -1.0),
+            direction: Vec3(0.0, 0.0, 2.0),
+        };
+        let n = 20000;
+        let mut passed = 0;
+        let mut i = 0;
+        while i < n {
+            let mut rec = HitRecord::default();
+            if !medium.hit(&r, interval::new(0.0, f64::INFINITY), &mut rec) {
+                passed += 1;
+            }
+            i += 1;
+        }
+        let optical_depth = 0.5 * 1.0 + 1.0 * 0.75 + 0.5 * 0.5;
+        let expected = f64::exp(-optical_depth);
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.015);
+    }
+}
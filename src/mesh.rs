@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use crate::color::{make_color, Color};
+use crate::rtweekend::degrees_to_radians;
+use crate::vec3::{cross, dot, make_point, unit_vector, zero_vector, Point3, Vec3};
+
+// Triangles over a shared list of vertices. Colors, one per vertex, are
+// empty when the file has none. Normals are given for each corner of each
+// triangle so that they can change across creases, and are empty for flat
+// shading.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub colors: Vec<Color>,
+    pub faces: Vec<[usize; 3]>,
+    pub normals: Vec<[Vec3; 3]>,
+}
+
+impl TriangleMesh {
+    // Sets every corner's normal to the average over the triangles around
+    // its vertex, each weighted by its angle there, leaving out those
+    // turned more than max_angle degrees from the corner's own triangle so
+    // creases stay sharp
+    pub fn smooth_normals(&mut self, max_angle: f64) {
+        let face_normals: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|f| {
+                let [a, b, c] = f.map(|i| self.positions[i]);
+                let n = cross(b - a, c - a);
+                if n.near_zero() {
+                    zero_vector()
+                } else {
+                    unit_vector(n)
+                }
+            })
+            .collect();
+        // The triangles at each vertex with their angles there
+        let mut around = vec![Vec::new(); self.positions.len()];
+        for (index, face) in self.faces.iter().enumerate() {
+            let mut k = 0;
+            while k < 3 {
+                let p = self.positions[face[k]];
+                let e1 = self.positions[face[(k + 1) % 3]] - p;
+                let e2 = self.positions[face[(k + 2) % 3]] - p;
+                let angle = if e1.near_zero() || e2.near_zero() {
+                    0.0
+                } else {
+                    dot(unit_vector(e1), unit_vector(e2))
+                        .clamp(-1.0, 1.0)
+                        .acos()
+                };
+                around[face[k]].push((index, angle));
+                k += 1;
+            }
+        }
+
+        let min_cos = degrees_to_radians(max_angle).cos();
+        self.normals = self
+            .faces
+            .iter()
+            .enumerate()
+            .map(|(index, face)| {
+                let own = face_normals[index];
+                face.map(|vertex| {
+                    let mut sum = zero_vector();
+                    for (other, angle) in &around[vertex] {
+                        if dot(own, face_normals[*other]) >= min_cos {
+                            sum += *angle * face_normals[*other];
+                        }
+                    }
+                    if sum.near_zero() {
+                        own
+                    } else {
+                        unit_vector(sum)
+                    }
+                })
+            })
+            .collect();
+    }
+}
+
+// Loads a PLY or STL file, in text or binary form. Only PLY carries vertex
+// normals and colors; STL files list each triangle's corners separately,
+// and corners at the same position are joined into one vertex so that
+// normals can be smoothed across them.
+pub fn load_mesh(path: &str) -> io::Result<TriangleMesh> {
+    let bytes = fs::read(path)?;
+    let mesh = if bytes.starts_with(b"ply") {
+        parse_ply(&bytes)?
+    } else if is_binary_stl(&bytes) {
+        parse_binary_stl(&bytes)?
+    } else if bytes.starts_with(b"solid") {
+        parse_ascii_stl(&bytes)?
+    } else {
+        return Err(invalid_data(&format!("{path} is not a PLY or STL file")));
+    };
+    if mesh.faces.is_empty() {
+        return Err(invalid_data(&format!("{path} has no triangles")));
+    }
+    Ok(mesh)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Scalar> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(invalid_data(&format!("unknown PLY type {name}"))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // What a color channel of this type is divided by to bring it to [0, 1]
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::I8 | Scalar::U8 => 255.0,
+            Scalar::I16 | Scalar::U16 => 65535.0,
+            Scalar::I32 | Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Scalar,
+    // Type of the length in front of list properties
+    count: Option<Scalar>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// The data after a PLY header, read one value at a time
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        pos: usize,
+        little_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    // How many more values of this type the data can hold, when known
+    fn remaining(&self, kind: Scalar) -> Option<usize> {
+        match self {
+            Body::Ascii(_) => None,
+            Body::Binary { bytes, pos, .. } => Some((bytes.len() - *pos) / kind.size()),
+        }
+    }
+
+    fn read(&mut self, kind: Scalar) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => tokens
+                .next()
+                .ok_or_else(|| invalid_data("truncated PLY data"))?
+                .parse::<f64>()
+                .map_err(|_| invalid_data("bad number in PLY data")),
+            Body::Binary {
+                bytes,
+                pos,
+                little_endian,
+            } => {
+                let size = kind.size();
+                let Some(raw) = bytes.get(*pos..*pos + size) else {
+                    return Err(invalid_data("truncated PLY data"));
+                };
+                *pos += size;
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(raw);
+                if !*little_endian {
+                    b[..size].reverse();
+                }
+                Ok(match kind {
+                    Scalar::I8 => b[0] as i8 as f64,
+                    Scalar::U8 => b[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    let mut pos = 0;
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let start = pos;
+        while pos < bytes.len() && bytes[pos] != b'\n' {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return Err(invalid_data("unexpected end of PLY header"));
+        }
+        let line = std::str::from_utf8(&bytes[start..pos])
+            .map_err(|_| invalid_data("PLY header is not text"))?;
+        pos += 1;
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", name, _] => format = Some(name.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("bad PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid_data("PLY property outside an element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    kind: Scalar::parse(kind)?,
+                    count: Some(Scalar::parse(count)?),
+                }),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid_data("PLY property outside an element"))?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    kind: Scalar::parse(kind)?,
+                    count: None,
+                }),
+            _ => {}
+        }
+    }
+
+    let mut body = match format.as_deref() {
+        Some("ascii") => Body::Ascii(
+            std::str::from_utf8(&bytes[pos..])
+                .map_err(|_| invalid_data("PLY data is not text"))?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => Body::Binary {
+            bytes,
+            pos,
+            little_endian: true,
+        },
+        Some("binary_big_endian") => Body::Binary {
+            bytes,
+            pos,
+            little_endian: false,
+        },
+        _ => return Err(invalid_data("unknown PLY format")),
+    };
+
+    let mut positions = Vec::new();
+    let mut vertex_normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        let find = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let xyz = [find("x"), find("y"), find("z")];
+        let nxyz = [find("nx"), find("ny"), find("nz")];
+        let rgb = [find("red"), find("green"), find("blue")];
+        let indices = find("vertex_indices").or(find("vertex_index"));
+
+        let mut i = 0;
+        while i < element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            let mut list = Vec::new();
+            for (index, property) in element.properties.iter().enumerate() {
+                match property.count {
+                    Some(count) => {
+                        let n = body.read(count)?;
+                        if n < 0.0 || n.fract() != 0.0 {
+                            return Err(invalid_data("bad PLY list length"));
+                        }
+                        let n = n as usize;
+                        // Text gives no bound short of reading the values
+                        let mut items = match body.remaining(property.kind) {
+                            Some(left) if n > left => {
+                                return Err(invalid_data("truncated PLY data"))
+                            }
+                            Some(_) => Vec::with_capacity(n),
+                            None => Vec::new(),
+                        };
+                        while items.len() < n {
+                            items.push(body.read(property.kind)?);
+                        }
+                        if Some(index) == indices {
+                            list = items;
+                        }
+                        values.push(0.0);
+                    }
+                    None => values.push(body.read(property.kind)?),
+                }
+            }
+
+            let get = |at: [Option<usize>; 3]| match at {
+                [Some(a), Some(b), Some(c)] => Some([a, b, c].map(|i| values[i])),
+                _ => None,
+            };
+            if element.name == "vertex" {
+                let [x, y, z] =
+                    get(xyz).ok_or_else(|| invalid_data("PLY vertex without x, y, z"))?;
+                positions.push(make_point(x, y, z));
+                if let Some([x, y, z]) = get(nxyz) {
+                    vertex_normals.push(Vec3(x, y, z));
+                }
+                if let Some(c) = get(rgb) {
+                    let scale = element.properties[rgb[0].unwrap_or(0)].kind.color_scale();
+                    colors.push(make_color(c[0], c[1], c[2]) / scale);
+                }
+            } else if element.name == "face" {
+                if list.iter().any(|v| *v < 0.0 || v.fract() != 0.0) {
+                    return Err(invalid_data("bad PLY vertex index"));
+                }
+                // Polygons are split into a fan of triangles
+                let mut k = 2;
+                while k < list.len() {
+                    faces.push([list[0], list[k - 1], list[k]].map(|v| v as usize));
+                    k += 1;
+                }
+            }
+            i += 1;
+        }
+    }
+
+    if faces.iter().flatten().any(|v| *v >= positions.len()) {
+        return Err(invalid_data("PLY face refers to a missing vertex"));
+    }
+    let normals = if vertex_normals.is_empty() {
+        Vec::new()
+    } else {
+        faces
+            .iter()
+            .map(|f: &[usize; 3]| f.map(|v| unit_vector(vertex_normals[v])))
+            .collect()
+    };
+    Ok(TriangleMesh {
+        positions,
+        colors,
+        faces,
+        normals,
+    })
+}
+
+// A binary STL is an 80 byte header, a triangle count and 50 bytes per
+// triangle; text files can start with "solid" too, so the size decides
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    if bytes.len() < 84 {
+        return false;
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    bytes.len() == 84 + 50 * count
+}
+
+fn parse_binary_stl(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let mut corners = Vec::with_capacity(3 * count);
+    let mut i = 0;
+    while i < count {
+        // Each triangle's normal comes first and is ignored
+        let start = 84 + 50 * i + 12;
+        let mut k = 0;
+        while k < 3 {
+            let at = |j: usize| {
+                let b = &bytes[start + 12 * k + 4 * j..];
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+            };
+            corners.push(make_point(at(0), at(1), at(2)));
+            k += 1;
+        }
+        i += 1;
+    }
+    Ok(weld(&corners))
+}
+
+fn parse_ascii_stl(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid_data("STL file is not text"))?;
+    let mut tokens = text.split_ascii_whitespace();
+    let mut corners = Vec::new();
+    while let Some(token) = tokens.next() {
+        if token == "vertex" {
+            let mut xyz = [0.0; 3];
+            for value in xyz.iter_mut() {
+                *value = tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .ok_or_else(|| invalid_data("bad STL vertex"))?;
+            }
+            corners.push(make_point(xyz[0], xyz[1], xyz[2]));
+        }
+    }
+    if corners.len() % 3 != 0 {
+        return Err(invalid_data("STL facet without three vertices"));
+    }
+    Ok(weld(&corners))
+}
+
+// Joins corners at exactly the same position into shared vertices
+fn weld(corners: &[Point3]) -> TriangleMesh {
+    let mut positions = Vec::new();
+    let mut index_of = HashMap::new();
+    let indices: Vec<usize> = corners
+        .iter()
+        .map(|p| {
+            let key = [p.x().to_bits(), p.y().to_bits(), p.z().to_bits()];
+            *index_of.entry(key).or_insert_with(|| {
+                positions.push(*p);
+                positions.len() - 1
+            })
+        })
+        .collect();
+    TriangleMesh {
+        positions,
+        colors: Vec::new(),
+        faces: indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect(),
+        normals: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit cube as four-sided faces, wound outwards
+    const CORNERS: [[f64; 3]; 8] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 1.0],
+        [1.0, 1.0, 1.0],
+        [0.0, 1.0, 1.0],
+    ];
+    const SIDES: [[usize; 4]; 6] = [
+        [0, 3, 2, 1],
+        [4, 5, 6, 7],
+        [0, 1, 5, 4],
+        [2, 3, 7, 6],
+        [1, 2, 6, 5],
+        [0, 4, 7, 3],
+    ];
+
+    fn check_cube(mesh: &TriangleMesh) {
+        assert_eq!(mesh.positions.len(), 8);
+        assert_eq!(mesh.faces.len(), 12);
+        // Every triangle's normal points away from the center
+        for face in &mesh.faces {
+            let [a, b, c] = face.map(|i| mesh.positions[i]);
+            let center = make_point(0.5, 0.5, 0.5);
+            assert!(dot(cross(b - a, c - a), a - center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn it_reads_the_same_cube_from_every_format() {
+        // Text PLY with a color per vertex
+        let mut text = String::from("ply\nformat ascii 1.0\ncomment a cube\nelement vertex 8\n");
+        text += "property float x\nproperty float y\nproperty float z\n";
+        text += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+        text += "element face 6\nproperty list uchar int vertex_indices\nend_header\n";
+        for c in CORNERS {
+            text += &format!("{} {} {} 255 0 51\n", c[0], c[1], c[2]);
+        }
+        for s in SIDES {
+            text += &format!("4 {} {} {} {}\n", s[0], s[1], s[2], s[3]);
+        }
+        let mesh = parse_ply(text.as_bytes()).unwrap();
+        check_cube(&mesh);
+        assert!((mesh.colors[3].z() - 0.2).abs() < 1e-12);
+
+        // Big endian binary PLY with normals and an extra element
+        let mut bytes = b"ply\nformat binary_big_endian 1.0\nelement vertex 8\n".to_vec();
+        bytes.extend(b"property double x\nproperty double y\nproperty double z\n");
+        bytes.extend(b"property float nx\nproperty float ny\nproperty float nz\n");
+        bytes.extend(b"element face 6\nproperty list uchar uint vertex_index\n");
+        bytes.extend(b"element edge 1\nproperty int vertex1\nproperty int vertex2\nend_header\n");
+        for c in CORNERS {
+            for x in c {
+                bytes.extend(x.to_be_bytes());
+            }
+            for x in c {
+                bytes.extend((x as f32 * 2.0 - 1.0).to_be_bytes());
+            }
+        }
+        for s in SIDES {
+            bytes.push(4);
+            for v in s {
+                bytes.extend((v as u32).to_be_bytes());
+            }
+        }
+        bytes.extend(0_i32.to_be_bytes());
+        bytes.extend(1_i32.to_be_bytes());
+        let mesh = parse_ply(&bytes).unwrap();
+        check_cube(&mesh);
+        assert!(mesh.colors.is_empty());
+        assert!((mesh.normals[0][0].x() + 1.0 / 3.0_f64.sqrt()).abs() < 1e-6);
+
+        // Text and binary STL, whose corners are joined back together
+        let mut text = String::from("solid cube\n");
+        let mut bytes = vec![0u8; 80];
+        bytes.extend(12_u32.to_le_bytes());
+        for s in SIDES {
+            for triangle in [[s[0], s[1], s[2]], [s[0], s[2], s[3]]] {
+                text += "facet normal 0 0 0\nouter loop\n";
+                bytes.extend([0u8; 12]);
+                for v in triangle {
+                    let c = CORNERS[v];
+                    text += &format!("vertex {} {} {}\n", c[0], c[1], c[2]);
+                    for x in c {
+                        bytes.extend((x as f32).to_le_bytes());
+                    }
+                }
+                text += "endloop\nendfacet\n";
+                bytes.extend([0u8; 2]);
+            }
+        }
+        text += "endsolid cube\n";
+        assert!(!is_binary_stl(text.as_bytes()) && is_binary_stl(&bytes));
+        check_cube(&parse_ascii_stl(text.as_bytes()).unwrap());
+        let mut mesh = parse_binary_stl(&bytes).unwrap();
+        check_cube(&mesh);
+
+        // The cube's edges are sharper than 60 degrees but not 100
+        mesh.smooth_normals(60.0);
+        let [a, b, c] = mesh.faces[0].map(|i| mesh.positions[i]);
+        let flat = unit_vector(cross(b - a, c - a));
+        assert!(mesh.normals[0].iter().all(|n| dot(*n, flat) > 1.0 - 1e-12));
+        mesh.smooth_normals(100.0);
+        let corner = mesh.normals[0][0];
+        let diagonal = 1.0 / 3.0_f64.sqrt();
+        assert!([corner.x(), corner.y(), corner.z()]
+            .iter()
+            .all(|x| (x.abs() - diagonal).abs() < 1e-9));
+    }
+
+    #[test]
+    fn it_rejects_malformed_ply_lists() {
+        let header = "element vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                      element face 1\nproperty list uint int vertex_indices\nend_header\n";
+        // A binary list far longer than the data left fails without
+        // reserving room for it first
+        let mut bytes = format!("ply\nformat binary_little_endian 1.0\n{header}").into_bytes();
+        for x in [0.0_f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend(0_i32.to_le_bytes());
+        assert!(parse_ply(&bytes).is_err());
+
+        // Negative indices are not wrapped around to the first vertex
+        let mut text = format!("ply\nformat ascii 1.0\n{header}");
+        text += "0 0 0\n1 0 0\n0 1 0\n3 -1 1 2\n";
+        assert!(parse_ply(text.as_bytes()).is_err());
+        assert!(parse_ply(text.replace("-1", "0").as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,291 @@
+use std::ops;
+
+use crate::color::Color;
+use crate::rtweekend::PI;
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+// Trowbridge-Reitz (GGX) microfacet distribution. All directions are in the
+// local shading frame where the macro surface normal is +z.
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    // Below this roughness the surface is treated as a perfect mirror
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos2_theta = wm.z() * wm.z();
+        let sin2_theta = (1.0 - cos2_theta).max(0.0);
+        let tan2_theta = sin2_theta / cos2_theta;
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0.0;
+        }
+        let cos4_theta = cos2_theta * cos2_theta;
+        let (cos2_phi, sin2_phi) = cos2_sin2_phi(wm);
+        let e = tan2_theta
+            * (cos2_phi / (self.alpha_x * self.alpha_x) + sin2_phi / (self.alpha_y * self.alpha_y));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos4_theta * (1.0 + e) * (1.0 + e))
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2_theta = w.z() * w.z();
+        let sin2_theta = (1.0 - cos2_theta).max(0.0);
+        let tan2_theta = sin2_theta / cos2_theta;
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0.0;
+        }
+        let (cos2_phi, sin2_phi) = cos2_sin2_phi(w);
+        let alpha2 =
+            cos2_phi * self.alpha_x * self.alpha_x + sin2_phi * self.alpha_y * self.alpha_y;
+        ((1.0 + alpha2 * tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    // Smith masking for one direction
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated Smith masking-shadowing
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of normals visible from w
+    pub fn visible_d(&self, w: Vec3, wm: Vec3) -> f64 {
+        self.g1(w) / w.z().abs() * self.d(wm) * dot(w, wm).abs()
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible
+    // from w (Heitz 2018)
+    pub fn sample_wm(&self, w: Vec3, u: (f64, f64)) -> Vec3 {
+        let mut wh = unit_vector(Vec3(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()));
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z() < 0.99999 {
+            unit_vector(cross(Vec3(0.0, 0.0, 1.0), wh))
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(wh, t1);
+
+        // Uniform disk sample, warped to the projection of the visible hemisphere
+        let r = u.0.sqrt();
+        let theta = 2.0 * PI * u.1;
+        let px = r * theta.cos();
+        let mut py = r * theta.sin();
+        let h = (1.0 - px * px).sqrt();
+        let t = (1.0 + wh.z()) / 2.0;
+        py = (1.0 - t) * h + t * py;
+
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        unit_vector(Vec3(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+}
+
+fn cos2_sin2_phi(w: Vec3) -> (f64, f64) {
+    let sin2_theta = w.x() * w.x() + w.y() * w.y();
+    if sin2_theta == 0.0 {
+        return (1.0, 0.0);
+    }
+    (w.x() * w.x() / sin2_theta, w.y() * w.y() / sin2_theta)
+}
+
+// Roughness in [0, 1] is squared into alpha so that it reads perceptually linear
+pub fn make_trowbridge_reitz(roughness_x: f64, roughness_y: f64) -> TrowbridgeReitz {
+    TrowbridgeReitz {
+        alpha_x: (roughness_x * roughness_x).max(1e-4),
+        alpha_y: (roughness_y * roughness_y).max(1e-4),
+    }
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface; eta is the
+// relative index of refraction across it and cos_theta_i may be negative
+// when arriving from the inside.
+pub fn fr_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+#[derive(Clone, Copy)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl ops::Add<Complex> for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Self::Output {
+        Complex {
+            re: self.re + rhs.re,
+            im: self.im + rhs.im,
+        }
+    }
+}
+
+impl ops::Sub<Complex> for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Self::Output {
+        Complex {
+            re: self.re - rhs.re,
+            im: self.im - rhs.im,
+        }
+    }
+}
+
+impl ops::Mul<Complex> for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Self::Output {
+        Complex {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.re * rhs.im + self.im * rhs.re,
+        }
+    }
+}
+
+impl ops::Div<Complex> for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Self::Output {
+        let scale = 1.0 / (rhs.re * rhs.re + rhs.im * rhs.im);
+        Complex {
+            re: scale * (self.re * rhs.re + self.im * rhs.im),
+            im: scale * (self.im * rhs.re - self.re * rhs.im),
+        }
+    }
+}
+
+impl Complex {
+    // Squared magnitude
+    pub fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn sqrt(self) -> Complex {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return real(0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex { re: t1, im: t2 }
+        } else {
+            Complex {
+                re: t2.abs(),
+                im: t1.copysign(self.im),
+            }
+        }
+    }
+
+    pub fn exp(self) -> Complex {
+        let scale = self.re.exp();
+        Complex {
+            re: scale * self.im.cos(),
+            im: scale * self.im.sin(),
+        }
+    }
+}
+
+pub fn real(re: f64) -> Complex {
+    Complex { re, im: 0.0 }
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction eta + i k
+fn fr_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let eta = Complex { re: eta, im: k };
+    let sin2_theta_i = real(1.0 - cos_theta_i * cos_theta_i);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    let cos_theta_t = (real(1.0) - sin2_theta_t).sqrt();
+    let cos_i = real(cos_theta_i);
+    let r_parl = (eta * cos_i - cos_theta_t) / (eta * cos_i + cos_theta_t);
+    let r_perp = (cos_i - eta * cos_theta_t) / (cos_i + eta * cos_theta_t);
+    (r_parl.norm() + r_perp.norm()) / 2.0
+}
+
+pub fn fr_complex_color(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Vec3(
+        fr_complex(cos_theta_i, eta.x(), k.x()),
+        fr_complex(cos_theta_i, eta.y(), k.y()),
+        fr_complex(cos_theta_i, eta.z(), k.z()),
+    )
+}
+
+// Refracts w about n for relative index eta, flipping to the other side when
+// w arrives from below n. Returns the transmitted direction and the relative
+// index that was actually crossed, or None on total internal reflection.
+pub fn refract_local(w: Vec3, n: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let mut cos_theta_i = dot(n, w);
+    let mut eta = eta;
+    let mut n = n;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+        n = -n;
+    }
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some((-w / eta + (cos_theta_i / eta - cos_theta_t) * n, eta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_normalizes_projected_microfacet_area() {
+        // The projected area of the microfacets integrates to one
+        let distribution = make_trowbridge_reitz(0.5, 0.8);
+        let n = 400;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let cos_theta = (i as f64 + 0.5) / n as f64;
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let wm = Vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                sum += distribution.d(wm) * cos_theta;
+            }
+        }
+        let integral = sum * 2.0 * PI / (n * n) as f64;
+        assert!((integral - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn it_reflects_everything_from_a_perfect_conductor() {
+        let reflectance = fr_complex(0.7, 0.0, 1e6);
+        assert!((reflectance - 1.0).abs() < 1e-6);
+        assert!((fr_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::vec3::{cross, dot, unit_vector, Vec3};
+
+// Orthonormal basis around w, used to move directions in and out of a local
+// shading frame where w is the z axis.
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
+}
+
+// Builds a basis from a unit vector without branching on its largest axis
+// (Duff et al. 2017), so neighbouring normals get neighbouring tangents.
+pub fn make_onb(n: Vec3) -> Onb {
+    let sign = 1.0_f64.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    let u = Vec3(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x());
+    let v = Vec3(b, sign + n.y() * n.y() * a, -n.y());
+    Onb { u, v, w: n }
+}
+
+// Basis around n whose u axis follows the surface tangent, so anisotropic
+// lobes line up with the surface. Falls back to make_onb where the tangent
+// is missing or lies along n.
+pub fn make_tangent_onb(n: Vec3, tangent: Vec3) -> Onb {
+    let along = tangent - dot(tangent, n) * n;
+    if along.near_zero() {
+        return make_onb(n);
+    }
+    let u = unit_vector(along);
+    Onb {
+        u,
+        v: cross(n, u),
+        w: n,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_turns_u_along_the_tangent() {
+        let n = unit_vector(Vec3(0.0, 1.0, 1.0));
+        // The part of the tangent along n is dropped
+        let frame = make_tangent_onb(n, Vec3(2.0, 0.0, 0.0) + 3.0 * n);
+        assert!((frame.u - Vec3(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((cross(frame.u, frame.v) - n).length() < 1e-12);
+        // Without a tangent any basis around n will do
+        let fallback = make_tangent_onb(n, Vec3(0.0, 0.0, 0.0));
+        assert!(dot(fallback.u, n).abs() < 1e-12 && dot(fallback.v, n).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,395 @@
+use std::cell::RefCell;
+
+use crate::background::make_background_emitter;
+use crate::color::{make_color, Color};
+use crate::film::Film;
+use crate::hit_record::HitRecord;
+use crate::integrator::{occluded, scatter, Integrator, Scene};
+use crate::interval;
+use crate::kdtree::{make_kd_tree, KdItem, KdTree};
+use crate::material::TransportMode;
+use crate::ray::Ray;
+use crate::rtweekend::PI;
+use crate::sampler::{make_independent_sampler, Sampler};
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// How quickly the gather radius shrinks from pass to pass, between 0 and 1.
+// Smaller values shrink it faster, trading noise for less blur.
+const ALPHA: f64 = 2.0 / 3.0;
+
+// Progressive photon mapping (Knaus and Zwicker's formulation). Every pass
+// shoots a fresh batch of photons from the lights and the background and
+// stores them where they land on non-specular surfaces. Camera rays follow
+// mirrors and glass to the first non-specular surface and estimate the light
+// leaving it from the photons within the gather radius. Each pass on its own
+// is ordinary, blurry photon mapping; the radius shrinks a little with every
+// pass, so that the average over passes converges while only one pass's
+// photons are ever kept. Participating media are treated like glass: a
+// volume estimate would need the medium's density at the gather point, so
+// photons and camera rays both just scatter on through them.
+//
+// Photons from directional lights and the background start on a disk around
+// the bounded part of the scene and never reach what lies outside the
+// cylinder it sweeps out, such as the far parts of an infinite plane. Light
+// arriving straight from them in such directions is sampled at the gather
+// point instead.
+pub struct PhotonIntegrator {
+    photons_per_pass: i32,
+    initial_radius: f64,
+    pass: RefCell<Option<PhotonPass>>,
+}
+
+struct PhotonPass {
+    photons: KdTree<Photon>,
+    radius: f64,
+}
+
+struct Photon {
+    p: Point3,
+    // Unit direction back towards where the photon came from
+    wi: Vec3,
+    power: Color,
+}
+
+impl KdItem for Photon {
+    fn position(&self) -> Point3 {
+        self.p
+    }
+}
+
+impl Integrator for PhotonIntegrator {
+    fn start_pass(&self, scene: &Scene, pass: i32, max_depth: i32) {
+        let mut radius_squared = self.initial_radius * self.initial_radius;
+        let mut i = 1;
+        while i <= pass {
+            radius_squared *= (i as f64 + ALPHA) / (i as f64 + 1.0);
+            i += 1;
+        }
+        let photons = trace_photons(scene, self.photons_per_pass, max_depth);
+        *self.pass.borrow_mut() = Some(PhotonPass {
+            photons: make_kd_tree(photons),
+            radius: radius_squared.sqrt(),
+        });
+    }
+
+    fn li(
+        &self,
+        r: Ray,
+        scene: &Scene,
+        max_depth: i32,
+        sampler: &mut dyn Sampler,
+        _film: &mut Film,
+    ) -> Color {
+        let pass = self.pass.borrow();
+        let Some(pass) = pass.as_ref() else {
+            return make_color(0.0, 0.0, 0.0);
+        };
+
+        let mut color = make_color(0.0, 0.0, 0.0);
+        let mut throughput = make_color(1.0, 1.0, 1.0);
+        let mut ray = r;
+        let mut depth = 0;
+        while depth < max_depth {
+            let mut rec = HitRecord::default();
+            if !scene
+                .world
+                .hit(&ray, interval::new(0.001, f64::INFINITY), &mut rec)
+            {
+                color += throughput * scene.background.value(ray.direction());
+                break;
+            }
+            color += throughput * rec.mat.emitted(&rec);
+
+            let Some((scattered, attenuation)) = scatter(&ray, &rec, sampler) else {
+                break;
+            };
+            if on_surface(&rec) && rec.mat.scattering_pdf(&ray, &rec, &scattered) > 0.0 {
+                color += throughput * gather(pass, &ray, &rec);
+                color += throughput * direct_beyond_photons(&ray, &rec, scene, sampler);
+                break;
+            }
+            throughput *= attenuation;
+            ray = scattered;
+            depth += 1;
+        }
+        color
+    }
+}
+
+pub fn make_photon_integrator(photons_per_pass: i32, initial_radius: f64) -> PhotonIntegrator {
+    PhotonIntegrator {
+        photons_per_pass: photons_per_pass.max(1),
+        initial_radius,
+        pass: RefCell::new(None),
+    }
+}
+
+// Follows `count` photon paths from lights chosen uniformly, the background
+// counting as one of them. Each path carries its share of the emitted power.
+fn trace_photons(scene: &Scene, count: i32, max_depth: i32) -> Vec<Photon> {
+    let (center, radius) = scene.bounding_sphere();
+    let background = make_background_emitter(scene.background, center, radius);
+    let light_count = scene.lights.lights.len() + 1;
+    let light_choice_pdf = 1.0 / light_count as f64;
+    let mut sampler = make_independent_sampler();
+
+    let mut photons = Vec::new();
+    let mut path = 0;
+    while path < count {
+        path += 1;
+        let index = ((sampler.get_1d() * light_count as f64) as usize).min(light_count - 1);
+        let u1 = sampler.get_2d();
+        let u2 = sampler.get_2d();
+        let emission = match scene.lights.lights.get(index) {
+            Some(light) => light.sample_le(u1, u2, center, radius),
+            None => background.sample_le(u1, u2),
+        };
+        let Some(emission) = emission else {
+            continue;
+        };
+        if emission.pdf_pos == 0.0 || emission.pdf_dir == 0.0 {
+            continue;
+        }
+        let direction = unit_vector(emission.ray.direction());
+        // Light from infinitely far away has to get to the disk first, past
+        // anything that reaches out of the scene such as an infinite plane
+        let infinite = scene
+            .lights
+            .lights
+            .get(index)
+            .is_none_or(|light| light.is_infinite());
+        let from_afar = Ray {
+            origin: emission.ray.origin(),
+            direction: -direction,
+        };
+        if infinite && occluded(&from_afar, f64::INFINITY, scene.world) {
+            continue;
+        }
+        let mut power = emission.radiance * dot(emission.normal, direction).abs()
+            / (light_choice_pdf * emission.pdf_pos * emission.pdf_dir * count as f64);
+        let mut ray = Ray {
+            origin: emission.ray.origin(),
+            direction,
+        };
+
+        let mut depth = 0;
+        while depth < max_depth {
+            let mut rec = HitRecord::default();
+            if !scene
+                .world
+                .hit(&ray, interval::new(0.001, f64::INFINITY), &mut rec)
+            {
+                break;
+            }
+            // Nothing can block light starting inside such a shape, but it
+            // meets the shape's surface from behind first
+            if infinite && depth == 0 && on_surface(&rec) && !rec.front_face {
+                break;
+            }
+            // Photons carry power, which refraction doesn't scale
+            rec.transport = TransportMode::Importance;
+            let Some((scattered, attenuation)) = scatter(&ray, &rec, &mut sampler) else {
+                break;
+            };
+            if on_surface(&rec) && rec.mat.scattering_pdf(&ray, &rec, &scattered) > 0.0 {
+                photons.push(Photon {
+                    p: rec.p,
+                    wi: -unit_vector(ray.direction()),
+                    power,
+                });
+            }
+            power *= attenuation;
+            ray = scattered;
+            depth += 1;
+        }
+    }
+    photons
+}
+
+// Density estimate of the light leaving the hit point back along r
+fn gather(pass: &PhotonPass, r: &Ray, rec: &HitRecord) -> Color {
+    let mut sum = make_color(0.0, 0.0, 0.0);
+    pass.photons
+        .for_each_within(rec.p, pass.radius, &mut |photon: &Photon| {
+            let cosine = dot(rec.normal, photon.wi).abs();
+            if cosine == 0.0 {
+                return;
+            }
+            let incoming = Ray {
+                origin: rec.p,
+                direction: photon.wi,
+            };
+            // eval includes the cosine, which the photon's power already has
+            sum += rec.mat.eval(r, rec, &incoming) * photon.power / cosine;
+        });
+    sum / (PI * pass.radius * pass.radius)
+}
+
+// Light sampled straight from each directional light and the background,
+// for the directions from which their photons can't reach the hit point
+fn direct_beyond_photons(
+    r: &Ray,
+    rec: &HitRecord,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let (center, radius) = scene.bounding_sphere();
+    let background = make_background_emitter(scene.background, center, radius);
+    let mut direct = make_color(0.0, 0.0, 0.0);
+    let mut add = |wi: Vec3, radiance: Color, pdf: f64| {
+        if background.pdf_position(rec.p, -wi) > 0.0 {
+            return;
+        }
+        let shadow_ray = Ray {
+            origin: rec.p,
+            direction: wi,
+        };
+        let f = rec.mat.eval(r, rec, &shadow_ray);
+        if f.near_zero() || occluded(&shadow_ray, f64::INFINITY, scene.world) {
+            return;
+        }
+        direct += f * radiance / pdf;
+    };
+    for light in scene.lights.lights.iter() {
+        let u = sampler.get_2d();
+        if !light.is_infinite() {
+            continue;
+        }
+        if let Some(sample) = light.sample_li(rec.p, u) {
+            add(sample.wi, sample.radiance, sample.pdf);
+        }
+    }
+    if let Some((wi, pdf)) = background.sample_direction(sampler.get_2d()) {
+        add(wi, scene.background.value(wi), pdf);
+    }
+    direct
+}
+
+// Media mark their scattering points with a zero normal
+fn on_surface(rec: &HitRecord) -> bool {
+    rec.normal.length_squared() > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::make_gradient_sky;
+    use crate::bvh::make_bvh;
+    use crate::camera::Camera;
+    use crate::film::make_film;
+    use crate::hittable::Hittable;
+    use crate::light::{make_directional_light, make_light_list};
+    use crate::material::make_lambertian;
+    use crate::plane::make_plane;
+    use crate::quadric::make_disk;
+    use crate::sampler::make_stratified_sampler;
+    use crate::sphere::make_sphere;
+    use crate::vec3::make_point;
+
+    #[test]
+    fn it_estimates_the_light_on_an_evenly_lit_plane() {
+        // A white disk under light falling straight down, seen from above
+        let disk = make_disk(
+            make_point(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+        );
+        let mut lights = make_light_list();
+        lights.add(Box::new(make_directional_light(
+            Vec3(0.0, -1.0, 0.0),
+            make_color(2.0, 2.0, 2.0),
+        )));
+        let background = make_gradient_sky(make_color(0.0, 0.0, 0.0), make_color(0.0, 0.0, 0.0));
+        let camera = Camera::default();
+        let scene = Scene {
+            world: &disk,
+            lights: &lights,
+            background: &background,
+            camera: &camera,
+        };
+        let pass = PhotonPass {
+            photons: make_kd_tree(trace_photons(&scene, 200000, 5)),
+            radius: 0.25,
+        };
+        // Averaged over gather points far enough apart not to share photons
+        let mut sum = 0.0;
+        for x in [-0.5, 0.0, 0.5] {
+            for z in [-0.5, 0.0, 0.5] {
+                let r = Ray {
+                    origin: make_point(x, 1.0, z),
+                    direction: Vec3(0.0, -1.0, 0.0),
+                };
+                let mut rec = HitRecord::default();
+                assert!(disk.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+                sum += gather(&pass, &r, &rec).y();
+            }
+        }
+        // albedo / pi * irradiance
+        let expected = 0.5 / PI * 2.0;
+        assert!((sum / 9.0 - expected).abs() < 0.03 * expected);
+    }
+
+    #[test]
+    fn it_lights_ground_the_photons_cannot_reach() {
+        // An infinite white plane under a gray sky and light falling straight
+        // down, with a ball on it to give the photons somewhere to go
+        let world = make_bvh(vec![
+            Box::new(make_plane(
+                make_point(0.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )),
+            Box::new(make_sphere(
+                make_point(0.0, 1.0, 0.0),
+                1.0,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )),
+        ]);
+        let mut lights = make_light_list();
+        lights.add(Box::new(make_directional_light(
+            Vec3(0.0, -1.0, 0.0),
+            make_color(2.0, 2.0, 2.0),
+        )));
+        let background = make_gradient_sky(make_color(0.5, 0.5, 0.5), make_color(0.5, 0.5, 0.5));
+        let camera = Camera::default();
+        let scene = Scene {
+            world: world.as_ref(),
+            lights: &lights,
+            background: &background,
+            camera: &camera,
+        };
+        let integrator = make_photon_integrator(1000, 0.25);
+        integrator.start_pass(&scene, 0, 5);
+        let mut sampler = make_stratified_sampler(true);
+        let mut film = make_film(1, 1);
+        let look_down = |x: f64| Ray {
+            origin: make_point(x, 1.0, 1.5),
+            direction: Vec3(0.0, -1.0, 0.0),
+        };
+
+        // Far out only sampling the lights finds anything: albedo / pi times
+        // the sun's irradiance, plus albedo times the sky's radiance
+        let n = 1024;
+        let mut sum = 0.0;
+        for s in 0..n {
+            sampler.start_pixel_sample(0, 0, s, n);
+            sum += integrator
+                .li(look_down(50.0), &scene, 5, &mut sampler, &mut film)
+                .y();
+        }
+        let expected = 0.5 / PI * 2.0 + 0.5 * 0.5;
+        assert!((sum / n as f64 - expected).abs() < 0.03 * expected);
+
+        // Next to the ball the sun's photons cover the ground alone
+        let mut rec = HitRecord::default();
+        let r = look_down(0.0);
+        assert!(world.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        let lights_only = Scene {
+            background: &make_gradient_sky(make_color(0.0, 0.0, 0.0), make_color(0.0, 0.0, 0.0)),
+            ..scene
+        };
+        assert!(direct_beyond_photons(&r, &rec, &lights_only, &mut sampler).near_zero());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::aabb::Aabb;
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::{self, Interval};
+use crate::material::Material;
+use crate::onb::{make_onb, Onb};
+use crate::ray::Ray;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// Infinite plane through a point. u and v are distances in scene units from
+// that point along two directions in the plane, so textures are mapped flat
+// onto it.
+pub struct Plane {
+    point: Point3,
+    frame: Onb,
+    mat: Box<dyn Material>,
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let normal = self.frame.w;
+        let denominator = dot(normal, r.direction());
+        if denominator == 0.0 {
+            return false;
+        }
+        let t = dot(self.point - r.origin(), normal) / denominator;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.set_face_normal(r, normal);
+        let offset = rec.p - self.point;
+        rec.u = dot(offset, self.frame.u);
+        rec.v = dot(offset, self.frame.v);
+        rec.mat = self.mat.clone();
+        rec.clear_extras();
+        rec.tangent = self.frame.u;
+        true
+    }
+
+    // Everywhere, which keeps the plane out of bounding volume hierarchies
+    fn bounding_box(&self) -> Aabb {
+        let all = interval::new(-f64::INFINITY, f64::INFINITY);
+        Aabb {
+            x: all,
+            y: all,
+            z: all,
+        }
+    }
+}
+
+pub fn make_plane(point: Point3, normal: Vec3, mat: Box<dyn Material>) -> Plane {
+    Plane {
+        point,
+        frame: make_onb(unit_vector(normal)),
+        mat,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_plane;
+    use crate::bvh::make_bvh;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_is_hit_far_from_the_rest_of_the_scene() {
+        let world = make_bvh(vec![
+            Box::new(make_plane(
+                make_point(0.0, 0.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )),
+            Box::new(make_sphere(
+                make_point(0.0, 1.0, 0.0),
+                1.0,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )),
+        ]);
+        assert!(world.bounding_box().x.max == 1.0);
+
+        // Nearly grazing, landing a long way off
+        let r = Ray {
+            origin: make_point(0.0, 1.0, 5.0),
+            direction: Vec3(3.0, -0.001, 4.0),
+        };
+        let mut rec = HitRecord::default();
+        assert!(world.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 1000.0).abs() < 1e-6);
+        assert!(rec.p.y().abs() < 1e-9 && rec.front_face);
+        assert!((rec.u - 3000.0).abs() < 1e-6 && (rec.v + 4005.0).abs() < 1e-6);
+
+        // The sphere in front of it
+        let r = Ray {
+            origin: make_point(0.0, 1.0, 5.0),
+            direction: Vec3(0.0, -0.1, -1.0),
+        };
+        assert!(world.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(rec.p.y() > 0.5);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::{
+    color::{make_color, Color},
+    hit_record::HitRecord,
+    material::{
+        local_directions, make_rough_dielectric, outward_normal, scatter_local, shading_frame,
+        Material, RoughDielectric, TransportMode,
+    },
+    microfacet::{make_trowbridge_reitz, TrowbridgeReitz},
+    ray::Ray,
+    rtweekend::PI,
+    sampler::Sampler,
+    texture::{make_solid_color, Texture},
+    vec3::{dot, reflect, sample_unit_sphere, unit_vector, Vec3},
+};
+
+// Principled BSDF after Burley's Disney model. One material covers plastics,
+// metals, varnished and cloth-like surfaces and glass through a handful of
+// [0, 1] parameters, each of which can vary over the surface through a
+// texture (scalar textures are read from their red channel).
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Box<dyn Texture>,
+    pub metallic: Box<dyn Texture>,
+    pub roughness: Box<dyn Texture>,
+    pub specular: Box<dyn Texture>,
+    pub specular_tint: Box<dyn Texture>,
+    pub sheen: Box<dyn Texture>,
+    pub sheen_tint: Box<dyn Texture>,
+    pub clearcoat: Box<dyn Texture>,
+    pub clearcoat_gloss: Box<dyn Texture>,
+    pub transmission: Box<dyn Texture>,
+    pub ior: Box<dyn Texture>,
+}
+
+// The material's parameters resolved at one hit point
+struct Lobes {
+    base: Color,
+    tint: Color,
+    roughness: f64,
+    f0: Color,
+    // Reflectance at normal incidence of the dielectric specular layer
+    // above the diffuse base
+    dielectric_f0: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    specular: TrowbridgeReitz,
+    glass: RoughDielectric,
+    clearcoat_alpha: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    clearcoat_weight: f64,
+    transmission_weight: f64,
+    transport: TransportMode,
+}
+
+impl Lobes {
+    // Probabilities of sampling the diffuse, specular, clearcoat and
+    // transmission lobes, in that order
+    fn selection(&self) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight,
+            self.specular_weight,
+            self.clearcoat_weight,
+            self.transmission_weight,
+        ];
+        let total: f64 = weights.iter().sum();
+        weights.map(|w| w / total)
+    }
+
+    // Share of light at the given angle that the specular and clearcoat
+    // layers let through to the diffuse base, or back out of it. Taking it
+    // both ways keeps the BSDF symmetric.
+    fn through_coats(&self, cos_theta: f64) -> f64 {
+        let w = schlick_weight(cos_theta);
+        let specular = self.dielectric_f0 + w * (1.0 - self.dielectric_f0);
+        let clearcoat = self.clearcoat_weight * (0.04 + 0.96 * w);
+        (1.0 - specular) * (1.0 - clearcoat)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let u_lobe = sampler.get_1d();
+        let u = sampler.get_2d();
+        let lobes = self.lobes(rec);
+        let onb = shading_frame(rec, outward_normal(rec));
+        let wo = onb.world_to_local(-unit_vector(r_in.direction()));
+        match sample_local(&lobes, wo, u_lobe, u) {
+            Some(wi) => scatter_local(self, &r_in, rec, &onb, wi, attenuation, scattered),
+            None => false,
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_directions(r_in, rec, outward_normal(rec), scattered);
+        eval_local(&self.lobes(rec), wo, wi)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_directions(r_in, rec, outward_normal(rec), scattered);
+        pdf_local(&self.lobes(rec), wo, wi)
+    }
+}
+
+impl Principled {
+    fn lobes(&self, rec: &HitRecord) -> Lobes {
+        let scalar = |texture: &dyn Texture| texture.value(rec.u, rec.v, rec.p).x();
+        let unit = |texture: &dyn Texture| scalar(texture).clamp(0.0, 1.0);
+        let base = self.base_color.value(rec.u, rec.v, rec.p) * rec.color;
+        let metallic = unit(self.metallic.as_ref());
+        // Keep a little roughness so that no lobe degenerates to a delta
+        let roughness = scalar(self.roughness.as_ref()).clamp(0.05, 1.0);
+        let transmission = unit(self.transmission.as_ref());
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let tint = tint_color(base);
+        let white = make_color(1.0, 1.0, 1.0);
+        let dielectric_f0 = unit(self.specular.as_ref())
+            * 0.08
+            * lerp_color(unit(self.specular_tint.as_ref()), white, tint);
+        Lobes {
+            base,
+            tint,
+            roughness,
+            f0: lerp_color(metallic, dielectric_f0, base),
+            dielectric_f0: (dielectric_f0.x() + dielectric_f0.y() + dielectric_f0.z()) / 3.0,
+            sheen: unit(self.sheen.as_ref()),
+            sheen_tint: unit(self.sheen_tint.as_ref()),
+            specular: make_trowbridge_reitz(roughness, roughness),
+            glass: make_rough_dielectric(scalar(self.ior.as_ref()).max(1.0), roughness, roughness),
+            clearcoat_alpha: lerp(unit(self.clearcoat_gloss.as_ref()), 0.1, 0.001),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - transmission_weight,
+            clearcoat_weight: 0.25 * unit(self.clearcoat.as_ref()),
+            transmission_weight,
+            transport: rec.transport,
+        }
+    }
+}
+
+// BSDF times cosine in the frame around the outward normal
+fn eval_local(lobes: &Lobes, wo: Vec3, wi: Vec3) -> Color {
+    if wo.z() < 0.0 {
+        // Only light refracted into the object travels inside it
+        let f = lobes.glass.eval_local(wo, wi, lobes.transport);
+        return make_color(f, f, f);
+    }
+    let mut f = make_color(0.0, 0.0, 0.0);
+    if wi.z() > 0.0 && wo.z() > 0.0 {
+        let cos_theta_o = wo.z();
+        let cos_theta_i = wi.z();
+        let wm = unit_vector(wo + wi);
+        let cos_theta_d = dot(wi, wm);
+
+        if lobes.diffuse_weight > 0.0 {
+            let fl = schlick_weight(cos_theta_i);
+            let fv = schlick_weight(cos_theta_o);
+            let rr = 2.0 * lobes.roughness * cos_theta_d * cos_theta_d;
+            let retro = rr * (fl + fv + fl * fv * (rr - 1.0));
+            let diffuse = lobes.base / PI * ((1.0 - 0.5 * fl) * (1.0 - 0.5 * fv) + retro);
+            let sheen_color = lerp_color(lobes.sheen_tint, make_color(1.0, 1.0, 1.0), lobes.tint);
+            let sheen = lobes.sheen * schlick_weight(cos_theta_d) * sheen_color;
+            f += lobes.diffuse_weight
+                * lobes.through_coats(cos_theta_i)
+                * lobes.through_coats(cos_theta_o)
+                * cos_theta_i
+                * (diffuse + sheen);
+        }
+
+        let fresnel =
+            lobes.f0 + schlick_weight(cos_theta_d) * (make_color(1.0, 1.0, 1.0) - lobes.f0);
+        let specular = lobes.specular.d(wm) * lobes.specular.g(wo, wi) / (4.0 * cos_theta_o);
+        f += lobes.specular_weight * specular * fresnel;
+
+        if lobes.clearcoat_weight > 0.0 {
+            let d = gtr1(wm.z(), lobes.clearcoat_alpha);
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_theta_d);
+            let g = smith_g1(cos_theta_o, 0.25) * smith_g1(cos_theta_i, 0.25);
+            let clearcoat = lobes.clearcoat_weight * d * fresnel * g / (4.0 * cos_theta_o);
+            f += make_color(clearcoat, clearcoat, clearcoat);
+        }
+    }
+    if lobes.transmission_weight > 0.0 {
+        f += lobes.transmission_weight
+            * lobes.glass.eval_local(wo, wi, lobes.transport)
+            * lobes.base;
+    }
+    f
+}
+
+fn pdf_local(lobes: &Lobes, wo: Vec3, wi: Vec3) -> f64 {
+    if wo.z() < 0.0 {
+        return lobes.glass.pdf_local(wo, wi);
+    }
+    let [p_diffuse, p_specular, p_clearcoat, p_transmission] = lobes.selection();
+    let mut pdf = 0.0;
+    if wi.z() > 0.0 && wo.z() > 0.0 {
+        let wm = unit_vector(wo + wi);
+        let jacobian = 1.0 / (4.0 * dot(wo, wm).abs());
+        pdf += p_diffuse * wi.z() / PI;
+        pdf += p_specular * lobes.specular.visible_d(wo, wm) * jacobian;
+        pdf += p_clearcoat * gtr1(wm.z(), lobes.clearcoat_alpha) * wm.z() * jacobian;
+    }
+    if p_transmission > 0.0 {
+        pdf += p_transmission * lobes.glass.pdf_local(wo, wi);
+    }
+    pdf
+}
+
+fn sample_local(lobes: &Lobes, wo: Vec3, u_lobe: f64, u: (f64, f64)) -> Option<Vec3> {
+    if wo.z() == 0.0 {
+        return None;
+    }
+    if wo.z() < 0.0 {
+        return lobes.glass.sample_local(wo, u_lobe, u);
+    }
+    let [p_diffuse, p_specular, p_clearcoat, p_transmission] = lobes.selection();
+    let wi = if u_lobe < p_diffuse {
+        let wi = Vec3(0.0, 0.0, 1.0) + sample_unit_sphere(u);
+        if wi.near_zero() {
+            Vec3(0.0, 0.0, 1.0)
+        } else {
+            unit_vector(wi)
+        }
+    } else if u_lobe < p_diffuse + p_specular {
+        reflect(-wo, lobes.specular.sample_wm(wo, u))
+    } else if u_lobe < p_diffuse + p_specular + p_clearcoat {
+        reflect(-wo, sample_gtr1(lobes.clearcoat_alpha, u))
+    } else {
+        // Reuse the leftover of u_lobe for the glass's own Fresnel choice
+        let u_glass = (u_lobe - (1.0 - p_transmission)) / p_transmission;
+        return lobes.glass.sample_local(wo, u_glass.clamp(0.0, 1.0), u);
+    };
+    if wi.z() <= 0.0 {
+        return None;
+    }
+    Some(wi)
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// Generalized Trowbridge-Reitz with gamma = 1, used for the clearcoat's long tail
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64, u: (f64, f64)) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+// Separable Smith masking for an isotropic GGX lobe
+fn smith_g1(cos_theta: f64, alpha: f64) -> f64 {
+    let a = alpha * alpha;
+    let b = cos_theta * cos_theta;
+    2.0 * cos_theta / (cos_theta + (a + b - a * b).sqrt())
+}
+
+// Hue and saturation of the base color with its luminance taken out
+fn tint_color(base: Color) -> Color {
+    let luminance = 0.3 * base.x() + 0.6 * base.y() + 0.1 * base.z();
+    if luminance > 0.0 {
+        base / luminance
+    } else {
+        make_color(1.0, 1.0, 1.0)
+    }
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    (1.0 - t) * a + t * b
+}
+
+fn lerp_color(t: f64, a: Color, b: Color) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// A plain dielectric with the given base color; adjust the public fields from there
+pub fn make_principled(base_color: Box<dyn Texture>) -> Principled {
+    let constant = |value: f64| -> Box<dyn Texture> {
+        Box::new(make_solid_color(make_color(value, value, value)))
+    };
+    Principled {
+        base_color,
+        metallic: constant(0.0),
+        roughness: constant(0.5),
+        specular: constant(0.5),
+        specular_tint: constant(0.0),
+        sheen: constant(0.0),
+        sheen_tint: constant(0.5),
+        clearcoat: constant(0.0),
+        clearcoat_gloss: constant(1.0),
+        transmission: constant(0.0),
+        ior: constant(1.5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(value: f64) -> Box<dyn Texture> {
+        Box::new(make_solid_color(make_color(value, value, value)))
+    }
+
+    fn direction(cos_theta: f64, phi: f64) -> Vec3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        Vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    // A white material of each kind the principled BSDF covers
+    fn white_materials() -> Vec<Principled> {
+        let mut materials = Vec::new();
+        for (metallic, roughness, sheen, clearcoat) in [
+            (1.0, 0.05, 0.0, 0.0),
+            (1.0, 0.6, 0.0, 0.0),
+            (0.0, 0.3, 0.0, 0.0),
+            (0.0, 1.0, 1.0, 1.0),
+            (0.5, 0.4, 0.0, 1.0),
+        ] {
+            let mut mat = make_principled(constant(1.0));
+            mat.metallic = constant(metallic);
+            mat.roughness = constant(roughness);
+            mat.sheen = constant(sheen);
+            mat.clearcoat = constant(clearcoat);
+            materials.push(mat);
+        }
+        materials
+    }
+
+    #[test]
+    fn it_reflects_the_same_both_ways() {
+        let rec = HitRecord::default();
+        for mat in white_materials() {
+            let lobes = mat.lobes(&rec);
+            let a = direction(0.8, 0.3);
+            let b = direction(0.35, 2.0);
+            // eval carries the cosine of the incoming direction
+            let forward = eval_local(&lobes, a, b) / b.z();
+            let backward = eval_local(&lobes, b, a) / a.z();
+            assert!((forward - backward).length() < 1e-9 * forward.length());
+        }
+    }
+
+    #[test]
+    fn it_reflects_no_more_light_than_arrives() {
+        // Up to the error of the stratified estimate
+        let rec = HitRecord::default();
+        for mat in white_materials() {
+            let lobes = mat.lobes(&rec);
+            for cos_theta_o in [0.95, 0.6, 0.3] {
+                let wo = direction(cos_theta_o, 0.0);
+                let n = 32;
+                let mut albedo = make_color(0.0, 0.0, 0.0);
+                for lobe in 0..n {
+                    for i in 0..n {
+                        for j in 0..n {
+                            let u_lobe = (lobe as f64 + 0.5) / n as f64;
+                            let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                            let Some(wi) = sample_local(&lobes, wo, u_lobe, u) else {
+                                continue;
+                            };
+                            let pdf = pdf_local(&lobes, wo, wi);
+                            if pdf > 0.0 {
+                                albedo += eval_local(&lobes, wo, wi) / pdf;
+                            }
+                        }
+                    }
+                }
+                albedo /= (n * n * n) as f64;
+                assert!(albedo.x() <= 1.0 + 1e-3 && albedo.x() > 0.7);
+            }
+        }
+    }
+}
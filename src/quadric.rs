@@ -0,0 +1,439 @@
+use crate::aabb::{make_aabb, surrounding_box, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::{Hittable, Span};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::{make_onb, Onb};
+use crate::ray::Ray;
+use crate::rtweekend::PI;
+use crate::vec3::{cross, make_point, unit_vector, Point3, Vec3};
+
+const PADDING: f64 = 1e-4;
+
+// Where a ray crosses a surface, in the surface's own frame
+pub struct Crossing {
+    pub t: f64,
+    // Unit normal pointing out of the shape
+    pub normal: Vec3,
+    pub u: f64,
+    pub v: f64,
+}
+
+// A surface described in its own frame, around the z axis
+pub trait Surface {
+    // Every point where the whole line through r crosses the surface, in
+    // any order
+    fn crossings(&self, r: &Ray) -> Vec<Crossing>;
+
+    // Box around the surface in its own frame
+    fn bounds(&self) -> Aabb;
+
+    // Whether the surface encloses a volume, which makes its crossings
+    // alternate between entering and leaving it
+    fn closed(&self) -> bool;
+}
+
+// A surface placed in the scene: its frame's origin moved to `origin` and
+// its z axis turned to `axis`
+pub struct Shape<S: Surface> {
+    surface: S,
+    origin: Point3,
+    frame: Onb,
+    mat: Box<dyn Material>,
+    bbox: Aabb,
+}
+
+impl<S: Surface> Hittable for Shape<S> {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+        let Some(crossing) = self
+            .crossings(r)
+            .into_iter()
+            .find(|crossing| ray_t.surrounds(crossing.t))
+        else {
+            return false;
+        };
+        self.fill_record(r, &crossing, rec);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Span> {
+        if !self.surface.closed() {
+            return Vec::new();
+        }
+        let crossings = self.crossings(r);
+        let mut spans = Vec::new();
+        for pair in crossings.chunks_exact(2) {
+            let mut enter = HitRecord::default();
+            let mut exit = HitRecord::default();
+            self.fill_record(r, &pair[0], &mut enter);
+            self.fill_record(r, &pair[1], &mut exit);
+            spans.push(Span { enter, exit });
+        }
+        spans
+    }
+}
+
+impl<S: Surface> Shape<S> {
+    // The surface's crossings in order along r. The frame is rigid, so
+    // distances along the ray are the same in both frames.
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        let local = Ray {
+            origin: self.frame.world_to_local(r.origin() - self.origin),
+            direction: self.frame.world_to_local(r.direction()),
+        };
+        let mut crossings = self.surface.crossings(&local);
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        crossings
+    }
+
+    fn fill_record(&self, r: &Ray, crossing: &Crossing, rec: &mut HitRecord) {
+        rec.t = crossing.t;
+        rec.p = r.at(crossing.t);
+        rec.set_face_normal(r, self.frame.local(crossing.normal));
+        rec.u = crossing.u;
+        rec.v = crossing.v;
+        rec.mat = self.mat.clone();
+        rec.clear_extras();
+        // u is the angle around the axis
+        rec.tangent = cross(self.frame.w, rec.p - self.origin);
+    }
+}
+
+pub fn make_shape<S: Surface>(
+    surface: S,
+    origin: Point3,
+    axis: Vec3,
+    mat: Box<dyn Material>,
+) -> Shape<S> {
+    let frame = make_onb(unit_vector(axis));
+    // Box around the corners of the local box, given some thickness so
+    // flat surfaces still have a box rays can hit
+    let local = surface.bounds();
+    let mut bbox = Aabb::default();
+    for x in [local.x.min - PADDING, local.x.max + PADDING] {
+        for y in [local.y.min - PADDING, local.y.max + PADDING] {
+            for z in [local.z.min - PADDING, local.z.max + PADDING] {
+                let corner = origin + frame.local(Vec3(x, y, z));
+                bbox = surrounding_box(&bbox, &make_aabb(corner, corner));
+            }
+        }
+    }
+    Shape {
+        surface,
+        origin,
+        frame,
+        mat,
+        bbox,
+    }
+}
+
+// Real roots of a t^2 + b t + c in increasing order, avoiding the
+// cancellation in the textbook formula
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    let (t0, t1) = (q / a, c / q);
+    vec![t0.min(t1), t0.max(t1)]
+}
+
+// Angle around the z axis mapped to [0, 1)
+fn azimuth(p: Point3) -> f64 {
+    let phi = p.y().atan2(p.x());
+    (if phi < 0.0 { phi + 2.0 * PI } else { phi }) / (2.0 * PI)
+}
+
+// Crossing of the plane z = height within `radius` of the axis, facing
+// along `normal_z`. u goes around the axis and v out from it.
+fn cap(r: &Ray, height: f64, radius: f64, normal_z: f64) -> Option<Crossing> {
+    if r.direction().z() == 0.0 {
+        return None;
+    }
+    let t = (height - r.origin().z()) / r.direction().z();
+    let p = r.at(t);
+    let distance = (p.x() * p.x() + p.y() * p.y()).sqrt();
+    if distance > radius {
+        return None;
+    }
+    Some(Crossing {
+        t,
+        normal: Vec3(0.0, 0.0, normal_z),
+        u: azimuth(p),
+        v: distance / radius,
+    })
+}
+
+pub struct Cylinder {
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Surface for Cylinder {
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        let (o, d) = (r.origin(), r.direction());
+        let mut crossings = Vec::new();
+        let a = d.x() * d.x() + d.y() * d.y();
+        let b = 2.0 * (o.x() * d.x() + o.y() * d.y());
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        for t in solve_quadratic(a, b, c) {
+            let p = r.at(t);
+            if (0.0..=self.height).contains(&p.z()) {
+                crossings.push(Crossing {
+                    t,
+                    normal: Vec3(p.x() / self.radius, p.y() / self.radius, 0.0),
+                    u: azimuth(p),
+                    v: p.z() / self.height,
+                });
+            }
+        }
+        if self.capped {
+            crossings.extend(cap(r, 0.0, self.radius, -1.0));
+            crossings.extend(cap(r, self.height, self.radius, 1.0));
+        }
+        crossings
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.radius;
+        make_aabb(make_point(-r, -r, 0.0), make_point(r, r, self.height))
+    }
+
+    fn closed(&self) -> bool {
+        self.capped
+    }
+}
+
+// Cylinder standing on a circle around `base` and reaching `height` along
+// `axis`. u goes around the axis and v up it; on the caps v goes out from
+// the axis.
+pub fn make_cylinder(
+    base: Point3,
+    axis: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Box<dyn Material>,
+) -> Shape<Cylinder> {
+    let cylinder = Cylinder {
+        radius,
+        height,
+        capped,
+    };
+    make_shape(cylinder, base, axis, mat)
+}
+
+pub struct Cone {
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Surface for Cone {
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        let (o, d) = (r.origin(), r.direction());
+        let mut crossings = Vec::new();
+        // x^2 + y^2 = k^2 (height - z)^2
+        let k2 = (self.radius / self.height).powi(2);
+        let above = self.height - o.z();
+        let a = d.x() * d.x() + d.y() * d.y() - k2 * d.z() * d.z();
+        let b = 2.0 * (o.x() * d.x() + o.y() * d.y() + k2 * above * d.z());
+        let c = o.x() * o.x() + o.y() * o.y() - k2 * above * above;
+        for t in solve_quadratic(a, b, c) {
+            let p = r.at(t);
+            if (0.0..=self.height).contains(&p.z()) {
+                crossings.push(Crossing {
+                    t,
+                    normal: unit_vector(Vec3(p.x(), p.y(), k2 * (self.height - p.z()))),
+                    u: azimuth(p),
+                    v: p.z() / self.height,
+                });
+            }
+        }
+        if self.capped {
+            crossings.extend(cap(r, 0.0, self.radius, -1.0));
+        }
+        crossings
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.radius;
+        make_aabb(make_point(-r, -r, 0.0), make_point(r, r, self.height))
+    }
+
+    fn closed(&self) -> bool {
+        self.capped
+    }
+}
+
+// Cone on a circle around `base` with its tip `height` along `axis`.
+// u goes around the axis and v up it.
+pub fn make_cone(
+    base: Point3,
+    axis: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Box<dyn Material>,
+) -> Shape<Cone> {
+    let cone = Cone {
+        radius,
+        height,
+        capped,
+    };
+    make_shape(cone, base, axis, mat)
+}
+
+pub struct Disk {
+    radius: f64,
+    inner_radius: f64,
+}
+
+impl Surface for Disk {
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        let Some(mut crossing) = cap(r, 0.0, self.radius, 1.0) else {
+            return Vec::new();
+        };
+        let distance = crossing.v * self.radius;
+        if distance < self.inner_radius {
+            return Vec::new();
+        }
+        crossing.v = (self.radius - distance) / (self.radius - self.inner_radius);
+        vec![crossing]
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.radius;
+        make_aabb(make_point(-r, -r, 0.0), make_point(r, r, 0.0))
+    }
+
+    fn closed(&self) -> bool {
+        false
+    }
+}
+
+// Flat disk around `center` facing along `normal`, with a hole of
+// `inner_radius` making it an annulus. u goes around the center and v in
+// from the rim.
+pub fn make_disk(
+    center: Point3,
+    normal: Vec3,
+    radius: f64,
+    inner_radius: f64,
+    mat: Box<dyn Material>,
+) -> Shape<Disk> {
+    let disk = Disk {
+        radius,
+        inner_radius: inner_radius.clamp(0.0, radius),
+    };
+    make_shape(disk, center, normal, mat)
+}
+
+pub struct Paraboloid {
+    radius: f64,
+    height: f64,
+}
+
+impl Surface for Paraboloid {
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        let (o, d) = (r.origin(), r.direction());
+        let mut crossings = Vec::new();
+        // z = k (x^2 + y^2)
+        let k = self.height / (self.radius * self.radius);
+        let a = k * (d.x() * d.x() + d.y() * d.y());
+        let b = 2.0 * k * (o.x() * d.x() + o.y() * d.y()) - d.z();
+        let c = k * (o.x() * o.x() + o.y() * o.y()) - o.z();
+        for t in solve_quadratic(a, b, c) {
+            let p = r.at(t);
+            if (0.0..=self.height).contains(&p.z()) {
+                crossings.push(Crossing {
+                    t,
+                    normal: unit_vector(Vec3(2.0 * k * p.x(), 2.0 * k * p.y(), -1.0)),
+                    u: azimuth(p),
+                    v: p.z() / self.height,
+                });
+            }
+        }
+        crossings
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.radius;
+        make_aabb(make_point(-r, -r, 0.0), make_point(r, r, self.height))
+    }
+
+    fn closed(&self) -> bool {
+        false
+    }
+}
+
+// Open bowl with its bottom at `base`, opening along `axis` and reaching
+// `radius` wide at `height`. u goes around the axis and v up it.
+pub fn make_paraboloid(
+    base: Point3,
+    axis: Vec3,
+    radius: f64,
+    height: f64,
+    mat: Box<dyn Material>,
+) -> Shape<Paraboloid> {
+    make_shape(Paraboloid { radius, height }, base, axis, mat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_cylinder;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_closes_capped_cylinders_with_caps() {
+        // Lying along x, seen end on from the left
+        let cylinder = |capped| {
+            make_cylinder(
+                make_point(1.0, 0.0, 0.0),
+                Vec3(1.0, 0.0, 0.0),
+                0.5,
+                2.0,
+                capped,
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            )
+        };
+        let r = Ray {
+            origin: make_point(-1.0, 0.1, 0.2),
+            direction: Vec3(1.0, 0.0, 0.0),
+        };
+
+        let mut rec = HitRecord::default();
+        assert!(!cylinder(false).hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+
+        assert!(cylinder(true).hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert!((rec.normal.x() + 1.0).abs() < 1e-9);
+
+        let spans = cylinder(true).intervals(&r);
+        assert!(spans.len() == 1);
+        assert!((spans[0].exit.t - 4.0).abs() < 1e-9);
+        assert!(!spans[0].exit.front_face);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::vec3::*;
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub(crate) origin: Point3,
     pub(crate) direction: Vec3,
@@ -1,6 +1,9 @@
 use rand::{thread_rng, Rng};
 
-const PI: f64 = std::f64::consts::PI;
+pub const PI: f64 = std::f64::consts::PI;
+
+// Largest f64 strictly below 1.0, used to keep sample values in [0, 1)
+pub const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
@@ -17,3 +20,20 @@ pub fn random_f64_in_range(min: f64, max: f64) -> f64 {
     // [min, max)
     rng.gen_range(min..max)
 }
+
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash_values(values: &[u64]) -> u64 {
+    let mut h = 0x9e3779b97f4a7c15;
+    for v in values {
+        h = mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15));
+    }
+    h
+}
@@ -0,0 +1,371 @@
+use crate::rtweekend::{hash_values, mix_bits, random_f64, ONE_MINUS_EPSILON};
+
+// Samplers hand out the random numbers used for one camera sample, one
+// dimension at a time. The camera consumes the first two 2D dimensions for the
+// pixel jitter and the lens, materials consume the rest as the path bounces.
+pub trait Sampler: SamplerClone {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32, samples_per_pixel: i32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+pub trait SamplerClone {
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+impl<T> SamplerClone for T
+where
+    T: 'static + Sampler + Clone,
+{
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Sampler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+// Where the sampler currently is: which pixel sample and which dimension
+#[derive(Clone, Copy, Default)]
+struct SampleState {
+    pixel_hash: u64,
+    sample_index: u32,
+    samples_per_pixel: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn start(&mut self, i: i32, j: i32, sample_index: i32, samples_per_pixel: i32) {
+        self.pixel_hash = hash_values(&[i as u64, j as u64]);
+        self.sample_index = sample_index as u32;
+        self.samples_per_pixel = samples_per_pixel.max(1) as u32;
+        self.dimension = 0;
+    }
+
+    // Hash unique to the pixel and the dimension being handed out, then advance
+    fn next_dimension(&mut self, seed: u64) -> (u32, u64) {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        (
+            dimension,
+            hash_values(&[self.pixel_hash, dimension as u64, seed]),
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _i: i32, _j: i32, _sample_index: i32, _spp: i32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        random_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_f64(), random_f64())
+    }
+}
+
+pub fn make_independent_sampler() -> IndependentSampler {
+    IndependentSampler
+}
+
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    jitter: bool,
+    state: SampleState,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32, spp: i32) {
+        self.state.start(i, j, sample_index, spp);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, hash) = self.state.next_dimension(0);
+        let n = self.state.samples_per_pixel;
+        // Each dimension visits the strata in its own order so that dimensions
+        // are not correlated with each other.
+        let stratum = permutation_element(self.state.sample_index % n, n, hash as u32);
+        (stratum as f64 + self.offset()) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, hash) = self.state.next_dimension(0);
+        let n = self.state.samples_per_pixel;
+        let (x_strata, y_strata) = strata_grid(n);
+        let stratum = permutation_element(self.state.sample_index % n, n, hash as u32);
+        let x = stratum % x_strata;
+        let y = stratum / x_strata;
+        (
+            (x as f64 + self.offset()) / x_strata as f64,
+            (y as f64 + self.offset()) / y_strata as f64,
+        )
+    }
+}
+
+impl StratifiedSampler {
+    fn offset(&self) -> f64 {
+        if self.jitter {
+            random_f64()
+        } else {
+            0.5
+        }
+    }
+}
+
+// Splits n samples into the most square x * y grid that uses all of them
+fn strata_grid(n: u32) -> (u32, u32) {
+    let mut x = (n as f64).sqrt() as u32;
+    while x > 1 && !n.is_multiple_of(x) {
+        x -= 1;
+    }
+    let x = x.max(1);
+    (n / x, x)
+}
+
+pub fn make_stratified_sampler(jitter: bool) -> StratifiedSampler {
+    StratifiedSampler {
+        jitter,
+        state: SampleState::default(),
+    }
+}
+
+#[derive(Clone)]
+pub struct HaltonSampler {
+    primes: Vec<u64>,
+    state: SampleState,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32, spp: i32) {
+        self.state.start(i, j, sample_index, spp);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, hash) = self.state.next_dimension(1);
+        self.sample_dimension(dimension, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (dimension_a, hash_a) = self.state.next_dimension(1);
+        let (dimension_b, hash_b) = self.state.next_dimension(1);
+        (
+            self.sample_dimension(dimension_a, hash_a),
+            self.sample_dimension(dimension_b, hash_b),
+        )
+    }
+}
+
+impl HaltonSampler {
+    fn sample_dimension(&self, dimension: u32, hash: u64) -> f64 {
+        match self.primes.get(dimension as usize) {
+            Some(&base) => {
+                owen_scrambled_radical_inverse(base, self.state.sample_index as u64, hash)
+            }
+            // Past the prime table the sequence has lost most of its benefit
+            None => random_f64(),
+        }
+    }
+}
+
+pub fn make_halton_sampler() -> HaltonSampler {
+    HaltonSampler {
+        primes: first_primes(256),
+        state: SampleState::default(),
+    }
+}
+
+fn first_primes(count: usize) -> Vec<u64> {
+    let mut primes: Vec<u64> = Vec::with_capacity(count);
+    let mut candidate: u64 = 2;
+    while primes.len() < count {
+        if primes.iter().all(|p| !candidate.is_multiple_of(*p)) {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+// Radical inverse of a in the given base, with the digits randomly permuted
+// at every level of the digit tree (Owen scrambling).
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits: u64 = 0;
+    let mut digit_index = 0;
+    while 1.0 - (base - 1) as f64 * inv_base_m < 1.0 {
+        let next = a / base;
+        let digit_value = a - next * base;
+        let digit_hash = mix_bits(hash ^ reversed_digits ^ ((digit_index as u64) << 56));
+        let digit_value =
+            permutation_element(digit_value as u32, base as u32, digit_hash as u32) as u64;
+        reversed_digits = reversed_digits * base + digit_value;
+        inv_base_m *= inv_base;
+        digit_index += 1;
+        a = next;
+    }
+    (inv_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+// Padded Sobol: every dimension (or pair of dimensions) uses the first two
+// Sobol dimensions with its own shuffle of the sample order and its own Owen
+// scramble, which keeps the (0, 2)-sequence quality per pair.
+#[derive(Clone)]
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32, spp: i32) {
+        self.state.start(i, j, sample_index, spp);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, hash) = self.state.next_dimension(2);
+        let index = self.shuffled_index(hash);
+        sobol_to_f64(owen_scramble(index.reverse_bits(), (hash >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, hash) = self.state.next_dimension(2);
+        let index = self.shuffled_index(hash);
+        let scramble = mix_bits(hash);
+        (
+            sobol_to_f64(owen_scramble(index.reverse_bits(), scramble as u32)),
+            sobol_to_f64(owen_scramble(
+                sobol_second_dimension(index),
+                (scramble >> 32) as u32,
+            )),
+        )
+    }
+}
+
+impl SobolSampler {
+    fn shuffled_index(&self, hash: u64) -> u32 {
+        let n = self.state.samples_per_pixel;
+        let index = self.state.sample_index;
+        // Shuffle within each group of samples_per_pixel samples
+        let group = index / n * n;
+        group + permutation_element(index % n, n, hash as u32)
+    }
+}
+
+pub fn make_sobol_sampler() -> SobolSampler {
+    SobolSampler {
+        state: SampleState::default(),
+    }
+}
+
+// Second Sobol dimension, generated by x + 1, for a sample index
+fn sobol_second_dimension(mut a: u32) -> u32 {
+    let mut v: u32 = 1 << 31;
+    let mut result = 0;
+    while a != 0 {
+        if a & 1 != 0 {
+            result ^= v;
+        }
+        v ^= v >> 1;
+        a >>= 1;
+    }
+    result
+}
+
+// Hash based nested uniform scrambling (Burley 2020)
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut x = v.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+fn sobol_to_f64(v: u32) -> f64 {
+    (v as f64 * (1.0 / 4294967296.0)).min(ONE_MINUS_EPSILON)
+}
+
+// Element i of a random permutation of 0..l picked by p (Kensler 2013)
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_visits_every_stratum_once() {
+        let mut sampler = make_stratified_sampler(true);
+        let mut seen = [false; 16];
+        for s in 0..16 {
+            sampler.start_pixel_sample(3, 7, s, 16);
+            let (x, y) = sampler.get_2d();
+            let stratum = (y * 4.0) as usize * 4 + (x * 4.0) as usize;
+            assert!(!seen[stratum]);
+            seen[stratum] = true;
+        }
+    }
+
+    #[test]
+    fn it_permutes_without_repeats() {
+        let mut seen = [false; 10];
+        for i in 0..10 {
+            let e = permutation_element(i, 10, 0xdeadbeef) as usize;
+            assert!(!seen[e]);
+            seen[e] = true;
+        }
+    }
+
+    #[test]
+    fn it_keeps_sequence_samples_in_unit_interval() {
+        let mut halton = make_halton_sampler();
+        let mut sobol = make_sobol_sampler();
+        for s in 0..64 {
+            halton.start_pixel_sample(1, 2, s, 64);
+            sobol.start_pixel_sample(1, 2, s, 64);
+            for _ in 0..8 {
+                let h = halton.get_1d();
+                let (a, b) = sobol.get_2d();
+                assert!((0.0..1.0).contains(&h));
+                assert!((0.0..1.0).contains(&a) && (0.0..1.0).contains(&b));
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use crate::aabb::{make_aabb, surrounding_box, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{dot, make_point, unit_vector, Point3, Vec3};
+
+// Marching gives up after this many steps, counting it as a miss
+const MAX_STEPS: i32 = 512;
+// Distance at which a march counts as having reached the surface
+const SURFACE_EPSILON: f64 = 1e-4;
+
+// A shape given by the distance from any point to its surface, negative
+// inside. Operators that bend space may underestimate the distance, which
+// only slows marching down, but must never overestimate it.
+pub trait DistanceField {
+    fn distance(&self, p: Point3) -> f64;
+
+    // Box that contains the whole surface
+    fn bounding_box(&self) -> Aabb;
+}
+
+// Renders a distance field by sphere tracing: stepping along the ray by
+// the distance to the nearest surface, which can't skip over any of it
+pub struct SdfObject {
+    field: Box<dyn DistanceField>,
+    mat: Box<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let Some(inside) = self.bbox.clip(r, ray_t) else {
+            return false;
+        };
+        let speed = r.direction().length();
+        let mut t = inside.min;
+        // March towards the surface from whichever side the ray starts on.
+        // Rays leaving a surface start on it. They go by the gradient to
+        // tell which side they are heading to, and first jump clear of it.
+        let start = self.field.distance(r.at(t));
+        let side = if start.abs() < SURFACE_EPSILON {
+            let cosine = dot(self.normal(r.at(t)), r.direction()) / speed;
+            t += 2.0 * SURFACE_EPSILON / (speed * cosine.abs().max(0.01));
+            cosine.signum()
+        } else {
+            start.signum()
+        };
+        let mut step = 0;
+        while step < MAX_STEPS && t < inside.max {
+            step += 1;
+            let distance = side * self.field.distance(r.at(t));
+            if distance < SURFACE_EPSILON {
+                if !ray_t.surrounds(t) {
+                    return false;
+                }
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.set_face_normal(r, self.normal(rec.p));
+                rec.u = 0.0;
+                rec.v = 0.0;
+                rec.mat = self.mat.clone();
+                rec.clear_extras();
+                return true;
+            }
+            t += distance / speed;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+impl SdfObject {
+    // Gradient of the field from four samples at the corners of a
+    // tetrahedron, which needs two fewer than central differences
+    fn normal(&self, p: Point3) -> Vec3 {
+        let h = 1e-4;
+        let corners = [
+            Vec3(1.0, -1.0, -1.0),
+            Vec3(-1.0, -1.0, 1.0),
+            Vec3(-1.0, 1.0, -1.0),
+            Vec3(1.0, 1.0, 1.0),
+        ];
+        let mut gradient = Vec3(0.0, 0.0, 0.0);
+        for k in corners {
+            gradient += k * self.field.distance(p + h * k);
+        }
+        unit_vector(gradient)
+    }
+}
+
+pub fn make_sdf_object(field: Box<dyn DistanceField>, mat: Box<dyn Material>) -> SdfObject {
+    let bbox = field.bounding_box();
+    SdfObject { field, mat, bbox }
+}
+
+pub struct SdfSphere {
+    radius: f64,
+}
+
+impl DistanceField for SdfSphere {
+    fn distance(&self, p: Point3) -> f64 {
+        p.length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        make_aabb(make_point(-r, -r, -r), make_point(r, r, r))
+    }
+}
+
+// Sphere around the origin
+pub fn make_sdf_sphere(radius: f64) -> SdfSphere {
+    SdfSphere { radius }
+}
+
+pub struct SdfTorus {
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl DistanceField for SdfTorus {
+    fn distance(&self, p: Point3) -> f64 {
+        let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - self.major_radius;
+        (ring * ring + p.y() * p.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = self.major_radius + self.minor_radius;
+        let h = self.minor_radius;
+        make_aabb(make_point(-r, -h, -r), make_point(r, h, r))
+    }
+}
+
+// Torus around the y axis, centered on the origin
+pub fn make_sdf_torus(major_radius: f64, minor_radius: f64) -> SdfTorus {
+    SdfTorus {
+        major_radius,
+        minor_radius,
+    }
+}
+
+pub struct RoundedBox {
+    half_extent: Vec3,
+    radius: f64,
+}
+
+impl DistanceField for RoundedBox {
+    fn distance(&self, p: Point3) -> f64 {
+        // Distance to a box shrunk by the radius, then inflated again
+        let q = Vec3(
+            p.x().abs() - self.half_extent.x() + self.radius,
+            p.y().abs() - self.half_extent.y() + self.radius,
+            p.z().abs() - self.half_extent.z() + self.radius,
+        );
+        let outside = Vec3(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+        outside + inside - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        make_aabb(-self.half_extent, self.half_extent)
+    }
+}
+
+// Box centered on the origin with edges rounded off to the given radius
+pub fn make_rounded_box(half_extent: Vec3, radius: f64) -> RoundedBox {
+    RoundedBox {
+        half_extent,
+        radius: radius.min(half_extent.x().min(half_extent.y()).min(half_extent.z())),
+    }
+}
+
+// The Mandelbulb fractal, a 3D analogue of the Mandelbrot set
+pub struct Mandelbulb {
+    power: f64,
+    iterations: i32,
+}
+
+impl DistanceField for Mandelbulb {
+    // Hubbard-Douady distance estimate, from how fast the orbit of p escapes
+    // and the derivative along it
+    fn distance(&self, p: Point3) -> f64 {
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = 0.0;
+        let mut i = 0;
+        while i < self.iterations {
+            r = z.length();
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.z() / r).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z =
+                zr * Vec3(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ) + p;
+            i += 1;
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        make_aabb(make_point(-1.2, -1.2, -1.2), make_point(1.2, 1.2, 1.2))
+    }
+}
+
+// Mandelbulb around the origin. Power 8 gives the classic shape, which fits
+// within a radius of about 1.2.
+pub fn make_mandelbulb(power: f64, iterations: i32) -> Mandelbulb {
+    Mandelbulb { power, iterations }
+}
+
+pub struct Translate {
+    field: Box<dyn DistanceField>,
+    offset: Vec3,
+}
+
+impl DistanceField for Translate {
+    fn distance(&self, p: Point3) -> f64 {
+        self.field.distance(p - self.offset)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let b = self.field.bounding_box();
+        make_aabb(
+            make_point(b.x.min, b.y.min, b.z.min) + self.offset,
+            make_point(b.x.max, b.y.max, b.z.max) + self.offset,
+        )
+    }
+}
+
+pub fn make_translate(field: Box<dyn DistanceField>, offset: Vec3) -> Translate {
+    Translate { field, offset }
+}
+
+// Blends two shapes into one, filling in the crease where they meet over a
+// width of about k
+pub struct SmoothUnion {
+    a: Box<dyn DistanceField>,
+    b: Box<dyn DistanceField>,
+    k: f64,
+}
+
+impl DistanceField for SmoothUnion {
+    // Quilez's polynomial smooth minimum
+    fn distance(&self, p: Point3) -> f64 {
+        let a = self.a.distance(p);
+        let b = self.b.distance(p);
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+        b + (a - b) * h - self.k * h * (1.0 - h)
+    }
+
+    // The blend pushes the surface out by at most k / 4
+    fn bounding_box(&self) -> Aabb {
+        let b = surrounding_box(&self.a.bounding_box(), &self.b.bounding_box());
+        let pad = Vec3(0.25 * self.k, 0.25 * self.k, 0.25 * self.k);
+        make_aabb(
+            make_point(b.x.min, b.y.min, b.z.min) - pad,
+            make_point(b.x.max, b.y.max, b.z.max) + pad,
+        )
+    }
+}
+
+pub fn make_smooth_union(
+    a: Box<dyn DistanceField>,
+    b: Box<dyn DistanceField>,
+    k: f64,
+) -> SmoothUnion {
+    SmoothUnion {
+        a,
+        b,
+        k: k.max(1e-9),
+    }
+}
+
+// Twists a shape around the y axis by `rate` radians per unit of height
+pub struct Twist {
+    field: Box<dyn DistanceField>,
+    rate: f64,
+    // Furthest the shape reaches from the y axis
+    reach: f64,
+}
+
+impl DistanceField for Twist {
+    // Twisting stretches space by up to sqrt(1 + (rate reach)^2), so the
+    // distance is scaled down by that much to stay conservative
+    fn distance(&self, p: Point3) -> f64 {
+        let angle = self.rate * p.y();
+        let (sin, cos) = angle.sin_cos();
+        let q = make_point(cos * p.x() + sin * p.z(), p.y(), cos * p.z() - sin * p.x());
+        let stretch = (1.0 + (self.rate * self.reach).powi(2)).sqrt();
+        self.field.distance(q) / stretch
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let b = self.field.bounding_box();
+        let r = self.reach;
+        make_aabb(make_point(-r, b.y.min, -r), make_point(r, b.y.max, r))
+    }
+}
+
+pub fn make_twist(field: Box<dyn DistanceField>, rate: f64) -> Twist {
+    let b = field.bounding_box();
+    let x = b.x.min.abs().max(b.x.max.abs());
+    let z = b.z.min.abs().max(b.z.max.abs());
+    Twist {
+        field,
+        rate,
+        reach: (x * x + z * z).sqrt(),
+    }
+}
+
+// Copies of a shape on a grid `period` apart, `count` copies out from the
+// original in each direction along each axis. The shape should fit within
+// one cell of the grid, or the distance may be overestimated.
+pub struct Repetition {
+    field: Box<dyn DistanceField>,
+    period: Vec3,
+    count: [i32; 3],
+}
+
+impl DistanceField for Repetition {
+    fn distance(&self, p: Point3) -> f64 {
+        let cell = |x: f64, period: f64, count: i32| {
+            if period <= 0.0 {
+                return x;
+            }
+            let n = (x / period).round().clamp(-count as f64, count as f64);
+            x - period * n
+        };
+        self.field.distance(make_point(
+            cell(p.x(), self.period.x(), self.count[0]),
+            cell(p.y(), self.period.y(), self.count[1]),
+            cell(p.z(), self.period.z(), self.count[2]),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let b = self.field.bounding_box();
+        let pad = Vec3(
+            self.period.x() * self.count[0] as f64,
+            self.period.y() * self.count[1] as f64,
+            self.period.z() * self.count[2] as f64,
+        );
+        make_aabb(
+            make_point(b.x.min, b.y.min, b.z.min) - pad,
+            make_point(b.x.max, b.y.max, b.z.max) + pad,
+        )
+    }
+}
+
+pub fn make_repetition(field: Box<dyn DistanceField>, period: Vec3, count: [i32; 3]) -> Repetition {
+    Repetition {
+        field,
+        period,
+        count: count.map(|n| n.max(0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_sdf_object, make_sdf_sphere, make_translate};
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{dot, make_point, unit_vector, Vec3};
+
+    #[test]
+    fn it_finds_the_same_hits_as_an_analytic_sphere() {
+        let center = make_point(0.3, -0.2, 0.1);
+        let material = || Box::new(make_lambertian(make_color(0.5, 0.5, 0.5)));
+        let sphere = make_sphere(center, 1.0, material());
+        let sdf = make_sdf_object(
+            Box::new(make_translate(Box::new(make_sdf_sphere(1.0)), center)),
+            material(),
+        );
+
+        // From outside, and from inside towards the far side
+        for (origin, direction) in [
+            (make_point(0.0, 0.0, 5.0), Vec3(0.1, 0.05, -1.0)),
+            (center, Vec3(0.4, -0.7, 0.2)),
+        ] {
+            let r = Ray { origin, direction };
+            let mut expected = HitRecord::default();
+            let mut found = HitRecord::default();
+            assert!(sphere.hit(&r, interval::new(0.001, f64::INFINITY), &mut expected));
+            assert!(sdf.hit(&r, interval::new(0.001, f64::INFINITY), &mut found));
+            assert!((expected.t - found.t).abs() < 1e-4);
+            assert!(expected.front_face == found.front_face);
+            assert!(dot(unit_vector(expected.normal), found.normal) > 0.9999);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::background::Background;
+use crate::color::{make_color, Color};
+use crate::onb::{make_onb, Onb};
+use crate::rtweekend::{degrees_to_radians, PI};
+use crate::spectrum::xyz_to_rgb;
+use crate::vec3::{dot, sample_unit_sphere, unit_vector, Vec3};
+
+// Angular radius of the sun as seen from the ground, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+
+// Sky luminance comes out of the model in kcd/m^2; this brings a clear
+// midday zenith to roughly 1 so it sits next to the other backgrounds.
+const SKY_SCALE: f64 = 0.1;
+
+// Radiance of the sun disk before atmospheric extinction, in the same units
+// as the scaled sky (about ten times the sky's irradiance at noon).
+const SUN_RADIANCE: f64 = 1.5e5;
+
+// Preetham, Shirley and Smits' analytic daylight model: a Perez sky luminance
+// distribution fitted against turbidity and sun position, with the sun itself
+// as a small disk that is sampled directly.
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    sun_frame: Onb,
+    cos_sun_radius: f64,
+    sun_radiance: Color,
+    zenith: (f64, f64, f64),
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yy: [f64; 5],
+}
+
+impl Background for PreethamSky {
+    fn value(&self, direction: Vec3) -> Color {
+        let d = unit_vector(direction);
+        let mut color = self.sky(d);
+        if self.sun_above_horizon() && dot(d, self.sun_direction) >= self.cos_sun_radius {
+            color += self.sun_radiance;
+        }
+        color
+    }
+
+    // Half of the samples go to the sun disk while it is up, the rest cover
+    // the whole sphere
+    fn sample(&self, u: (f64, f64)) -> Option<Vec3> {
+        if !self.sun_above_horizon() {
+            return Some(sample_unit_sphere(u));
+        }
+        if u.0 < 0.5 {
+            let u_cone = (u.0 * 2.0, u.1);
+            let cos_theta = 1.0 - u_cone.0 * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u_cone.1;
+            Some(self.sun_frame.local(Vec3(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            )))
+        } else {
+            Some(sample_unit_sphere(((u.0 - 0.5) * 2.0, u.1)))
+        }
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let sphere_pdf = 1.0 / (4.0 * PI);
+        if !self.sun_above_horizon() {
+            return sphere_pdf;
+        }
+        let mut pdf = 0.5 * sphere_pdf;
+        if dot(unit_vector(direction), self.sun_direction) >= self.cos_sun_radius {
+            pdf += 0.5 / (2.0 * PI * (1.0 - self.cos_sun_radius));
+        }
+        pdf
+    }
+}
+
+impl PreethamSky {
+    fn sun_above_horizon(&self) -> bool {
+        self.sun_direction.y() > 0.0
+    }
+
+    fn sky(&self, d: Vec3) -> Color {
+        // The model is only defined above the horizon; continue the horizon below
+        let cos_theta = d.y().max(0.001);
+        let cos_gamma = dot(d, self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let theta_s = self.sun_direction.y().clamp(-1.0, 1.0).acos();
+
+        let relative = |coefficients: &[f64; 5]| {
+            perez(coefficients, cos_theta, gamma, cos_gamma)
+                / perez(coefficients, 1.0, theta_s, theta_s.cos())
+        };
+        let luminance = self.zenith.0 * relative(&self.perez_y);
+        let x = self.zenith.1 * relative(&self.perez_x);
+        let y = self.zenith.2 * relative(&self.perez_yy);
+        SKY_SCALE * xyy_to_rgb(x, y, luminance)
+    }
+}
+
+// Perez et al. all-weather luminance distribution
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64, cos_gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return make_color(0.0, 0.0, 0.0);
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    let rgb = xyz_to_rgb(cx, luminance, cz);
+    make_color(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+// Transmittance of the atmosphere along the sun's path for red, green and
+// blue, from Rayleigh and aerosol (Angstrom) extinction (Preetham appendix)
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+    let theta_degrees = theta_s.to_degrees().min(93.0);
+    let optical_mass =
+        1.0 / (theta_s.cos().max(0.0) + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda_um: f64| {
+        let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * optical_mass).exp();
+        let aerosol = (-beta * lambda_um.powf(-1.3) * optical_mass).exp();
+        rayleigh * aerosol
+    };
+    make_color(
+        transmittance(0.68),
+        transmittance(0.55),
+        transmittance(0.44),
+    )
+}
+
+// Sun elevation is measured up from the horizon and azimuth around +y
+// starting at +x, both in degrees. Turbidity runs from about 2 (very clear)
+// to 10 (hazy).
+pub fn make_preetham_sky(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> PreethamSky {
+    let elevation = degrees_to_radians(sun_elevation);
+    let azimuth = degrees_to_radians(sun_azimuth);
+    let sun_direction = Vec3(
+        elevation.cos() * azimuth.cos(),
+        elevation.sin(),
+        elevation.cos() * azimuth.sin(),
+    );
+    let t = turbidity;
+    let theta_s = PI / 2.0 - elevation;
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+    let chromaticity = |m: [[f64; 4]; 3]| {
+        let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let turbidities = [t * t, t, 1.0];
+        let mut sum = 0.0;
+        for (row, weight) in m.iter().zip(turbidities) {
+            for (value, theta_power) in row.iter().zip(thetas) {
+                sum += weight * value * theta_power;
+            }
+        }
+        sum
+    };
+    let zenith_x = chromaticity([
+        [0.00166, -0.00375, 0.00209, 0.0],
+        [-0.02903, 0.06377, -0.03202, 0.00394],
+        [0.11693, -0.21196, 0.06052, 0.25886],
+    ]);
+    let zenith_y = chromaticity([
+        [0.00275, -0.00610, 0.00317, 0.0],
+        [-0.04214, 0.08970, -0.04153, 0.00516],
+        [0.15346, -0.26756, 0.06670, 0.26688],
+    ]);
+
+    PreethamSky {
+        sun_direction,
+        sun_frame: make_onb(sun_direction),
+        cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+        sun_radiance: SUN_RADIANCE * sun_transmittance(theta_s, t),
+        zenith: (zenith_luminance, zenith_x, zenith_y),
+        perez_y: [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ],
+        perez_x: [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ],
+        perez_yy: [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::luminance;
+
+    #[test]
+    fn it_matches_the_zenith_luminance_of_the_model() {
+        // Preetham's zenith formula gives 5.139 kcd/m^2 for turbidity 3 with
+        // the sun 30 degrees up, and the Perez distribution is normalized to
+        // it straight up
+        let sky = make_preetham_sky(30.0, 0.0, 3.0);
+        let zenith = luminance(sky.value(Vec3(0.0, 1.0, 0.0)));
+        assert!((zenith - SKY_SCALE * 5.139).abs() < 1e-3);
+        // The sky glows around the sun and is darker opposite it
+        let towards_sun = sky.value(Vec3(1.0, 0.2, 0.0));
+        let away = sky.value(Vec3(-1.0, 0.2, 0.0));
+        assert!(luminance(towards_sun) > 2.0 * luminance(away));
+        // Looking right at the sun adds its disk on top
+        assert!(luminance(sky.value(sky.sun_direction)) > 1e3 * zenith);
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::OnceLock;
+
+use crate::color::{make_color, Color};
+
+// Spectral rendering traces every path at a single wavelength (in nm).
+// RGB colors in the scene are turned into spectra with Smits' method, and
+// the radiance found at each wavelength is turned back into RGB through the
+// CIE color matching functions.
+
+// Shortest and longest wavelengths that are sampled
+const MIN_WAVELENGTH: f64 = 360.0;
+const MAX_WAVELENGTH: f64 = 830.0;
+
+// Picks a wavelength with density roughly following the eye's sensitivity
+// (pbrt-v4's visible wavelength distribution). Returns it with its density.
+pub fn sample_wavelength(u: f64) -> (f64, f64) {
+    let wavelength = 538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh();
+    let wavelength = wavelength.clamp(MIN_WAVELENGTH, MAX_WAVELENGTH);
+    let cosh = (0.0072 * (wavelength - 538.0)).cosh();
+    (wavelength, 0.0039398042 / (cosh * cosh))
+}
+
+// Smits' spectra for white, the secondary and the primary colors, in ten
+// bins of 34 nm from 380 to 720 nm
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value at `wavelength` of a smooth spectrum with the given RGB color
+// (Smits 1999). The spectrum is built from white plus one secondary and
+// one primary color, so that it stays as flat as the color allows.
+pub fn rgb_to_spectrum(c: Color, wavelength: f64) -> f64 {
+    let bin = (((wavelength - 380.0) / 34.0).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (c.x(), c.y(), c.z());
+    if r <= g && r <= b {
+        let value = r * SMITS_WHITE[bin];
+        if g <= b {
+            value + (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            value + (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        let value = g * SMITS_WHITE[bin];
+        if r <= b {
+            value + (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            value + (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        let value = b * SMITS_WHITE[bin];
+        if r <= g {
+            value + (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            value + (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+// An RGB quantity seen at a single wavelength, as a gray color so that it
+// can go through the same arithmetic as RGB. Colors pass unchanged when
+// no wavelength is given.
+pub fn at_wavelength(c: Color, wavelength: Option<f64>) -> Color {
+    match wavelength {
+        Some(wavelength) => {
+            let value = rgb_to_spectrum(c, wavelength);
+            make_color(value, value, value)
+        }
+        None => c,
+    }
+}
+
+// CIE 1931 color matching functions, from the piecewise Gaussian fit of
+// Wyman, Sloan and Shirley (2013)
+pub fn color_matching(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if wavelength < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (wavelength - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    (
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// CIE XYZ to linear sRGB primaries. Single wavelengths lie outside the sRGB
+// gamut, so components can come out negative.
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    make_color(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// RGB of unit radiance at a single wavelength, scaled so that a constant
+// spectrum integrates to white
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let mut sum = make_color(0.0, 0.0, 0.0);
+        let mut wavelength = MIN_WAVELENGTH + 0.5;
+        while wavelength < MAX_WAVELENGTH {
+            let (x, y, z) = color_matching(wavelength);
+            sum += xyz_to_rgb(x, y, z);
+            wavelength += 1.0;
+        }
+        sum
+    });
+    let (x, y, z) = color_matching(wavelength);
+    xyz_to_rgb(x, y, z) * make_color(1.0 / white.x(), 1.0 / white.y(), 1.0 / white.z())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rgb_to_spectrum, sample_wavelength, wavelength_to_rgb};
+    use crate::color::make_color;
+
+    #[test]
+    fn it_renders_gray_spectra_as_gray() {
+        let gray = make_color(0.5, 0.5, 0.5);
+        let n = 10000;
+        let mut sum = make_color(0.0, 0.0, 0.0);
+        let mut i = 0;
+        while i < n {
+            let (wavelength, pdf) = sample_wavelength((i as f64 + 0.5) / n as f64);
+            sum += rgb_to_spectrum(gray, wavelength) * wavelength_to_rgb(wavelength) / pdf;
+            i += 1;
+        }
+        let average = sum / n as f64;
+        assert!((average.x() - 0.5).abs() < 0.01);
+        assert!((average.y() - 0.5).abs() < 0.01);
+        assert!((average.z() - 0.5).abs() < 0.01);
+    }
+}
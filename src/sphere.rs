@@ -1,7 +1,10 @@
+use crate::aabb::{make_aabb, Aabb};
 use crate::hit_record::HitRecord;
-use crate::hittable::Hittable;
+use crate::hittable::{Hittable, Span};
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::ray::Ray;
+use crate::rtweekend::PI;
 use crate::vec3::{self, dot};
 
 pub struct Sphere {
@@ -11,7 +14,7 @@ pub struct Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let oc = r.origin() - self.center;
         let a = r.direction().length_squared();
         let half_b = dot(oc, r.direction());
@@ -32,16 +35,55 @@ impl Hittable for Sphere {
             }
         }
 
-        rec.t = root;
-        rec.p = r.at(rec.t);
+        self.fill_record(r, root, rec);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = vec3::Vec3(self.radius, self.radius, self.radius);
+        make_aabb(self.center - extent, self.center + extent)
+    }
+
+    fn intervals(&self, r: &Ray) -> Vec<Span> {
+        let oc = r.origin() - self.center;
+        let a = r.direction().length_squared();
+        let half_b = dot(oc, r.direction());
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return Vec::new();
+        }
+        let sqrtd = discriminant.sqrt();
+        let mut enter = HitRecord::default();
+        let mut exit = HitRecord::default();
+        self.fill_record(r, (-half_b - sqrtd) / a, &mut enter);
+        self.fill_record(r, (-half_b + sqrtd) / a, &mut exit);
+        vec![Span { enter, exit }]
+    }
+}
+
+impl Sphere {
+    fn fill_record(&self, r: &Ray, t: f64, rec: &mut HitRecord) {
+        rec.t = t;
+        rec.p = r.at(t);
         let outward_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(r, outward_normal);
+        (rec.u, rec.v) = get_sphere_uv(outward_normal);
         rec.mat = self.mat.clone();
-
-        true
+        rec.clear_extras();
+        // u runs around the y axis
+        rec.tangent = vec3::cross(vec3::Vec3(0.0, 1.0, 0.0), outward_normal);
     }
 }
 
+// u is the angle around the y axis starting from x = -1, v the angle from
+// y = -1 up to y = +1, both mapped to [0, 1]
+fn get_sphere_uv(p: vec3::Point3) -> (f64, f64) {
+    let theta = (-p.y()).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
 pub fn make_sphere(center: vec3::Point3, radius: f64, mat: Box<dyn Material>) -> Sphere {
     Sphere {
         center,
@@ -0,0 +1,230 @@
+use std::rc::Rc;
+
+use crate::color::{make_color, Color};
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval;
+use crate::material::{make_dielectric, Dielectric, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{dot, sample_unit_sphere, unit_vector};
+
+// Walks longer than this are taken to be absorbed
+const MAX_STEPS: i32 = 256;
+
+// Translucent material such as wax, marble or skin. Light refracts in
+// through a smooth dielectric surface, then bounces around inside the
+// shape, scattering isotropically, until it finds its way out through the
+// surface again or is absorbed. The whole random walk happens within one
+// call to scatter(), which returns the ray leaving the shape.
+//
+// The walk traces against its own copy of the shape, so the shape must be
+// closed. Coefficients are per unit of scene length, one per color channel.
+#[derive(Clone)]
+pub struct Subsurface {
+    surface: Dielectric,
+    boundary: Rc<dyn Hittable>,
+    sigma_s: Color,
+    sigma_t: Color,
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // Entering, or being reflected off the surface
+        let mut ray = r_in;
+        let mut weight = make_color(1.0, 1.0, 1.0);
+        if !self.cross_surface(&mut ray, rec, &mut weight, sampler) {
+            *scattered = ray;
+            *attenuation = weight;
+            return true;
+        }
+
+        // Spectral MIS over the whole walk: every free path is sampled with
+        // one channel's coefficient, and the walk is weighted by the average
+        // of the densities it would have had with each channel. Both are
+        // kept relative to the sampled channel's density to avoid underflow.
+        let channel = ((sampler.get_1d() * 3.0) as i32).min(2);
+        let mut pdfs = make_color(1.0, 1.0, 1.0);
+        let mut step = 0;
+        while step < MAX_STEPS {
+            step += 1;
+            let distance = -(1.0 - sampler.get_1d()).ln() / self.sigma_t[channel];
+
+            let mut exit = HitRecord::default();
+            if self
+                .boundary
+                .hit(&ray, interval::new(0.001, distance), &mut exit)
+            {
+                let transmittance = self.transmittance(exit.t);
+                if transmittance[channel] == 0.0 {
+                    return false;
+                }
+                weight *= transmittance / transmittance[channel];
+                pdfs *= transmittance / transmittance[channel];
+                exit.wavelength = rec.wavelength;
+                exit.transport = rec.transport;
+                if !self.cross_surface(&mut ray, &exit, &mut weight, sampler) {
+                    *scattered = ray;
+                    *attenuation = weight / average(pdfs);
+                    return true;
+                }
+                continue;
+            }
+
+            let transmittance = self.transmittance(distance);
+            let pdf = self.sigma_t * transmittance;
+            if pdf[channel] == 0.0 {
+                return false;
+            }
+            weight *= self.sigma_s * transmittance / pdf[channel];
+            pdfs *= pdf / pdf[channel];
+            ray = Ray {
+                origin: ray.at(distance),
+                direction: sample_unit_sphere(sampler.get_2d()),
+            };
+        }
+        false
+    }
+}
+
+impl Subsurface {
+    // Reflects or refracts the ray at a point on the surface, returning
+    // whether it ends up going into the shape
+    fn cross_surface(
+        &self,
+        ray: &mut Ray,
+        rec: &HitRecord,
+        weight: &mut Color,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let mut attenuation = make_color(1.0, 1.0, 1.0);
+        let mut scattered = *ray;
+        self.surface
+            .scatter(*ray, rec, &mut attenuation, &mut scattered, sampler);
+        *weight *= attenuation;
+        *ray = Ray {
+            origin: scattered.origin(),
+            direction: unit_vector(scattered.direction()),
+        };
+        let outward = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        dot(ray.direction(), outward) < 0.0
+    }
+
+    fn transmittance(&self, distance: f64) -> Color {
+        make_color(
+            (-self.sigma_t.x() * distance).exp(),
+            (-self.sigma_t.y() * distance).exp(),
+            (-self.sigma_t.z() * distance).exp(),
+        )
+    }
+}
+
+fn average(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// Subsurface material for the closed shape `boundary` with a surface of
+// index ir over a medium with scattering and absorption coefficients
+// sigma_s and sigma_a
+pub fn make_subsurface(
+    boundary: Rc<dyn Hittable>,
+    ir: f64,
+    sigma_s: Color,
+    sigma_a: Color,
+) -> Subsurface {
+    Subsurface {
+        surface: make_dielectric(ir),
+        boundary,
+        sigma_s,
+        sigma_t: sigma_s + sigma_a,
+    }
+}
+
+// Subsurface material given its single scattering albedo (the fraction of
+// light scattered rather than absorbed at each event) and mean free path
+// (the average distance between events), both per color channel
+pub fn make_subsurface_from_albedo(
+    boundary: Rc<dyn Hittable>,
+    ir: f64,
+    albedo: Color,
+    mean_free_path: Color,
+) -> Subsurface {
+    let sigma_t = make_color(
+        1.0 / mean_free_path.x(),
+        1.0 / mean_free_path.y(),
+        1.0 / mean_free_path.z(),
+    );
+    make_subsurface(
+        boundary,
+        ir,
+        albedo * sigma_t,
+        (make_color(1.0, 1.0, 1.0) - albedo) * sigma_t,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::make_subsurface;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::integrator::scatter;
+    use crate::interval;
+    use crate::material::make_dielectric;
+    use crate::ray::Ray;
+    use crate::sampler::make_independent_sampler;
+    use crate::sphere::make_sphere;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_conserves_energy_without_absorption() {
+        // Index matched, so all light gets in and the chromatic walks are
+        // the only thing that can gain or lose energy
+        let center = make_point(0.0, 0.0, 0.0);
+        let boundary = Rc::new(make_sphere(center, 1.0, Box::new(make_dielectric(1.0))));
+        let material = make_subsurface(
+            boundary,
+            1.0,
+            make_color(0.5, 2.0, 5.0),
+            make_color(0.0, 0.0, 0.0),
+        );
+        let sphere = make_sphere(center, 1.0, Box::new(material));
+
+        let r = Ray {
+            origin: make_point(0.0, 0.0, 3.0),
+            direction: Vec3(0.0, 0.0, -1.0),
+        };
+        let mut rec = HitRecord::default();
+        assert!(sphere.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+
+        // Walks cut off at MAX_STEPS count as absorbed, which is rare enough
+        // not to matter here
+        let mut sampler = make_independent_sampler();
+        let n = 20000;
+        let mut sum = make_color(0.0, 0.0, 0.0);
+        let mut i = 0;
+        while i < n {
+            if let Some((_, attenuation)) = scatter(&r, &rec, &mut sampler) {
+                sum += attenuation;
+            }
+            i += 1;
+        }
+        let average = sum / n as f64;
+        assert!((average.x() - 1.0).abs() < 0.03);
+        assert!((average.y() - 1.0).abs() < 0.03);
+        assert!((average.z() - 1.0).abs() < 0.03);
+    }
+}
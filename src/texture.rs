@@ -0,0 +1,123 @@
+use crate::color::Color;
+use crate::vec3::Point3;
+
+pub trait Texture: TextureClone {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
+
+pub trait TextureClone {
+    fn clone_box(&self) -> Box<dyn Texture>;
+}
+
+impl<T> TextureClone for T
+where
+    T: 'static + Texture + Clone,
+{
+    fn clone_box(&self) -> Box<dyn Texture> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Texture> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[derive(Clone)]
+pub struct SolidColor(Color);
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        self.0
+    }
+}
+
+pub fn make_solid_color(color: Color) -> SolidColor {
+    SolidColor(color)
+}
+
+// Alternates between two textures on a 3D grid of cells scale units wide
+#[derive(Clone)]
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+pub fn make_checker_texture(
+    scale: f64,
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+) -> CheckerTexture {
+    CheckerTexture {
+        inv_scale: 1.0 / scale,
+        even,
+        odd,
+    }
+}
+
+// Alternates between two textures on a grid of cells scale units wide in u
+// and v, for surfaces such as planes whose u and v are lengths
+#[derive(Clone)]
+pub struct UvCheckerTexture {
+    inv_scale: f64,
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let x = (self.inv_scale * u).floor() as i64;
+        let y = (self.inv_scale * v).floor() as i64;
+        if (x + y) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+pub fn make_uv_checker_texture(
+    scale: f64,
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+) -> UvCheckerTexture {
+    UvCheckerTexture {
+        inv_scale: 1.0 / scale,
+        even,
+        odd,
+    }
+}
+
+// Blends linearly from one color at v = 0 to another at v = 1, which runs
+// from the bottom to the top of a sphere
+#[derive(Clone)]
+pub struct GradientTexture {
+    bottom: Color,
+    top: Color,
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, _u: f64, v: f64, _p: Point3) -> Color {
+        let v = v.clamp(0.0, 1.0);
+        (1.0 - v) * self.bottom + v * self.top
+    }
+}
+
+pub fn make_gradient_texture(bottom: Color, top: Color) -> GradientTexture {
+    GradientTexture { bottom, top }
+}
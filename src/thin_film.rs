@@ -0,0 +1,132 @@
+use crate::color::{make_color, Color};
+use crate::hit_record::HitRecord;
+use crate::microfacet::{real, Complex};
+use crate::rtweekend::PI;
+use crate::spectrum::rgb_to_spectrum;
+use crate::texture::Texture;
+
+// Wavelengths (nm) standing in for red, green and blue in RGB rendering
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+// A thin transparent layer on a surface, such as soap or an oxide. Light
+// reflected off the top and the bottom of the layer interferes, which makes
+// the reflectance depend on wavelength, film thickness and angle.
+#[derive(Clone)]
+pub struct ThinFilm {
+    // Thickness in nm, read from the texture's red channel
+    pub thickness: Box<dyn Texture>,
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    // Reflectance for light arriving from a medium with index `outside` at
+    // cos_theta to the normal, over a substrate with complex index
+    // substrate(wavelength). Gray when the hit carries a wavelength.
+    pub fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        outside: f64,
+        substrate: &dyn Fn(f64, usize) -> Complex,
+    ) -> Color {
+        let thickness = self.thickness.value(rec.u, rec.v, rec.p).x().max(0.0);
+        let film = |wavelength: f64, channel: usize| {
+            airy_reflectance(
+                cos_theta,
+                outside,
+                self.ior,
+                substrate(wavelength, channel),
+                thickness,
+                wavelength,
+            )
+        };
+        match rec.wavelength {
+            Some(wavelength) => {
+                let r = film(wavelength, 0);
+                make_color(r, r, r)
+            }
+            None => make_color(
+                film(RGB_WAVELENGTHS[0], 0),
+                film(RGB_WAVELENGTHS[1], 1),
+                film(RGB_WAVELENGTHS[2], 2),
+            ),
+        }
+    }
+}
+
+pub fn make_thin_film(thickness: Box<dyn Texture>, ior: f64) -> ThinFilm {
+    ThinFilm { thickness, ior }
+}
+
+// Index of refraction of a metal whose reflectance at normal incidence is
+// `color`, with the same color at grazing angles (Gulbrandsen 2014). The
+// channel picks the component in RGB rendering; a wavelength in the hit
+// record picks the color's spectrum at that wavelength instead.
+pub fn metal_ior(color: Color, rec: &HitRecord, channel: usize) -> Complex {
+    let reflectance = match rec.wavelength {
+        Some(wavelength) => rgb_to_spectrum(color, wavelength),
+        None => color[channel as i32],
+    };
+    let r = reflectance.clamp(0.0, 0.99);
+    let n_min = (1.0 - r) / (1.0 + r);
+    let n_max = (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+    let n = r * n_min + (1.0 - r) * n_max;
+    let k2 = ((n + 1.0) * (n + 1.0) * r - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+    Complex {
+        re: n,
+        im: k2.max(0.0).sqrt(),
+    }
+}
+
+// Fresnel reflectance of a film of index n2 and thickness d (nm) between
+// media n1 and n3, averaged over both polarizations. Summing the waves
+// bouncing between the film's two faces gives Airy's formula
+// r = (r12 + r23 e^(i phase)) / (1 + r12 r23 e^(i phase)).
+fn airy_reflectance(cos_theta: f64, n1: f64, n2: f64, n3: Complex, d: f64, wavelength: f64) -> f64 {
+    let n1 = real(n1);
+    let n2 = real(n2);
+    let cos1 = real(cos_theta.clamp(0.0, 1.0));
+    let sin2_1 = real(1.0 - cos_theta * cos_theta);
+    // Snell's law, complex past the critical angle or in metals
+    let cos2 = (real(1.0) - (n1 / n2) * (n1 / n2) * sin2_1).sqrt();
+    let cos3 = (real(1.0) - (n1 / n3) * (n1 / n3) * sin2_1).sqrt();
+
+    let phase = real(4.0 * PI * d / wavelength) * n2 * cos2;
+    let shift = (Complex { re: 0.0, im: 1.0 } * phase).exp();
+    let airy = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * shift) / (real(1.0) + r12 * r23 * shift);
+        r.norm()
+    };
+
+    let s = airy(
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3),
+    );
+    let p = airy(
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+        (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3),
+    );
+    (0.5 * (s + p)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::airy_reflectance;
+    use crate::microfacet::real;
+
+    #[test]
+    fn it_matches_plain_fresnel_without_a_film() {
+        // Air to glass at normal incidence: ((1.5 - 1) / (1.5 + 1))^2
+        let r = airy_reflectance(1.0, 1.0, 1.33, real(1.5), 0.0, 550.0);
+        assert!((r - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_cancels_reflection_with_a_quarter_wave_coating() {
+        // A film of index sqrt(1.5) and a quarter wavelength thick on glass
+        let n2 = 1.5_f64.sqrt();
+        let d = 550.0 / (4.0 * n2);
+        let r = airy_reflectance(1.0, 1.0, n2, real(1.5), d, 550.0);
+        assert!(r < 1e-9);
+    }
+}
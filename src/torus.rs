@@ -0,0 +1,198 @@
+use crate::aabb::{make_aabb, Aabb};
+use crate::material::Material;
+use crate::quadric::{make_shape, solve_quadratic, Crossing, Shape, Surface};
+use crate::ray::Ray;
+use crate::rtweekend::PI;
+use crate::vec3::{make_point, unit_vector, Point3, Vec3};
+
+// Bisection steps per root; the bracket stops shrinking well before this
+const MAX_BISECTIONS: i32 = 200;
+
+// Ring around the z axis: a circle of `minor_radius` swept around a circle
+// of `major_radius`. Rays cross it up to four times, at the roots of a
+// quartic.
+pub struct Torus {
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Surface for Torus {
+    fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+        // Solve in distance along a unit direction so the coefficients stay
+        // well scaled
+        let length = r.direction().length();
+        let o = r.origin();
+        let d = r.direction() / length;
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // (|p|^2 - R^2 - r^2)^2 = 4 R^2 (r^2 - z^2), with |d| = 1
+        let f = o.x() * d.x() + o.y() * d.y() + o.z() * d.z();
+        let e = o.length_squared() - major * major - minor * minor;
+        let coefficients = [
+            e * e + 4.0 * major * major * (o.z() * o.z() - minor * minor),
+            4.0 * f * e + 8.0 * major * major * o.z() * d.z(),
+            2.0 * e + 4.0 * f * f + 4.0 * major * major * d.z() * d.z(),
+            4.0 * f,
+            1.0,
+        ];
+
+        let mut crossings = Vec::new();
+        for s in real_roots(&coefficients) {
+            let p = o + s * d;
+            let ring = (p.x() * p.x() + p.y() * p.y()).sqrt();
+            if ring == 0.0 {
+                continue;
+            }
+            // Out from the nearest point on the center circle
+            let center = make_point(major * p.x() / ring, major * p.y() / ring, 0.0);
+            let tube = p.z().atan2(ring - major);
+            crossings.push(Crossing {
+                t: s / length,
+                normal: unit_vector(p - center),
+                u: turns(p.y().atan2(p.x())),
+                v: turns(tube),
+            });
+        }
+        crossings
+    }
+
+    fn bounds(&self) -> Aabb {
+        let r = self.major_radius + self.minor_radius;
+        let h = self.minor_radius;
+        make_aabb(make_point(-r, -r, -h), make_point(r, r, h))
+    }
+
+    fn closed(&self) -> bool {
+        true
+    }
+}
+
+// Angle as a fraction of a turn in [0, 1)
+fn turns(angle: f64) -> f64 {
+    (if angle < 0.0 { angle + 2.0 * PI } else { angle }) / (2.0 * PI)
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c)
+}
+
+// Real roots of a polynomial in increasing order, given its coefficients
+// from the constant term up. The roots of the derivative split the line
+// into pieces where the polynomial is monotonic, and each piece whose ends
+// differ in sign holds exactly one root, found by bisection.
+pub fn real_roots(coefficients: &[f64]) -> Vec<f64> {
+    let mut c = coefficients.to_vec();
+    while c.last() == Some(&0.0) {
+        c.pop();
+    }
+    match c.len() {
+        0 | 1 => return Vec::new(),
+        2 => return solve_quadratic(0.0, c[1], c[0]),
+        3 => return solve_quadratic(c[2], c[1], c[0]),
+        _ => {}
+    }
+
+    // Every root lies within this of zero
+    let n = c.len() - 1;
+    let bound = 1.0 + c[..n].iter().fold(0.0, |m: f64, a| m.max((a / c[n]).abs()));
+
+    let derivative: Vec<f64> = (1..c.len()).map(|i| i as f64 * c[i]).collect();
+    let mut ends = vec![-bound];
+    ends.extend(
+        real_roots(&derivative)
+            .into_iter()
+            .filter(|x| x.abs() < bound),
+    );
+    ends.push(bound);
+
+    let mut roots = Vec::new();
+    for piece in ends.windows(2) {
+        let (mut lo, mut hi) = (piece[0], piece[1]);
+        let lo_value = evaluate(&c, lo);
+        if lo_value == 0.0 {
+            roots.push(lo);
+            continue;
+        }
+        if (lo_value < 0.0) == (evaluate(&c, hi) < 0.0) {
+            continue;
+        }
+        let mut step = 0;
+        while step < MAX_BISECTIONS {
+            step += 1;
+            let mid = 0.5 * (lo + hi);
+            if mid == lo || mid == hi {
+                break;
+            }
+            if (evaluate(&c, mid) < 0.0) == (lo_value < 0.0) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        roots.push(0.5 * (lo + hi));
+    }
+    roots
+}
+
+// Torus around `center`, its ring turning about `axis`. u goes around the
+// ring and v around the tube, starting from the outside.
+pub fn make_torus(
+    center: Point3,
+    axis: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+    mat: Box<dyn Material>,
+) -> Shape<Torus> {
+    let torus = Torus {
+        major_radius,
+        minor_radius,
+    };
+    make_shape(torus, center, axis, mat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_torus, real_roots};
+    use crate::color::make_color;
+    use crate::hittable::Hittable;
+    use crate::material::make_lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::{make_point, Vec3};
+
+    #[test]
+    fn it_finds_all_four_crossings_of_a_torus() {
+        // (x + 3)(x + 0.5)(x - 1)(x - 2)
+        let roots = real_roots(&[3.0, 2.5, -7.0, 0.5, 1.0]);
+        let expected = [-3.0, -0.5, 1.0, 2.0];
+        assert!(roots.len() == 4);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+
+        // Straight through the hole, standing the ring up along y
+        let torus = make_torus(
+            make_point(0.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+        );
+        let r = Ray {
+            origin: make_point(-5.0, 0.0, 0.0),
+            direction: Vec3(2.0, 0.0, 0.0),
+        };
+        let spans = torus.intervals(&r);
+        assert!(spans.len() == 2);
+        let ts = [
+            spans[0].enter.t,
+            spans[0].exit.t,
+            spans[1].enter.t,
+            spans[1].exit.t,
+        ];
+        for (t, expected) in ts.iter().zip([1.25, 1.75, 3.25, 3.75]) {
+            assert!((t - expected).abs() < 1e-9);
+        }
+        assert!(spans[0].enter.front_face && !spans[0].exit.front_face);
+        assert!((spans[1].exit.normal.x() + 1.0).abs() < 1e-9);
+    }
+}
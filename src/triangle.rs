@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use crate::aabb::{make_aabb, Aabb};
+use crate::hit_record::HitRecord;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::vec3::{cross, dot, make_point, unit_vector, Point3, Vec3};
+
+// What all the triangles of one mesh share
+struct MeshCommon {
+    mesh: TriangleMesh,
+    mat: Box<dyn Material>,
+}
+
+// One triangle of a mesh. The side its corners wind counterclockwise
+// around is the outside. Normals and colors are interpolated from the
+// mesh's corners when it has them, and u and v are the weights of the
+// second and third corners.
+pub struct Triangle {
+    common: Rc<MeshCommon>,
+    face: usize,
+    bbox: Aabb,
+}
+
+pub struct TriangleHit {
+    pub t: f64,
+    // Barycentric weights of the second and third corners
+    pub b1: f64,
+    pub b2: f64,
+    // Unit normal on the side the corners wind counterclockwise around
+    pub geometric_normal: Vec3,
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mesh = &self.common.mesh;
+        let face = mesh.faces[self.face];
+        let Some(hit) = hit_triangle(r, ray_t, face.map(|i| mesh.positions[i])) else {
+            return false;
+        };
+        let weights = [1.0 - hit.b1 - hit.b2, hit.b1, hit.b2];
+
+        rec.t = hit.t;
+        rec.p = r.at(hit.t);
+        rec.front_face = dot(r.direction(), hit.geometric_normal) < 0.0;
+        // The smooth normal is kept on the triangle's outside, then turned
+        // to the side that was hit
+        let mut normal = hit.geometric_normal;
+        if let Some(corners) = mesh.normals.get(self.face) {
+            let smooth =
+                weights[0] * corners[0] + weights[1] * corners[1] + weights[2] * corners[2];
+            if !smooth.near_zero() {
+                normal = unit_vector(smooth);
+                if dot(normal, hit.geometric_normal) < 0.0 {
+                    normal = -normal;
+                }
+            }
+        }
+        rec.normal = if rec.front_face { normal } else { -normal };
+        rec.u = hit.b1;
+        rec.v = hit.b2;
+        rec.clear_extras();
+        rec.tangent = mesh.positions[face[1]] - mesh.positions[face[0]];
+        if !mesh.colors.is_empty() {
+            rec.color = weights[0] * mesh.colors[face[0]]
+                + weights[1] * mesh.colors[face[1]]
+                + weights[2] * mesh.colors[face[2]];
+        }
+        rec.mat = self.common.mat.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+// Möller-Trumbore intersection with the triangle through the three points
+pub fn hit_triangle(r: &Ray, ray_t: Interval, points: [Point3; 3]) -> Option<TriangleHit> {
+    let edge1 = points[1] - points[0];
+    let edge2 = points[2] - points[0];
+    let pvec = cross(r.direction(), edge2);
+    let determinant = dot(edge1, pvec);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let tvec = r.origin() - points[0];
+    let b1 = dot(tvec, pvec) * inverse;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = cross(tvec, edge1);
+    let b2 = dot(r.direction(), qvec) * inverse;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = dot(edge2, qvec) * inverse;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some(TriangleHit {
+        t,
+        b1,
+        b2,
+        geometric_normal: unit_vector(cross(edge1, edge2)),
+    })
+}
+
+// The mesh's triangles as separate objects, ready to go into a hierarchy
+// with everything else. A mesh with vertex colors tints the material's
+// diffuse color with them.
+pub fn make_triangle_mesh(mesh: TriangleMesh, mat: Box<dyn Material>) -> Vec<Box<dyn Hittable>> {
+    let count = mesh.faces.len();
+    let common = Rc::new(MeshCommon { mesh, mat });
+    let mut triangles: Vec<Box<dyn Hittable>> = Vec::with_capacity(count);
+    let mut face = 0;
+    while face < count {
+        let points = common.mesh.faces[face].map(|i| common.mesh.positions[i]);
+        // Padded so that triangles lying in an axis plane have some depth
+        let mut low = points[0];
+        let mut high = points[0];
+        for p in &points[1..] {
+            low = make_point(low.x().min(p.x()), low.y().min(p.y()), low.z().min(p.z()));
+            high = make_point(
+                high.x().max(p.x()),
+                high.y().max(p.y()),
+                high.z().max(p.z()),
+            );
+        }
+        let padding = Vec3(1e-4, 1e-4, 1e-4);
+        triangles.push(Box::new(Triangle {
+            common: common.clone(),
+            face,
+            bbox: make_aabb(low - padding, high + padding),
+        }));
+        face += 1;
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::make_triangle_mesh;
+    use crate::color::make_color;
+    use crate::hit_record::HitRecord;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval;
+    use crate::material::make_lambertian;
+    use crate::mesh::TriangleMesh;
+    use crate::ray::Ray;
+    use crate::vec3::{make_point, unit_vector, Vec3};
+
+    #[test]
+    fn it_interpolates_corner_normals_and_colors() {
+        // A unit square in the z = 0 plane facing +z, its normals leaning
+        // outwards like those of a dome
+        let positions = vec![
+            make_point(0.0, 0.0, 0.0),
+            make_point(1.0, 0.0, 0.0),
+            make_point(1.0, 1.0, 0.0),
+            make_point(0.0, 1.0, 0.0),
+        ];
+        let lean = |p: Vec3| unit_vector(Vec3(p.x() - 0.5, p.y() - 0.5, 1.0));
+        let faces = vec![[0, 1, 2], [0, 2, 3]];
+        let normals = faces
+            .iter()
+            .map(|f: &[usize; 3]| f.map(|i| lean(positions[i])))
+            .collect();
+        let square = HittableList {
+            objects: make_triangle_mesh(
+                TriangleMesh {
+                    colors: vec![
+                        make_color(1.0, 0.0, 0.0),
+                        make_color(0.0, 1.0, 0.0),
+                        make_color(0.0, 0.0, 1.0),
+                        make_color(0.0, 0.0, 1.0),
+                    ],
+                    positions,
+                    faces,
+                    normals,
+                },
+                Box::new(make_lambertian(make_color(0.5, 0.5, 0.5))),
+            ),
+        };
+
+        // Three quarters of the way along the bottom edge, from above
+        let r = Ray {
+            origin: make_point(0.75, 0.0, 2.0),
+            direction: Vec3(0.0, 0.0, -1.0),
+        };
+        let mut rec = HitRecord::default();
+        assert!(square.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-12 && rec.front_face);
+        assert!((rec.color.x() - 0.25).abs() < 1e-12 && (rec.color.y() - 0.75).abs() < 1e-12);
+        let expected =
+            unit_vector(0.25 * lean(Vec3(0.0, 0.0, 0.0)) + 0.75 * lean(Vec3(1.0, 0.0, 0.0)));
+        assert!((rec.normal - expected).length() < 1e-12);
+
+        // From below the square is hit on its back, and the normal turns
+        // to face the ray
+        let r = Ray {
+            origin: make_point(0.25, 0.75, -1.0),
+            direction: Vec3(0.0, 0.0, 1.0),
+        };
+        assert!(square.hit(&r, interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(!rec.front_face && rec.normal.z() < 0.0);
+        assert!((rec.color.z() - 0.75).abs() < 1e-12);
+    }
+}
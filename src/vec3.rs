@@ -3,17 +3,17 @@ use std::ops;
 
 use crate::rtweekend::random_f64;
 use crate::rtweekend::random_f64_in_range;
+use crate::rtweekend::PI;
 
 #[derive(Copy, Clone)]
 pub struct Vec3(pub f64, pub f64, pub f64);
 
 impl fmt::Display for Vec3 {
-    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let x = self.x();
         let y = self.y();
         let z = self.z();
-        print!("<Vec3: {x} {y} {z}>");
-        Ok(())
+        write!(f, "<Vec3: {x} {y} {z}>")
     }
 }
 
@@ -104,6 +104,12 @@ impl ops::Div<f64> for Vec3 {
     }
 }
 
+impl ops::DivAssign<f64> for Vec3 {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs
+    }
+}
+
 pub fn dot(u: Vec3, v: Vec3) -> f64 {
     u[0] * v[0] + u[1] * v[1] + u[2] * v[2]
 }
@@ -159,6 +165,7 @@ pub fn random_in_range(min: f64, max: f64) -> Vec3 {
     )
 }
 
+#[allow(dead_code)]
 pub fn random_in_unit_sphere() -> Vec3 {
     loop {
         let p = random_in_range(-1.0, 1.0);
@@ -168,23 +175,12 @@ pub fn random_in_unit_sphere() -> Vec3 {
     }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3(
-            random_f64_in_range(-1.0, 1.0),
-            random_f64_in_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
-}
-
+#[allow(dead_code)]
 pub fn random_unit() -> Vec3 {
     unit_vector(random_in_unit_sphere())
 }
 
+#[allow(dead_code)]
 pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
     let on_unit_sphere = random_unit();
     if dot(on_unit_sphere, normal) > 0.0 {
@@ -194,6 +190,30 @@ pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
     }
 }
 
+// Maps a 2D sample in [0, 1)^2 uniformly onto the unit sphere
+pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Maps a 2D sample in [0, 1)^2 uniformly onto the unit disk in the xy plane.
+// The concentric mapping keeps strata of the sample square compact on the disk.
+pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+    let ox = 2.0 * u.0 - 1.0;
+    let oy = 2.0 * u.1 - 1.0;
+    if ox == 0.0 && oy == 0.0 {
+        return zero_vector();
+    }
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, PI / 4.0 * (oy / ox))
+    } else {
+        (oy, PI / 2.0 - PI / 4.0 * (ox / oy))
+    };
+    Vec3(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * dot(v, n) * n
 }
@@ -0,0 +1,245 @@
+use std::fs;
+use std::io;
+
+use crate::rtweekend::hash_values;
+use crate::vec3::Point3;
+
+// Densities on a regular grid spanning the unit cube, stored with x varying
+// fastest, then y, then z. Each value sits at the center of its cell.
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub values: Vec<f64>,
+}
+
+impl VoxelGrid {
+    // Trilinearly interpolated density at p in [0, 1]^3
+    pub fn lookup(&self, p: Point3) -> f64 {
+        let (x0, x1, tx) = cell(p.x(), self.nx);
+        let (y0, y1, ty) = cell(p.y(), self.ny);
+        let (z0, z1, tz) = cell(p.z(), self.nz);
+        let value = |x: usize, y: usize, z: usize| self.values[(z * self.ny + y) * self.nx + x];
+        let lerp = |a: f64, b: f64, t: f64| (1.0 - t) * a + t * b;
+        let plane = |z: usize| {
+            lerp(
+                lerp(value(x0, y0, z), value(x1, y0, z), tx),
+                lerp(value(x0, y1, z), value(x1, y1, z), tx),
+                ty,
+            )
+        };
+        lerp(plane(z0), plane(z1), tz)
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.values.iter().fold(0.0, |a, b| a.max(*b))
+    }
+}
+
+// The two cells to interpolate between along an axis of n cells, and the
+// weight of the second
+fn cell(x: f64, n: usize) -> (usize, usize, f64) {
+    let x = (x * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+    let i = (x as usize).min(n - 1);
+    (i, (i + 1).min(n - 1), x - i as f64)
+}
+
+// Loads a single channel grid in Mitsuba's .vol format: "VOL", a version
+// byte of 3, then little-endian i32 encoding (1 for f32, 3 for u8),
+// resolution in x, y and z, channel count, six f32 for a bounding box that
+// is ignored here, and the data. Only the first channel is kept.
+pub fn load_voxel_grid(path: &str) -> io::Result<VoxelGrid> {
+    let bytes = fs::read(path)?;
+    parse_vol(&bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn parse_vol(bytes: &[u8]) -> io::Result<VoxelGrid> {
+    const HEADER: usize = 48;
+    if bytes.len() < HEADER || !bytes.starts_with(b"VOL") || bytes[3] != 3 {
+        return Err(invalid_data("not a version 3 .vol file"));
+    }
+    let int = |offset: usize| {
+        i32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    };
+    let (encoding, nx, ny, nz, channels) = (int(4), int(8), int(12), int(16), int(20));
+    if nx <= 0 || ny <= 0 || nz <= 0 || channels <= 0 {
+        return Err(invalid_data("bad grid resolution"));
+    }
+    let size = match encoding {
+        1 => 4,
+        3 => 1,
+        _ => return Err(invalid_data("only f32 and u8 grids are supported")),
+    };
+    let (nx, ny, nz, channels) = (nx as usize, ny as usize, nz as usize, channels as usize);
+    let count = nx
+        .checked_mul(ny)
+        .and_then(|n| n.checked_mul(nz))
+        .ok_or_else(|| invalid_data("bad grid resolution"))?;
+    let stride = channels * size;
+    if (bytes.len() - HEADER) / stride < count {
+        return Err(invalid_data("truncated grid data"));
+    }
+
+    let mut values = Vec::with_capacity(count);
+    let mut i = 0;
+    while i < count {
+        let offset = HEADER + i * stride;
+        let value = if size == 4 {
+            f32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ]) as f64
+        } else {
+            bytes[offset] as f64 / 255.0
+        };
+        values.push(value.max(0.0));
+        i += 1;
+    }
+    Ok(VoxelGrid { nx, ny, nz, values })
+}
+
+// A puffy cloud: a ball fading out towards the faces of the cube, with
+// fractal noise of the given base frequency eating into its edges
+pub fn make_noise_grid(resolution: usize, frequency: f64) -> VoxelGrid {
+    let n = resolution.max(2);
+    let mut values = Vec::with_capacity(n * n * n);
+    let mut z = 0;
+    while z < n {
+        let mut y = 0;
+        while y < n {
+            let mut x = 0;
+            while x < n {
+                let p = [
+                    (x as f64 + 0.5) / n as f64,
+                    (y as f64 + 0.5) / n as f64,
+                    (z as f64 + 0.5) / n as f64,
+                ];
+                let d = 2.0
+                    * ((p[0] - 0.5).powi(2) + (p[1] - 0.5).powi(2) + (p[2] - 0.5).powi(2)).sqrt();
+                let noise = fractal_noise([p[0] * frequency, p[1] * frequency, p[2] * frequency]);
+                values.push((1.5 * (1.0 - d) + noise - 0.3).clamp(0.0, 1.0));
+                x += 1;
+            }
+            y += 1;
+        }
+        z += 1;
+    }
+    VoxelGrid {
+        nx: n,
+        ny: n,
+        nz: n,
+        values,
+    }
+}
+
+// Four octaves of value noise, roughly in [-1, 1]
+pub fn fractal_noise(p: [f64; 3]) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut scale = 1.0;
+    let mut octave = 0;
+    while octave < 4 {
+        sum += amplitude * value_noise([p[0] * scale, p[1] * scale, p[2] * scale], octave);
+        amplitude *= 0.5;
+        scale *= 2.0;
+        octave += 1;
+    }
+    sum
+}
+
+// Smoothly interpolated random values in [-1, 1] at integer lattice points
+fn value_noise(p: [f64; 3], seed: u64) -> f64 {
+    let lattice = |i: i64, j: i64, k: i64| {
+        let h = hash_values(&[i as u64, j as u64, k as u64, seed]);
+        2.0 * (h >> 11) as f64 / (1u64 << 53) as f64 - 1.0
+    };
+    let (i, j, k) = (p[0].floor(), p[1].floor(), p[2].floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (tx, ty, tz) = (smooth(p[0] - i), smooth(p[1] - j), smooth(p[2] - k));
+    let (i, j, k) = (i as i64, j as i64, k as i64);
+    let lerp = |a: f64, b: f64, t: f64| (1.0 - t) * a + t * b;
+    let plane = |k: i64| {
+        lerp(
+            lerp(lattice(i, j, k), lattice(i + 1, j, k), tx),
+            lerp(lattice(i, j + 1, k), lattice(i + 1, j + 1, k), tx),
+            ty,
+        )
+    };
+    lerp(plane(k), plane(k + 1), tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::make_point;
+
+    fn header(encoding: i32, n: [i32; 3], channels: i32) -> Vec<u8> {
+        let mut bytes = b"VOL\x03".to_vec();
+        for v in [encoding, n[0], n[1], n[2], channels] {
+            bytes.extend(v.to_le_bytes());
+        }
+        bytes.extend([0u8; 24]);
+        bytes
+    }
+
+    #[test]
+    fn it_reads_f32_and_u8_grids() {
+        // Two channels of which only the first is kept
+        let mut bytes = header(1, [2, 1, 1], 2);
+        for v in [0.25_f32, 9.0, 0.75, 9.0] {
+            bytes.extend(v.to_le_bytes());
+        }
+        let grid = parse_vol(&bytes).unwrap();
+        assert_eq!((grid.nx, grid.ny, grid.nz), (2, 1, 1));
+        assert_eq!(grid.values, vec![0.25, 0.75]);
+
+        let mut bytes = header(3, [1, 2, 2], 1);
+        bytes.extend([0, 51, 255, 102]);
+        let grid = parse_vol(&bytes).unwrap();
+        assert_eq!(grid.values, vec![0.0, 0.2, 1.0, 0.4]);
+    }
+
+    #[test]
+    fn it_rejects_truncated_and_oversized_grids() {
+        let mut bytes = header(1, [2, 2, 2], 1);
+        bytes.extend([0u8; 31]);
+        assert!(parse_vol(&bytes).is_err());
+        bytes.push(0);
+        assert!(parse_vol(&bytes).is_ok());
+        assert!(parse_vol(&header(1, [i32::MAX; 3], 1)).is_err());
+        assert!(parse_vol(&header(3, [65536, 65536, 1], i32::MAX)).is_err());
+        assert!(parse_vol(&header(2, [1, 1, 1], 1)).is_err());
+        assert!(parse_vol(&header(1, [0, 1, 1], 1)).is_err());
+    }
+
+    #[test]
+    fn it_interpolates_between_cell_centers() {
+        let grid = VoxelGrid {
+            nx: 2,
+            ny: 2,
+            nz: 2,
+            values: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0],
+        };
+        // Cell centers give back their own values
+        assert_eq!(grid.lookup(make_point(0.25, 0.25, 0.25)), 0.0);
+        assert_eq!(grid.lookup(make_point(0.75, 0.75, 0.75)), 7.0);
+        assert_eq!(grid.lookup(make_point(0.75, 0.25, 0.75)), 5.0);
+        // Halfway between centers along each axis
+        assert!((grid.lookup(make_point(0.5, 0.25, 0.25)) - 0.5).abs() < 1e-12);
+        assert!((grid.lookup(make_point(0.5, 0.5, 0.5)) - 3.5).abs() < 1e-12);
+        // Past the outer centers the value holds steady
+        assert_eq!(grid.lookup(make_point(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(grid.lookup(make_point(1.0, 1.0, 1.0)), 7.0);
+    }
+}